@@ -0,0 +1,162 @@
+use crate::exchange::exchange::{Exchange, ExchangeError};
+use crate::exchange::fill::Fill;
+use crate::exchange::price_feed::BinanceKline;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+
+/// Trading logic driven by `Backtest::run`.
+pub trait Strategy {
+    /// Called with each candle once it has closed, before the next tick.
+    fn on_candle(&mut self, exchange: &mut Exchange, symbol: &str, kline: &BinanceKline);
+}
+
+/// Where a backtest ended up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BacktestReport {
+    pub bars: usize,
+    pub wallet: HashMap<String, Decimal>,
+    pub fills: Vec<Fill>,
+    /// The fees charged, each converted into the quote asset of its pair and summed per quote.
+    pub fees: HashMap<String, Decimal>,
+    /// The exchange's equity curve, empty unless it had equity tracking enabled. See `metrics`.
+    pub equity_curve: Vec<(i64, Decimal)>,
+}
+
+/**
+Backtest drives an exchange through all of its price feeds in one call. Each tick consumes the
+next bar in time order, from every feed that closes one then, and the strategy sees the candles
+that just closed, in symbol order, and can place or cancel orders before the next tick. Runs
+until every feed is exhausted.
+*/
+pub struct Backtest;
+
+impl Backtest {
+    pub fn run(
+        exchange: &mut Exchange,
+        strategy: &mut impl Strategy,
+    ) -> Result<BacktestReport, ExchangeError> {
+        let mut bars = 0;
+        loop {
+            let before = exchange.bars_processed();
+            exchange.tick()?;
+            let mut advanced: Vec<String> = exchange
+                .bars_processed()
+                .into_iter()
+                .filter(|(symbol, position)| before.get(symbol) != Some(position))
+                .map(|(symbol, _)| symbol)
+                .collect();
+            if advanced.is_empty() {
+                break;
+            }
+            bars += 1;
+            advanced.sort();
+            for symbol in advanced {
+                if let Some(kline) = exchange.latest_kline(&symbol).cloned() {
+                    strategy.on_candle(exchange, &symbol, &kline);
+                }
+            }
+        }
+        let mut fees: HashMap<String, Decimal> = HashMap::new();
+        for fill in exchange.get_fills() {
+            let (_, quote) = exchange.get_asset_pair(&fill.pair)?;
+            *fees.entry(quote.to_string()).or_default() += fill.fee_in_quote();
+        }
+        Ok(BacktestReport {
+            bars,
+            wallet: exchange.get_wallet().clone(),
+            fills: exchange.get_fills().clone(),
+            fees,
+            equity_curve: exchange.equity_curve().to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::exchange::fee::FeeSchedule;
+    use crate::exchange::price_feed::PriceFeed;
+    use crate::test_util::ohlc_kline;
+    use rust_decimal_macros::dec;
+
+    struct BuyAndHold {
+        bought: bool,
+    }
+
+    impl Strategy for BuyAndHold {
+        fn on_candle(&mut self, exchange: &mut Exchange, symbol: &str, kline: &BinanceKline) {
+            if !self.bought {
+                let (_, _, _, _, close) = kline.get_ohlc();
+                exchange
+                    .place_market_buy_order(symbol, close, dec!(1))
+                    .unwrap();
+                self.bought = true;
+            }
+        }
+    }
+
+    fn kline(close: Decimal) -> BinanceKline {
+        ohlc_kline(close, close, close, close)
+    }
+
+    #[test]
+    fn test_buy_and_hold() {
+        let mut price_feed = PriceFeed::new();
+        price_feed.add_price_data(vec![kline(dec!(2)), kline(dec!(3)), kline(dec!(5))]);
+        let mut exchange = Exchange::new();
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .with_fees(FeeSchedule::new(dec!(0), dec!(0.01)))
+            .enable_equity_tracking("USDT")
+            .add_price_feed("BTCUSDT".to_string(), price_feed);
+
+        let report = Backtest::run(&mut exchange, &mut BuyAndHold { bought: false }).unwrap();
+        assert_eq!(report.bars, 3);
+        assert_eq!(report.fills.len(), 1);
+        assert_eq!(report.wallet["USDT"], dec!(8));
+        assert_eq!(report.wallet["BTC"], dec!(0.99));
+        assert_eq!(
+            report.fees,
+            HashMap::from([("USDT".to_string(), dec!(0.02))])
+        );
+        let values: Vec<Decimal> = report.equity_curve.iter().map(|(_, v)| *v).collect();
+        assert_eq!(values, vec![dec!(10), dec!(10.97), dec!(12.95)]);
+    }
+
+    struct BuyEachOnce {
+        bought: Vec<String>,
+    }
+
+    impl Strategy for BuyEachOnce {
+        fn on_candle(&mut self, exchange: &mut Exchange, symbol: &str, kline: &BinanceKline) {
+            if !self.bought.iter().any(|bought| bought == symbol) {
+                let (_, _, _, _, close) = kline.get_ohlc();
+                exchange
+                    .place_market_buy_order(symbol, close, dec!(1))
+                    .unwrap();
+                self.bought.push(symbol.to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn test_fees_are_summed_per_quote() {
+        let mut exchange = Exchange::new();
+        exchange
+            .with_capital(vec![
+                ("USDT".to_string(), dec!(10)),
+                ("BTC".to_string(), dec!(10)),
+            ])
+            .with_fees(FeeSchedule::new(dec!(0), dec!(0.01)));
+        for symbol in ["BTCUSDT", "ETHBTC"] {
+            let mut price_feed = PriceFeed::new();
+            price_feed.add_price_data(vec![kline(dec!(2))]);
+            exchange.add_price_feed(symbol.to_string(), price_feed);
+        }
+
+        let report = Backtest::run(&mut exchange, &mut BuyEachOnce { bought: vec![] }).unwrap();
+        assert_eq!(report.fills.len(), 2);
+        assert_eq!(report.fees["USDT"], dec!(0.02));
+        assert_eq!(report.fees["BTC"], dec!(0.02));
+    }
+}
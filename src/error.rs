@@ -0,0 +1,76 @@
+use crate::exchange::exchange::ExchangeError;
+use crate::exchange::price_feed::PriceFeedError;
+use crate::ExchangesError;
+use std::error::Error;
+use thiserror::Error;
+
+/// The error returned at the crate boundary, wrapping the errors of each part of the crate.
+#[derive(Debug, Error)]
+pub enum TradeSimError {
+    #[error(transparent)]
+    Exchange(#[from] ExchangeError),
+    #[error(transparent)]
+    Exchanges(#[from] ExchangesError),
+    #[error(transparent)]
+    PriceFeed(#[from] PriceFeedError),
+    // Whatever a price provider reports going wrong fetching klines, kept as the source.
+    #[error("Price provider failed: {0}")]
+    PriceProvider(#[source] Box<dyn Error + Send + Sync>),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serialization(#[from] serde_json::Error),
+}
+
+impl From<Box<dyn Error + Send + Sync>> for TradeSimError {
+    fn from(error: Box<dyn Error + Send + Sync>) -> Self {
+        match error.downcast::<PriceFeedError>() {
+            Ok(error) => TradeSimError::PriceFeed(*error),
+            Err(error) => TradeSimError::PriceProvider(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_errors_map_to_variants() {
+        assert!(matches!(
+            TradeSimError::from(ExchangeError::InvalidAmount),
+            TradeSimError::Exchange(ExchangeError::InvalidAmount)
+        ));
+        assert!(matches!(
+            TradeSimError::from(ExchangesError::InvalidExchangeId),
+            TradeSimError::Exchanges(ExchangesError::InvalidExchangeId)
+        ));
+        let malformed = PriceFeedError::MalformedCsvLine {
+            line: 3,
+            reason: "missing close".to_string(),
+        };
+        assert!(matches!(
+            TradeSimError::from(malformed.clone()),
+            TradeSimError::PriceFeed(PriceFeedError::MalformedCsvLine { line: 3, .. })
+        ));
+        let boxed: Box<dyn Error + Send + Sync> = Box::new(malformed);
+        assert!(matches!(
+            TradeSimError::from(boxed),
+            TradeSimError::PriceFeed(_)
+        ));
+        let boxed: Box<dyn Error + Send + Sync> = "connection refused".into();
+        let error = TradeSimError::from(boxed);
+        assert!(matches!(
+            &error,
+            TradeSimError::PriceProvider(source) if source.to_string() == "connection refused"
+        ));
+        assert_eq!(error.source().unwrap().to_string(), "connection refused");
+        let missing = std::io::Error::from(std::io::ErrorKind::NotFound);
+        assert!(matches!(TradeSimError::from(missing), TradeSimError::Io(_)));
+        let invalid = serde_json::from_str::<u32>("x").unwrap_err();
+        assert!(matches!(
+            TradeSimError::from(invalid),
+            TradeSimError::Serialization(_)
+        ));
+    }
+}
@@ -0,0 +1,323 @@
+use crate::exchange::transaction::{Transaction, TransactionKind};
+use rust_decimal::prelude::Decimal;
+use rust_decimal_macros::dec;
+use std::collections::{HashMap, VecDeque};
+
+/**
+CostBasis matches the sales of one asset against the lots it was bought in, reading the trade
+legs of the wallet's transaction history.
+
+Every buy of the asset opens a lot at its cost, every sale consumes lots until its quantity is
+covered: the oldest lots first with FIFO, the newest with LIFO. A sale spanning several lots is
+costed at each lot's price for the part it takes. The fee booked with a trade is netted into it
+like `PnlTracker` does, so a buy's lot holds the quantity received net of its fee at the full
+cost, and a sale's proceeds are net of the fee taken from them. Only fills on pairs the asset is
+the base of trade it, paying with it as the quote of another pair opens or consumes no lot.
+
+Transactions without a price, such as starting capital and deposits, have no known cost and
+open no lot. Sold quantity with no lot behind it comes out of those holdings and is left out of
+the gain, and whatever is sold beyond them opens a short lot at the sale's proceeds, which
+later buys cover before opening long lots again. Gains are in the quote asset the asset traded
+against.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LotMatching {
+    Fifo,
+    Lifo,
+}
+
+/// The gain on one sale, or on the buy covering a short, over the quantity matched against lots.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RealizedGain {
+    pub ts: i64,
+    pub qty: Decimal,
+    pub proceeds: Decimal,
+    pub cost: Decimal,
+    pub gain: Decimal,
+}
+
+#[derive(Debug, Clone)]
+struct Lot {
+    qty: Decimal,
+    unit_cost: Decimal,
+}
+
+/// Open lots of one asset, each the quantity left of an entry and its price per unit.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Lots {
+    lots: VecDeque<Lot>,
+}
+
+impl Lots {
+    pub(crate) fn open(&mut self, qty: Decimal, unit_cost: Decimal) {
+        self.lots.push_back(Lot { qty, unit_cost });
+    }
+    /// Takes up to `qty` out of the lots in `matching` order, returning the quantity matched
+    /// and what it cost.
+    pub(crate) fn take(&mut self, qty: Decimal, matching: LotMatching) -> (Decimal, Decimal) {
+        let mut remaining = qty;
+        let mut matched = dec!(0);
+        let mut cost = dec!(0);
+        while remaining > dec!(0) {
+            let lot = match matching {
+                LotMatching::Fifo => self.lots.front_mut(),
+                LotMatching::Lifo => self.lots.back_mut(),
+            };
+            let Some(lot) = lot else {
+                break;
+            };
+            let taken = remaining.min(lot.qty);
+            matched += taken;
+            cost += taken * lot.unit_cost;
+            remaining -= taken;
+            lot.qty -= taken;
+            if lot.qty <= dec!(0) {
+                match matching {
+                    LotMatching::Fifo => self.lots.pop_front(),
+                    LotMatching::Lifo => self.lots.pop_back(),
+                };
+            }
+        }
+        (matched, cost)
+    }
+    pub(crate) fn qty(&self) -> Decimal {
+        self.lots.iter().map(|lot| lot.qty).sum()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CostBasis {
+    matching: LotMatching,
+    longs: Lots,
+    // Lots sold short, priced at the proceeds per unit
+    shorts: Lots,
+    // Held quantity with no known cost
+    uncosted: Decimal,
+    gains: Vec<RealizedGain>,
+}
+
+impl CostBasis {
+    pub fn new(matching: LotMatching) -> Self {
+        CostBasis {
+            matching,
+            longs: Lots::default(),
+            shorts: Lots::default(),
+            uncosted: dec!(0),
+            gains: vec![],
+        }
+    }
+    /// Replays the trade legs of `asset` in `transactions`, in order. Fill legs only count on
+    /// pairs where `asset` is the base, with the fee leg of the same order netted in when it's
+    /// taken from the asset a buy receives or from the quote a sale receives. Legs outside of
+    /// a fill, like deposits, are recorded as they are.
+    pub fn from_transactions(
+        transactions: &[Transaction],
+        asset: &str,
+        matching: LotMatching,
+    ) -> Self {
+        let fees: HashMap<(u64, i64), &Transaction> = transactions
+            .iter()
+            .filter(|tx| tx.is_fee())
+            .filter_map(|tx| Some(((tx.get_order_id()?, *tx.get_ts()), tx)))
+            .collect();
+        let mut cost_basis = CostBasis::new(matching);
+        for tx in transactions {
+            if tx.get_symbol() != asset || tx.get_kind() != TransactionKind::Trade {
+                continue;
+            }
+            let (ts, qty, price) = (*tx.get_ts(), *tx.get_qty(), *tx.get_price());
+            let fee = match tx.get_order_id() {
+                None => dec!(0),
+                Some(_) if tx.get_base() != Some(asset) => continue,
+                Some(order_id) => match fees.get(&(order_id, ts)) {
+                    Some(fee) if (fee.get_symbol() == asset) == (qty > dec!(0)) => -*fee.get_qty(),
+                    _ => dec!(0),
+                },
+            };
+            cost_basis.record(ts, qty, price, fee);
+        }
+        cost_basis
+    }
+    /// Records a trade of `qty` of the asset at `price`, a buy when positive and a sale when
+    /// negative, with the `fee` taken from what it receives: the asset for a buy and the quote
+    /// for a sale. Without a price the quantity has no known cost.
+    pub fn record(&mut self, ts: i64, qty: Decimal, price: Decimal, fee: Decimal) {
+        if price.is_zero() {
+            self.uncosted = (self.uncosted + qty).max(dec!(0));
+        } else if qty > dec!(0) {
+            let received = qty - fee;
+            if received <= dec!(0) {
+                return;
+            }
+            let unit_cost = qty * price / received;
+            let (covered, proceeds) = self.shorts.take(received, self.matching);
+            if covered > dec!(0) {
+                self.realize(ts, covered, proceeds, covered * unit_cost);
+            }
+            if received > covered {
+                self.longs.open(received - covered, unit_cost);
+            }
+        } else if qty < dec!(0) {
+            let sold = qty * dec!(-1);
+            let unit_proceeds = (sold * price - fee) / sold;
+            let (matched, cost) = self.longs.take(sold, self.matching);
+            if matched > dec!(0) {
+                self.realize(ts, matched, matched * unit_proceeds, cost);
+            }
+            let uncosted = (sold - matched).min(self.uncosted);
+            self.uncosted -= uncosted;
+            let short = sold - matched - uncosted;
+            if short > dec!(0) {
+                self.shorts.open(short, unit_proceeds);
+            }
+        }
+    }
+    fn realize(&mut self, ts: i64, qty: Decimal, proceeds: Decimal, cost: Decimal) {
+        self.gains.push(RealizedGain {
+            ts,
+            qty,
+            proceeds,
+            cost,
+            gain: proceeds - cost,
+        });
+    }
+    /// One entry per sale or cover that matched a lot, in order.
+    pub fn realized_gains(&self) -> &[RealizedGain] {
+        &self.gains
+    }
+    pub fn total_gain(&self) -> Decimal {
+        self.gains.iter().map(|gain| gain.gain).sum()
+    }
+    /// The quantity still held in open lots, negative when short.
+    pub fn open_qty(&self) -> Decimal {
+        self.longs.qty() - self.shorts.qty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn history() -> Vec<Transaction> {
+        let btc = |ts: i64, price: Decimal, qty: Decimal| {
+            Transaction::new(ts, "BTC".to_string(), price, qty).with_fill(ts as u64, "BTC")
+        };
+        vec![
+            // Starting capital has no cost basis
+            Transaction::new(0, "BTC".to_string(), dec!(0), dec!(5)),
+            btc(1, dec!(100), dec!(1)),
+            Transaction::new(1, "USDT".to_string(), dec!(100), dec!(-100)).with_fill(1, "BTC"),
+            btc(2, dec!(200), dec!(1)),
+            btc(3, dec!(300), dec!(1)),
+            // Spans two lots either way
+            btc(4, dec!(250), dec!(-2)),
+            btc(5, dec!(400), dec!(-1)),
+        ]
+    }
+
+    #[test]
+    fn test_fifo_and_lifo_realized_gains() {
+        let fifo = CostBasis::from_transactions(&history(), "BTC", LotMatching::Fifo);
+        assert_eq!(
+            fifo.realized_gains(),
+            &[
+                RealizedGain {
+                    ts: 4,
+                    qty: dec!(2),
+                    proceeds: dec!(500),
+                    cost: dec!(300),
+                    gain: dec!(200),
+                },
+                RealizedGain {
+                    ts: 5,
+                    qty: dec!(1),
+                    proceeds: dec!(400),
+                    cost: dec!(300),
+                    gain: dec!(100),
+                },
+            ]
+        );
+
+        let lifo = CostBasis::from_transactions(&history(), "BTC", LotMatching::Lifo);
+        let gains: Vec<Decimal> = lifo.realized_gains().iter().map(|g| g.gain).collect();
+        assert_eq!(gains, vec![dec!(0), dec!(300)]);
+
+        // Same lots in the end, only the timing of the gains differs
+        assert_eq!(fifo.total_gain(), lifo.total_gain());
+        assert_eq!(fifo.open_qty(), dec!(0));
+    }
+
+    #[test]
+    fn test_fees_are_netted_into_lots_and_proceeds() {
+        let txs = vec![
+            Transaction::new(1, "BTC".to_string(), dec!(100), dec!(1)).with_fill(1, "BTC"),
+            Transaction::new(1, "USDT".to_string(), dec!(100), dec!(-100)).with_fill(1, "BTC"),
+            // Another fill booked in between, paid in BTC as its quote
+            Transaction::new(1, "ETH".to_string(), dec!(0.05), dec!(1)).with_fill(2, "ETH"),
+            Transaction::new(1, "BTC".to_string(), dec!(0.05), dec!(-0.05)).with_fill(2, "ETH"),
+            Transaction::fee(1, "BTC".to_string(), dec!(100), dec!(0.01)).with_fill(1, "BTC"),
+            Transaction::new(2, "BTC".to_string(), dec!(200), dec!(-0.99)).with_fill(3, "BTC"),
+            Transaction::new(2, "USDT".to_string(), dec!(200), dec!(198)).with_fill(3, "BTC"),
+            Transaction::fee(2, "USDT".to_string(), dec!(200), dec!(1.98)).with_fill(3, "BTC"),
+        ];
+        let cost_basis = CostBasis::from_transactions(&txs, "BTC", LotMatching::Fifo);
+        assert_eq!(
+            cost_basis.realized_gains(),
+            &[RealizedGain {
+                ts: 2,
+                qty: dec!(0.99),
+                proceeds: dec!(196.02),
+                cost: dec!(100),
+                gain: dec!(96.02),
+            }]
+        );
+        assert_eq!(cost_basis.open_qty(), dec!(0));
+    }
+
+    #[test]
+    fn test_sale_beyond_holdings_opens_a_short() {
+        let txs = vec![
+            // Starting capital is sold before going short
+            Transaction::new(0, "ETH".to_string(), dec!(0), dec!(1)),
+            Transaction::new(1, "ETH".to_string(), dec!(10), dec!(1)),
+            Transaction::new(2, "ETH".to_string(), dec!(15), dec!(-4)),
+        ];
+        let mut cost_basis = CostBasis::from_transactions(&txs, "ETH", LotMatching::Lifo);
+        assert_eq!(cost_basis.realized_gains().len(), 1);
+        assert_eq!(cost_basis.realized_gains()[0].qty, dec!(1));
+        assert_eq!(cost_basis.total_gain(), dec!(5));
+        assert_eq!(cost_basis.open_qty(), dec!(-2));
+
+        // Covering the short at 12 gains 3 on each, the rest opens a long lot
+        cost_basis.record(3, dec!(3), dec!(12), dec!(0));
+        assert_eq!(cost_basis.realized_gains()[1].qty, dec!(2));
+        assert_eq!(cost_basis.realized_gains()[1].gain, dec!(6));
+        assert_eq!(cost_basis.open_qty(), dec!(1));
+    }
+
+    #[test]
+    fn test_quote_legs_are_not_lots_of_the_quote() {
+        let txs = vec![
+            Transaction::new(0, "USDT".to_string(), dec!(0), dec!(1000)),
+            // USDT is the base here, and the quote of BTCUSDT below
+            Transaction::new(1, "USDT".to_string(), dec!(2), dec!(100)).with_fill(1, "USDT"),
+            Transaction::new(1, "BRL".to_string(), dec!(2), dec!(-200)).with_fill(1, "USDT"),
+            Transaction::new(2, "BTC".to_string(), dec!(500), dec!(1)).with_fill(2, "BTC"),
+            Transaction::new(2, "USDT".to_string(), dec!(500), dec!(-500)).with_fill(2, "BTC"),
+            Transaction::new(3, "USDT".to_string(), dec!(3), dec!(-100)).with_fill(3, "USDT"),
+            Transaction::new(3, "BRL".to_string(), dec!(3), dec!(300)).with_fill(3, "USDT"),
+        ];
+        let cost_basis = CostBasis::from_transactions(&txs, "USDT", LotMatching::Lifo);
+        assert_eq!(
+            cost_basis.realized_gains(),
+            &[RealizedGain {
+                ts: 3,
+                qty: dec!(100),
+                proceeds: dec!(300),
+                cost: dec!(200),
+                gain: dec!(100),
+            }]
+        );
+        assert_eq!(cost_basis.open_qty(), dec!(0));
+    }
+}
@@ -1,13 +1,35 @@
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
 use thiserror::Error;
 
-use crate::exchange::order::{Order, OrderDirection, OrderType};
+use crate::error::TradeSimError;
+use crate::exchange::cost_basis::{CostBasis, LotMatching};
+use crate::exchange::fee::{FeeSchedule, FeeTier};
+use crate::exchange::fill::{Fill, FillCondition, FillModel};
+use crate::exchange::margin::Margin;
+use crate::exchange::order::{
+    Order, OrderDirection, OrderFilter, OrderPreview, OrderRequest, OrderStatus, OrderType,
+    TimeInForce,
+};
+use crate::exchange::order_book::OrderBook;
+use crate::exchange::pnl::{PnlTracker, Position};
 use crate::exchange::price_feed::{BinanceKline, PriceFeed};
+use crate::exchange::price_provider::{default_price_provider, PriceProvider};
+use crate::exchange::rng::SimRng;
+use crate::exchange::slippage::SlippageModel;
+use crate::exchange::symbol_filters::SymbolFilters;
+use crate::exchange::trade_stats::TradeStats;
 use crate::exchange::transaction::Transaction;
-use crate::exchange::wallet::Wallet;
+use crate::exchange::wallet::{Wallet, WalletError};
 use rust_decimal::prelude::Decimal;
 use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 /*
 binance mock exchange ?
 
@@ -21,28 +43,91 @@ get_wallet:         take a look at the portfolios performance
 
  */
 
+#[derive(Serialize, Deserialize)]
 pub struct Exchange {
     active_orders: HashMap<String, Vec<Order>>,
     wallet: Wallet,
     price_feeds: HashMap<String, PriceFeed>,
+    #[serde(skip, default = "default_price_provider")]
+    price_provider: Arc<dyn PriceProvider>,
+    subbars: HashMap<String, PriceFeed>,
+    fills: Vec<Fill>,
+    filled_orders: Vec<Order>,
+    fee_schedule: FeeSchedule,
+    #[serde(default)]
+    traded_volume: HashMap<String, Decimal>,
+    fill_model: FillModel,
+    fill_condition: FillCondition,
+    slippage_model: SlippageModel,
+    symbol_filters: HashMap<String, SymbolFilters>,
+    spread_bps: Decimal,
+    participation_rate: Option<Decimal>,
+    #[serde(skip)]
+    replay_cancelled: Arc<AtomicBool>,
+    rng: SimRng,
+    fill_delay_jitter: Option<u32>,
+    fill_delays: HashMap<u64, u32>,
+    equity_quote: Option<String>,
+    equity_curve: Vec<(i64, Decimal)>,
+    #[serde(default)]
+    current_ts: i64,
+    #[serde(default)]
+    next_order_id: u64,
+    #[serde(default = "default_quote_assets")]
+    quote_assets: Vec<String>,
+    margin: Option<Margin>,
+    #[serde(default)]
+    short_selling: bool,
+    maintenance_margin: Option<Decimal>,
+    hourly_interest_rate: Option<Decimal>,
+}
+
+const DEFAULT_QUOTE_ASSETS: [&str; 32] = [
+    "AUD", "BIDR", "BKRW", "BNB", "BRL", "BTC", "BUSD", "BVND", "DAI", "DOGE", "DOT", "ETH", "EUR",
+    "GBP", "IDRT", "NGN", "PAX", "PLN", "RON", "RUB", "TRX", "TRY", "TUSD", "UAH", "USDC", "USDP",
+    "USDS", "USDT", "UST", "VAI", "XRP", "ZAR",
+];
+
+fn default_quote_assets() -> Vec<String> {
+    DEFAULT_QUOTE_ASSETS.map(String::from).to_vec()
 }
 
 #[derive(Debug, Clone, Error)]
 pub enum ExchangeError {
-    #[error("Failed to obtain asset pair")]
-    FailedToObtainAssetPair,
-    #[error("Insufficient funds")]
-    InsufficientFunds,
+    #[error("Failed to obtain asset pair from {0}")]
+    FailedToObtainAssetPair(String),
+    #[error("Insufficient funds for {0}")]
+    InsufficientFunds(String),
     #[error("Failed to place order")]
     FailedToPlaceOrder,
-    #[error("Failed to obtain a valid price")]
-    InvalidPrice,
     #[error("No KlineData")]
     NoKlineDataAvailable,
     #[error("Unable to obtain price from order")]
     NoOrderPriceAvailable,
+    #[error("Amount must be positive")]
+    InvalidAmount,
+    #[error("Replay speed must be a positive number")]
+    InvalidReplaySpeed,
     #[error("Unable to pull price feed")]
     NoPriceFeed,
+    #[error("OCO take profit price must be above the stop price")]
+    InvalidOcoPrices,
+    #[error("Iceberg visible quantity must be positive and at most the total quantity")]
+    InvalidVisibleQty,
+    #[error("No price to convert {0} into the quote currency")]
+    NoConversionPath(String),
+    #[error("Order notional is below the minimum for {0}")]
+    BelowMinNotional(String),
+    #[error("Order quantity is less than one lot of {0}")]
+    InvalidLotSize(String),
+    #[error("Post-only order would fill immediately")]
+    PostOnlyWouldCross,
+}
+
+impl Default for Exchange {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Exchange {
@@ -51,51 +136,524 @@ impl Exchange {
             active_orders: HashMap::new(),
             wallet: Wallet::new(),
             price_feeds: HashMap::new(),
+            price_provider: default_price_provider(),
+            subbars: HashMap::new(),
+            fills: vec![],
+            filled_orders: vec![],
+            fee_schedule: FeeSchedule::default(),
+            traded_volume: HashMap::new(),
+            fill_model: FillModel::default(),
+            fill_condition: FillCondition::default(),
+            slippage_model: SlippageModel::default(),
+            symbol_filters: HashMap::new(),
+            spread_bps: dec!(0),
+            participation_rate: None,
+            replay_cancelled: Arc::new(AtomicBool::new(false)),
+            rng: SimRng::new(0),
+            fill_delay_jitter: None,
+            fill_delays: HashMap::new(),
+            equity_quote: None,
+            equity_curve: vec![],
+            current_ts: 0,
+            next_order_id: 0,
+            quote_assets: default_quote_assets(),
+            margin: None,
+            short_selling: false,
+            maintenance_margin: None,
+            hourly_interest_rate: None,
         }
     }
+    /// Snapshots the exchange: wallet, orders, feeds with their cursors, fills and settings,
+    /// including the RNG state so a restored run draws the same random numbers. Price providers
+    /// aren't included, a restored exchange fetches new feeds from Binance.
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+    pub fn from_json(json: &str) -> Result<Exchange, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+    /// Hands `order` the next id of this exchange. Ids start at 1 on every exchange.
+    fn assign_order_id(&mut self, mut order: Order) -> Order {
+        self.next_order_id += 1;
+        order.id = self.next_order_id;
+        order
+    }
     pub fn with_capital(&mut self, funding: Vec<(String, Decimal)>) -> &mut Self {
         for (symbol, qty) in funding.iter() {
-            self.wallet.add(&Transaction::new(
-                0i64,
-                symbol.clone(),
-                dec!(0),
-                qty.clone(),
-            ));
+            self.wallet
+                .add(&Transaction::new(0i64, symbol.clone(), dec!(0), *qty));
+        }
+        self
+    }
+    pub fn deposit(&mut self, symbol: &str, amount: Decimal) -> Result<(), ExchangeError> {
+        if amount <= dec!(0) {
+            return Err(ExchangeError::InvalidAmount);
+        }
+        self.wallet.add(&Transaction::new(
+            self.now(),
+            symbol.to_string(),
+            dec!(0),
+            amount,
+        ));
+        Ok(())
+    }
+    /// The amount of `asset` borrowed on margin and not yet repaid, including what is held
+    /// short. A short is repaid by buying it back, `repay` can't pay it out of the balance.
+    pub fn borrowed(&self, asset: &str) -> Decimal {
+        self.wallet
+            .get_borrowed()
+            .get(asset)
+            .copied()
+            .unwrap_or_default()
+    }
+    /// Pays back `amount` of a margin loan in `asset` out of its balance.
+    pub fn repay(&mut self, asset: &str, amount: Decimal) -> Result<(), ExchangeError> {
+        if amount <= dec!(0) || amount > self.borrowed(asset) {
+            return Err(ExchangeError::InvalidAmount);
+        }
+        self.wallet
+            .try_add(&Transaction::loan(
+                self.now(),
+                asset.to_string(),
+                amount * dec!(-1),
+            ))
+            .map_err(|WalletError::InsufficientBalance(asset)| {
+                ExchangeError::InsufficientFunds(asset)
+            })
+    }
+    /// The largest notional a buy paid in `quote` can have: the balance of `quote` plus what
+    /// margin can still borrow against the equity, valued at the latest closes. Without margin
+    /// on `quote` that's just the balance.
+    pub fn max_position_size(&self, quote: &str) -> Result<Decimal, ExchangeError> {
+        Ok(self.get_balance(quote) + self.borrow_capacity(quote)?)
+    }
+    fn borrow_capacity(&self, quote: &str) -> Result<Decimal, ExchangeError> {
+        let Some(margin) = self.margin.as_ref().filter(|margin| margin.quote == quote) else {
+            return Ok(dec!(0));
+        };
+        let borrowed = self.borrowed(quote);
+        let equity = self.portfolio_value(quote, &self.latest_prices())? - borrowed;
+        Ok(margin.borrow_capacity(equity, borrowed))
+    }
+    /// How much more of an asset priced in `quote` can be sold short, in `quote`.
+    fn short_capacity(&self, quote: &str) -> Result<Decimal, ExchangeError> {
+        let leverage = self
+            .margin
+            .as_ref()
+            .filter(|margin| margin.quote == quote)
+            .map_or(dec!(1), |margin| margin.leverage);
+        let prices = self.latest_prices();
+        let equity = self.portfolio_value(quote, &prices)? - self.borrowed(quote);
+        Ok((equity * leverage - self.short_value(quote, &prices)).max(dec!(0)))
+    }
+    /// The value in `quote` of the balances held short, at `prices`.
+    fn short_value(&self, quote: &str, prices: &HashMap<String, Decimal>) -> Decimal {
+        self.wallet
+            .get_wallets()
+            .iter()
+            .filter(|(_, balance)| **balance < dec!(0))
+            .filter_map(|(asset, balance)| {
+                Some(balance.abs() * self.pair_price(prices, asset, quote)?)
+            })
+            .sum()
+    }
+    /// The asset a sell leaves short, if it sells more than is held and the short fits in
+    /// `short_capacity`.
+    fn short_sale<'a>(&self, fill: &'a Fill) -> Result<Option<&'a str>, ExchangeError> {
+        let (base, quote) = self.get_asset_pair(&fill.pair)?;
+        let short_qty = fill.qty - self.get_balance(base).max(dec!(0));
+        if !self.short_selling || fill.direction != OrderDirection::Sell || short_qty <= dec!(0) {
+            return Ok(None);
+        }
+        let capacity = self.short_capacity(quote).unwrap_or_default();
+        if short_qty * fill.price > capacity {
+            return Err(ExchangeError::InsufficientFunds(base.to_string()));
+        }
+        Ok(Some(base))
+    }
+    /// The loan a buy needs when its notional is more than the quote balance, if margin can
+    /// cover the difference.
+    fn margin_loan(&self, fill: &Fill) -> Result<Option<Transaction>, ExchangeError> {
+        let (_, quote) = self.get_asset_pair(&fill.pair)?;
+        let shortfall = fill.notional() - self.get_balance(quote);
+        if fill.direction != OrderDirection::Buy || shortfall <= dec!(0) {
+            return Ok(None);
+        }
+        // Funds the loan can't cover are reported the same as without margin
+        let capacity = self.borrow_capacity(quote).unwrap_or_default();
+        if capacity.is_zero() {
+            return Ok(None);
+        }
+        if shortfall > capacity {
+            return Err(ExchangeError::InsufficientFunds(quote.to_string()));
+        }
+        Ok(Some(Transaction::loan(
+            fill.ts,
+            quote.to_string(),
+            shortfall,
+        )))
+    }
+    /// Takes `amount` of `symbol` out of the wallet. Funds reserved by resting orders can't be
+    /// withdrawn.
+    pub fn withdraw(&mut self, symbol: &str, amount: Decimal) -> Result<(), ExchangeError> {
+        if amount <= dec!(0) {
+            return Err(ExchangeError::InvalidAmount);
+        }
+        let balance = self.get_balance(symbol);
+        if balance - self.reserved(symbol) < amount {
+            return Err(ExchangeError::InsufficientFunds(symbol.to_string()));
+        }
+        self.wallet.add(&Transaction::new(
+            self.now(),
+            symbol.to_string(),
+            dec!(0),
+            amount * dec!(-1),
+        ));
+        Ok(())
+    }
+    /// The simulated clock: the close timestamp of the newest bar consumed by `tick()`, or 0
+    /// before the first tick.
+    pub fn now(&self) -> i64 {
+        self.current_ts
+    }
+    pub fn with_fees(&mut self, fee_schedule: FeeSchedule) -> &mut Self {
+        self.fee_schedule = fee_schedule;
+        self
+    }
+    pub fn with_fill_model(&mut self, fill_model: FillModel) -> &mut Self {
+        self.fill_model = fill_model;
+        self
+    }
+    /// Whether resting limits fill when a bar merely touches their price, or only when it
+    /// trades through it, the default.
+    pub fn with_fill_condition(&mut self, fill_condition: FillCondition) -> &mut Self {
+        self.fill_condition = fill_condition;
+        self
+    }
+    /// How far market orders execute from the last close. Random models draw from the
+    /// exchange's seeded RNG, see `with_seed`.
+    pub fn with_slippage_model(&mut self, slippage_model: SlippageModel) -> &mut Self {
+        self.slippage_model = slippage_model;
+        self
+    }
+    /// Treats bar closes as the mid price of a book `bps` basis points wide: market buys pay
+    /// the ask (close + half the spread) and market sells receive the bid (close - half).
+    /// Resting limit orders are the ones quoting that spread, so they still fill at their limit.
+    pub fn with_spread(&mut self, bps: Decimal) -> &mut Self {
+        self.spread_bps = bps;
+        self
+    }
+    /// Limit prices and bar prices of `pair` are rounded to multiples of `tick_size` before
+    /// they're compared, so a limit with more precision than the feed still fills when the
+    /// bar reaches it to within a tick. Order and fill prices are rounded to it as well.
+    pub fn with_tick_size(&mut self, pair: &str, tick_size: Decimal) -> &mut Self {
+        self.symbol_filters
+            .entry(pair.to_string())
+            .or_default()
+            .tick_size = tick_size;
+        self
+    }
+    /// Lets pairs quoted in `quote` be parsed, on top of the Binance quote assets.
+    pub fn with_quote_asset(&mut self, quote: &str) -> &mut Self {
+        if !self.quote_assets.iter().any(|known| known == quote) {
+            self.quote_assets.push(quote.to_string());
         }
         self
     }
+    /// Allows buys paid in `quote` up to `leverage` times the equity, borrowing what the
+    /// balance can't cover, see `Margin`.
+    pub fn with_leverage(&mut self, quote: &str, leverage: Decimal) -> &mut Self {
+        self.margin = Some(Margin::new(quote, leverage));
+        self
+    }
+    /// Liquidates the margin account once its equity falls below `ratio` of the value of its
+    /// positions, see `liquidate`. Applies while a margin loan or a short is open.
+    pub fn with_maintenance_margin(&mut self, ratio: Decimal) -> &mut Self {
+        self.maintenance_margin = Some(ratio);
+        self
+    }
+    /// Charges interest on what the margin account borrows, `rate` per hour of simulated time,
+    /// see `accrue_interest`. Applies to margin loans and shorts.
+    pub fn with_borrow_interest(&mut self, rate: Decimal) -> &mut Self {
+        self.hourly_interest_rate = Some(rate);
+        self
+    }
+    /// Lets sells go beyond the held quantity, leaving a negative balance to be bought back.
+    /// The value held short is limited to the equity in the pair's quote asset, times the
+    /// leverage when margin is on that asset.
+    pub fn enable_short_selling(&mut self) -> &mut Self {
+        self.short_selling = true;
+        self
+    }
+    /// Trading rules for `pair` that new orders are checked against, see `SymbolFilters`. Order
+    /// prices are rounded to its tick on placement and fills to its tick and lot step.
+    pub fn with_symbol_filters(&mut self, pair: &str, filters: SymbolFilters) -> &mut Self {
+        self.symbol_filters.insert(pair.to_string(), filters);
+        self
+    }
+    /// Caps fills at `rate` times each candle's traded volume, leaving the rest of an order
+    /// resting for later candles. Without a rate, resting orders fill in full once crossed.
+    pub fn with_participation_rate(&mut self, rate: Decimal) -> &mut Self {
+        self.participation_rate = Some(rate);
+        self
+    }
+    /// Seeds the exchange's random number generator so randomized behavior is reproducible.
+    pub fn with_seed(&mut self, seed: u64) -> &mut Self {
+        self.rng = SimRng::new(seed);
+        self
+    }
+    /// Holds back each resting order's fill for a random 0..=`max_bars` of the bars on which it
+    /// would otherwise have filled, so orders don't all fill on the first eligible bar.
+    pub fn with_fill_delay_jitter(&mut self, max_bars: u32) -> &mut Self {
+        self.fill_delay_jitter = Some(max_bars);
+        self
+    }
+    /// Records the portfolio value in `quote` after every tick, see `equity_curve`.
+    pub fn enable_equity_tracking(&mut self, quote: &str) -> &mut Self {
+        self.equity_quote = Some(quote.to_string());
+        self
+    }
+    /// `(timestamp, portfolio value)` after each tick since equity tracking was enabled.
+    pub fn equity_curve(&self) -> &[(i64, Decimal)] {
+        &self.equity_curve
+    }
+    /// The change in portfolio value over each trailing window of `window_bars` ticks. Empty
+    /// unless equity is being tracked in `quote`.
+    pub fn rolling_pnl(&self, window_bars: usize, quote: &str) -> Vec<Decimal> {
+        if window_bars == 0 || self.equity_quote.as_deref() != Some(quote) {
+            return vec![];
+        }
+        self.equity_curve
+            .windows(window_bars + 1)
+            .map(|window| window[window_bars].1 - window[0].1)
+            .collect()
+    }
+    /// Beta of the bar-to-bar returns of the equity curve against those of `reference_pair`'s
+    /// close, over the ticks where both are known. `None` unless equity is being tracked in
+    /// `quote` and there are at least two overlapping returns with the reference moving.
+    pub fn portfolio_beta(&self, reference_pair: &str, quote: &str) -> Option<Decimal> {
+        if self.equity_quote.as_deref() != Some(quote) {
+            return None;
+        }
+        let closes: HashMap<i64, Decimal> = self
+            .price_feeds
+            .get(reference_pair)?
+            .consumed()
+            .iter()
+            .map(|kline| {
+                let (_, _, _, _, close) = kline.get_ohlc();
+                (kline.get_close_timestamp(), close)
+            })
+            .collect();
+        let points: Vec<(Decimal, Decimal)> = self
+            .equity_curve
+            .iter()
+            .filter_map(|(ts, value)| Some((*value, *closes.get(ts)?)))
+            .collect();
+        let returns: Vec<(Decimal, Decimal)> = points
+            .windows(2)
+            .filter(|window| !window[0].0.is_zero() && !window[0].1.is_zero())
+            .map(|window| {
+                (
+                    window[1].0 / window[0].0 - dec!(1),
+                    window[1].1 / window[0].1 - dec!(1),
+                )
+            })
+            .collect();
+        if returns.len() < 2 {
+            return None;
+        }
+        let n = Decimal::from(returns.len());
+        let mean_portfolio = returns.iter().map(|(p, _)| *p).sum::<Decimal>() / n;
+        let mean_reference = returns.iter().map(|(_, r)| *r).sum::<Decimal>() / n;
+        let mut covariance = dec!(0);
+        let mut variance = dec!(0);
+        for (p, r) in &returns {
+            covariance += (p - mean_portfolio) * (r - mean_reference);
+            variance += (r - mean_reference) * (r - mean_reference);
+        }
+        if variance.is_zero() {
+            return None;
+        }
+        Some(covariance / variance)
+    }
+    pub fn get_fee_schedule(&self) -> &FeeSchedule {
+        &self.fee_schedule
+    }
+    /// The fee tier the next fill on a `quote` pair is charged at, picked by the volume traded
+    /// in `quote`. Notionals in different quotes don't add up, so each climbs the tiers alone.
+    pub fn current_fee_tier(&self, quote: &str) -> FeeTier {
+        self.fee_schedule.tier_for(self.traded_volume(quote))
+    }
+    /// The summed notional of every fill on a `quote` pair so far.
+    pub fn traded_volume(&self, quote: &str) -> Decimal {
+        self.traded_volume.get(quote).copied().unwrap_or_default()
+    }
     pub fn with_price_feed(
         &mut self,
         symbol: String,
         interval: String,
         limit: i32,
     ) -> Result<&mut Self, ExchangeError> {
+        let provider = self.price_provider.clone();
         self.price_feeds
             .entry(symbol.clone())
-            .or_insert(PriceFeed::new())
+            .or_insert_with(|| PriceFeed::with_provider(provider))
             .initialize_price_feed(symbol, interval, limit)
             .map_err(|_| ExchangeError::NoPriceFeed)?;
         Ok(self)
     }
+    /// Where `with_price_feed` loads new feeds from, Binance unless set.
+    pub fn with_price_provider(&mut self, provider: Arc<dyn PriceProvider>) -> &mut Self {
+        self.price_provider = provider;
+        self
+    }
     pub fn add_price_feed(&mut self, symbol: String, price_feed: PriceFeed) -> &mut Self {
         self.price_feeds.insert(symbol, price_feed);
         self
     }
+    /// Attaches a finer grained feed to `pair`. When a bar is ticked its sub-bars are walked in
+    /// order to decide which orders fill first, instead of guessing from the bar's high and low.
+    pub fn with_subbars(&mut self, pair: String, sub_feed: PriceFeed) -> &mut Self {
+        self.subbars.insert(pair, sub_feed);
+        self
+    }
     pub fn price_feed_next(&mut self, symbol: &str) -> Option<BinanceKline> {
         if let Some(price_feed) = self.price_feeds.get_mut(symbol) {
             return price_feed.next();
         }
         None
     }
+    /// How many bars each feed has consumed so far, keyed by symbol.
+    pub fn bars_processed(&self) -> HashMap<String, usize> {
+        self.price_feeds
+            .iter()
+            .map(|(symbol, price_feed)| (symbol.clone(), price_feed.position()))
+            .collect()
+    }
+    /// Symbols whose last consumed bar closed more than `max_age_ms` before the newest bar
+    /// consumed across all feeds. A feed that hasn't produced a bar yet counts as stale as soon
+    /// as any other feed has.
+    pub fn stale_feeds(&self, max_age_ms: i64) -> Vec<String> {
+        let last_seen: Vec<(&String, Option<i64>)> = self
+            .price_feeds
+            .iter()
+            .map(|(symbol, price_feed)| {
+                let ts = price_feed.last_consumed().map(|k| k.get_close_timestamp());
+                (symbol, ts)
+            })
+            .collect();
+        let Some(newest) = last_seen.iter().filter_map(|(_, ts)| *ts).max() else {
+            return vec![];
+        };
+        let mut stale: Vec<String> = last_seen
+            .into_iter()
+            .filter(|(_, ts)| !ts.is_some_and(|ts| newest - ts <= max_age_ms))
+            .map(|(symbol, _)| symbol.clone())
+            .collect();
+        stale.sort();
+        stale
+    }
+    /// Pairs with resting orders but no registered price feed. Those orders can never fill.
+    pub fn orphan_order_symbols(&self) -> Vec<String> {
+        let mut orphans: Vec<String> = self
+            .active_orders
+            .iter()
+            .filter(|(symbol, orders)| {
+                !orders.is_empty() && !self.price_feeds.contains_key(symbol.as_str())
+            })
+            .map(|(symbol, _)| symbol.clone())
+            .collect();
+        orphans.sort();
+        orphans
+    }
     pub fn get_wallet(&self) -> &HashMap<String, Decimal> {
-        &self.wallet.get_wallets()
+        self.wallet.get_wallets()
+    }
+    /// The wallet balance of `symbol`, zero when it was never funded.
+    pub fn get_balance(&self, symbol: &str) -> Decimal {
+        self.get_wallet().get(symbol).copied().unwrap_or_default()
+    }
+    /// Whether the wallet holds a non-zero balance of `symbol`.
+    pub fn has_asset(&self, symbol: &str) -> bool {
+        !self.get_balance(symbol).is_zero()
     }
     pub fn get_transactions(&self) -> &Vec<Transaction> {
-        &self.wallet.get_transactions()
+        self.wallet.get_transactions()
+    }
+    pub fn get_fills(&self) -> &Vec<Fill> {
+        &self.fills
+    }
+    /// Resting orders that have completely filled, in the order they filled.
+    pub fn get_filled_orders(&self) -> &Vec<Order> {
+        &self.filled_orders
     }
     pub fn get_orders(&self) -> &HashMap<String, Vec<Order>> {
         &self.active_orders
     }
+    /// Resting orders matching `filter`, oldest first.
+    pub fn open_orders(&self, filter: OrderFilter) -> Vec<&Order> {
+        let mut orders: Vec<&Order> = self
+            .active_orders
+            .values()
+            .flatten()
+            .filter(|order| filter.matches(order))
+            .collect();
+        orders.sort_by_key(|order| order.id);
+        orders
+    }
+    /// The amount of `asset` committed to resting orders: quote for buys, base for sells. Legs
+    /// of an OCO group can only fill once so the group reserves a single leg.
+    pub fn reserved(&self, asset: &str) -> Decimal {
+        let mut reserved = dec!(0);
+        let mut counted_oco_groups: Vec<u64> = vec![];
+        for order in self.active_orders.values().flatten() {
+            if let Some(group) = order.oco_group {
+                if counted_oco_groups.contains(&group) {
+                    continue;
+                }
+                counted_oco_groups.push(group);
+            }
+            let Ok((base, quote)) = self.get_asset_pair(&order.pair) else {
+                continue;
+            };
+            match order.direction {
+                OrderDirection::Buy if quote == asset => {
+                    reserved += order.remaining_qty() * order.price.unwrap_or_default();
+                }
+                OrderDirection::Sell if base == asset => reserved += order.remaining_qty(),
+                _ => {}
+            }
+        }
+        reserved
+    }
+    /// How much more of `quote` can be committed to new buys: the balance not reserved by
+    /// resting orders, plus what margin can still lend when it's enabled in `quote`.
+    pub fn buying_power(&self, quote: &str) -> Decimal {
+        let available = self.get_balance(quote) + self.borrow_capacity(quote).unwrap_or_default();
+        (available - self.reserved(quote)).max(dec!(0))
+    }
+    pub fn get_order(&self, order_id: u64) -> Option<&Order> {
+        self.active_orders
+            .values()
+            .flatten()
+            .find(|order| order.id == order_id)
+    }
+    fn get_order_mut(&mut self, order_id: u64) -> Option<&mut Order> {
+        self.active_orders
+            .values_mut()
+            .flatten()
+            .find(|order| order.id == order_id)
+    }
+    pub fn get_order_status(&self, order_id: u64) -> Option<OrderStatus> {
+        self.get_order(order_id).map(|order| order.status.clone())
+    }
+    pub fn get_order_book(&self, pair: &str) -> OrderBook {
+        self.active_orders
+            .get(pair)
+            .map(|orders| OrderBook::from_orders(orders))
+            .unwrap_or_default()
+    }
     pub fn place_order(
         &mut self,
         pair: &str,
@@ -104,37 +662,630 @@ impl Exchange {
         direction: OrderDirection,
         order_type: OrderType,
     ) -> Result<Order, ExchangeError> {
-        // Get the base asset and the quote asset
-        let (base, quote) = Exchange::get_asset_pair(pair)?;
-        // Check if the wallet has the required funds
+        self.place_order_with_time_in_force(
+            pair,
+            optional_price,
+            qty,
+            direction,
+            order_type,
+            TimeInForce::GTC,
+        )
+    }
+    /// Places each request in turn. A rejected request doesn't stop the rest of the batch, its
+    /// error takes its place in the results.
+    pub fn place_orders(&mut self, orders: Vec<OrderRequest>) -> Vec<Result<Order, ExchangeError>> {
+        orders
+            .into_iter()
+            .map(|request| {
+                self.place_order(
+                    &request.pair,
+                    request.price,
+                    request.qty,
+                    request.direction,
+                    request.order_type,
+                )
+            })
+            .collect()
+    }
+    pub fn place_order_with_time_in_force(
+        &mut self,
+        pair: &str,
+        optional_price: Option<Decimal>,
+        qty: Decimal,
+        direction: OrderDirection,
+        order_type: OrderType,
+        time_in_force: TimeInForce,
+    ) -> Result<Order, ExchangeError> {
+        let (optional_price, qty) =
+            self.validate_order(pair, optional_price, qty, &direction, &order_type)?;
         if order_type == OrderType::Market {
-            unimplemented!("Stop letting the exchange take your money!");
+            let new_order = self.assign_order_id(Order::new_order(
+                pair,
+                optional_price,
+                qty,
+                direction,
+                order_type,
+            ));
+            let fill = self.market_fill(&new_order, qty)?;
+            return self.book_market_fill(new_order, fill);
         }
-        if let Some(price) = optional_price {
-            match direction {
-                OrderDirection::Buy => {
-                    if let None = self.wallet.has_funds_for_order(quote, price * qty) {
-                        return Err(ExchangeError::InsufficientFunds);
-                    }
-                }
-                OrderDirection::Sell => {
-                    if let None = self.wallet.has_funds_for_order(base, qty) {
-                        return Err(ExchangeError::InsufficientFunds);
-                    }
-                }
-            }
+
+        let mut new_order = self.assign_order_id(Order::new_order(
+            pair,
+            optional_price,
+            qty,
+            direction,
+            order_type,
+        ));
+        new_order.time_in_force = time_in_force;
+        if matches!(new_order.time_in_force, TimeInForce::IOC | TimeInForce::FOK) {
+            return self.match_immediately(new_order);
         }
 
         // Create the order and add to the orders hashmap
-        self.active_orders.entry(pair.to_string()).or_insert(vec![]);
-
-        let new_order = Order::new_order(pair, optional_price, qty, direction, order_type);
         self.active_orders
             .entry(pair.to_string())
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(new_order.clone());
         Ok(new_order)
     }
+    /// Rounds an order's price and quantity to the filters of `pair` and checks the wallet can
+    /// fund it. Market orders are left to the wallet when they fill.
+    fn validate_order(
+        &self,
+        pair: &str,
+        optional_price: Option<Decimal>,
+        qty: Decimal,
+        direction: &OrderDirection,
+        order_type: &OrderType,
+    ) -> Result<(Option<Decimal>, Decimal), ExchangeError> {
+        let (base, quote) = self.get_asset_pair(pair)?;
+        let qty = self.filter_order_qty(pair, optional_price, qty)?;
+        let optional_price = match self.symbol_filters.get(pair) {
+            Some(filters) => {
+                optional_price.map(|price| filters.round_limit_price(price, direction))
+            }
+            None => optional_price,
+        };
+        // Market orders are checked at the latest price, there's nothing to check before it
+        let check_price = match order_type {
+            OrderType::Market => self.mark_price(pair).ok(),
+            _ => optional_price,
+        };
+        if let Some(price) = check_price {
+            self.check_funds(pair, (base, quote), direction, order_type, price, qty)?;
+        }
+        Ok((optional_price, qty))
+    }
+    /// Fees come out of the asset received, as `fill_transactions` books them. A buy has to
+    /// cover its notional in quote, and its base balance has to stay positive once the fee at
+    /// the rate `order_type` pays is taken from the base bought, which only binds when covering
+    /// a short. A sell's fee comes out of the quote it receives. Buys can spend margin, see
+    /// `max_position_size`. With short selling, sells can go short up to `short_capacity`.
+    fn check_funds(
+        &self,
+        pair: &str,
+        (base, quote): (&str, &str),
+        direction: &OrderDirection,
+        order_type: &OrderType,
+        price: Decimal,
+        qty: Decimal,
+    ) -> Result<(), ExchangeError> {
+        let funded = match direction {
+            OrderDirection::Buy => {
+                price * qty <= self.max_position_size(quote)?
+                    && self.get_balance(base) + qty * (dec!(1) - self.fee_rate(quote, order_type))
+                        >= dec!(0)
+            }
+            OrderDirection::Sell => {
+                self.wallet.has_funds_for_order(base, qty).is_some()
+                    || (self.short_selling
+                        && (qty - self.get_balance(base).max(dec!(0))) * price
+                            <= self.short_capacity(quote)?)
+            }
+        };
+        if !funded {
+            return Err(ExchangeError::InsufficientFunds(pair.to_string()));
+        }
+        Ok(())
+    }
+    /// The fee rate an order of `order_type` on a `quote` pair pays at the current tier.
+    /// Resting limit orders provide liquidity, market orders and triggered stops take it.
+    fn fee_rate(&self, quote: &str, order_type: &OrderType) -> Decimal {
+        match order_type {
+            OrderType::Limit => self.current_fee_tier(quote).maker,
+            _ => self.current_fee_tier(quote).taker,
+        }
+    }
+    /// Runs the checks `place_order` would without placing anything, estimating where the order
+    /// would fill and the fee it would pay. Limit orders rest as makers, market orders are
+    /// filled like `market_fill` against the latest bar, spread, slippage and rounding
+    /// included, and the fill is checked against the wallet the way booking it would be.
+    pub fn simulate_order(&self, request: &OrderRequest) -> Result<OrderPreview, ExchangeError> {
+        let pair = request.pair.as_str();
+        let (optional_price, qty) = self.validate_order(
+            pair,
+            request.price,
+            request.qty,
+            &request.direction,
+            &request.order_type,
+        )?;
+        let order = Order::new_order(
+            pair,
+            optional_price,
+            qty,
+            request.direction.clone(),
+            request.order_type.clone(),
+        );
+        let fill = match request.order_type {
+            OrderType::Market => {
+                let fill = self.preview_market_fill(&order, qty, &mut self.rng.clone())?;
+                let (transactions, short) = self.fill_booking(&fill)?;
+                self.wallet.check_all(&transactions, short).map_err(
+                    |WalletError::InsufficientBalance(asset)| {
+                        ExchangeError::InsufficientFunds(asset)
+                    },
+                )?;
+                fill
+            }
+            _ => {
+                let price = optional_price.ok_or(ExchangeError::NoOrderPriceAvailable)?;
+                let (_, quote) = self.get_asset_pair(pair)?;
+                Fill::new(self.now(), &order, price, qty)
+                    .with_fee_rate(self.fee_rate(quote, &request.order_type))
+            }
+        };
+        Ok(OrderPreview {
+            pair: request.pair.clone(),
+            direction: request.direction.clone(),
+            price: fill.price,
+            qty: fill.qty,
+            notional: fill.notional(),
+            fee: fill.fee_in_quote(),
+        })
+    }
+    /// Rounds `qty` down to the lot step of `pair` and checks the order against its minimum
+    /// notional, valued at the latest close when the order has no price.
+    fn filter_order_qty(
+        &self,
+        pair: &str,
+        optional_price: Option<Decimal>,
+        qty: Decimal,
+    ) -> Result<Decimal, ExchangeError> {
+        let Some(filters) = self.symbol_filters.get(pair) else {
+            return Ok(qty);
+        };
+        let qty = filters.round_qty(qty);
+        if qty <= dec!(0) {
+            return Err(ExchangeError::InvalidLotSize(pair.to_string()));
+        }
+        let price = optional_price.or_else(|| self.mark_price(pair).ok());
+        if price.is_some_and(|price| !filters.meets_min_notional(price, qty)) {
+            return Err(ExchangeError::BelowMinNotional(pair.to_string()));
+        }
+        Ok(qty)
+    }
+    pub fn place_tagged_order(
+        &mut self,
+        tag: &str,
+        pair: &str,
+        optional_price: Option<Decimal>,
+        qty: Decimal,
+        direction: OrderDirection,
+        order_type: OrderType,
+    ) -> Result<Order, ExchangeError> {
+        let mut order = self.place_order(pair, optional_price, qty, direction, order_type)?;
+        order.client_tag = Some(tag.to_string());
+        if let Some(resting) = self.get_order_mut(order.id) {
+            resting.client_tag = order.client_tag.clone();
+        }
+        Ok(order)
+    }
+    /// Cancels every resting order carrying `tag`, returning them.
+    pub fn cancel_by_tag(&mut self, tag: &str) -> Vec<Order> {
+        let mut cancelled = vec![];
+        for orders in self.active_orders.values_mut() {
+            let (tagged, untagged) = orders
+                .drain(..)
+                .partition(|order| order.client_tag.as_deref() == Some(tag));
+            *orders = untagged;
+            cancelled.extend(tagged);
+        }
+        for order in cancelled.iter_mut() {
+            order.cancelled();
+            self.fill_delays.remove(&order.id);
+        }
+        cancelled
+    }
+    /// Cancels every resting order on `pair`, returning them. Funds they reserved are released.
+    pub fn cancel_all_orders(&mut self, pair: &str) -> Vec<Order> {
+        let mut cancelled: Vec<Order> = self
+            .active_orders
+            .get_mut(pair)
+            .map(std::mem::take)
+            .unwrap_or_default();
+        for order in cancelled.iter_mut() {
+            order.cancelled();
+            self.fill_delays.remove(&order.id);
+        }
+        cancelled
+    }
+    /// Cancels every resting order on every pair, oldest first.
+    pub fn cancel_all(&mut self) -> Vec<Order> {
+        let mut pairs: Vec<String> = self.active_orders.keys().cloned().collect();
+        pairs.sort();
+        let mut cancelled: Vec<Order> = pairs
+            .iter()
+            .flat_map(|pair| self.cancel_all_orders(pair))
+            .collect();
+        cancelled.sort_by_key(|order| order.id);
+        cancelled
+    }
+    /// Matches an IOC or FOK order against the latest consumed candle of its pair, using the
+    /// candle's volume as the liquidity available. Nothing is left resting: an IOC order keeps
+    /// whatever filled and a FOK order that can't fill completely is cancelled untouched.
+    fn match_immediately(&mut self, mut order: Order) -> Result<Order, ExchangeError> {
+        let mut fill = None;
+        let taker = self.fee_rate(self.get_asset_pair(&order.pair)?.1, &OrderType::Market);
+        if let Some(kline_data) = self.latest_kline(&order.pair) {
+            let (timestamp, open, high, low, _) = kline_data.get_ohlc();
+            let liquidity = kline_data.get_volume() * self.participation_rate.unwrap_or(dec!(1));
+            let prices = (open, high, low);
+            fill = self
+                .tick_handle_order(timestamp, prices, &order)?
+                .map(|mut fill| {
+                    fill.qty = fill.qty.min(liquidity);
+                    self.round_fill(fill).with_fee_rate(taker)
+                });
+        }
+        if order.time_in_force == TimeInForce::FOK {
+            fill = fill.filter(|fill| fill.qty == order.qty);
+        }
+
+        match fill {
+            Some(fill) if fill.qty > dec!(0) => {
+                order.record_fill(fill.qty);
+                self.apply_fill(fill)?;
+            }
+            _ => order.cancelled(),
+        }
+        Ok(order)
+    }
+    /// Market orders execute in full as takers against the bid or ask at the close of the
+    /// pair's latest bar, moved further against the order by the slippage model.
+    fn market_fill(&mut self, order: &Order, qty: Decimal) -> Result<Fill, ExchangeError> {
+        let mut rng = self.rng.clone();
+        let fill = self.preview_market_fill(order, qty, &mut rng)?;
+        self.rng = rng;
+        Ok(fill)
+    }
+    /// The fill `market_fill` would book, drawing any randomness from `rng`.
+    fn preview_market_fill(
+        &self,
+        order: &Order,
+        qty: Decimal,
+        rng: &mut SimRng,
+    ) -> Result<Fill, ExchangeError> {
+        let kline_data = self
+            .latest_kline(&order.pair)
+            .ok_or(ExchangeError::NoKlineDataAvailable)?;
+        let ts = kline_data.get_close_timestamp();
+        let price = self.market_price(&order.pair, &order.direction, qty, rng)?;
+        let (_, quote) = self.get_asset_pair(&order.pair)?;
+        Ok(self
+            .round_fill(Fill::new(ts, order, price, qty))
+            .with_fee_rate(self.fee_rate(quote, &OrderType::Market)))
+    }
+    /// The bid or ask at the close of the latest bar of `pair`, moved by the slippage model.
+    fn market_price(
+        &self,
+        pair: &str,
+        direction: &OrderDirection,
+        qty: Decimal,
+        rng: &mut SimRng,
+    ) -> Result<Decimal, ExchangeError> {
+        let kline_data = self
+            .latest_kline(pair)
+            .ok_or(ExchangeError::NoKlineDataAvailable)?;
+        let volume = kline_data.get_volume();
+        let mid_price = self.mark_price(pair)?;
+        let half_spread = mid_price * self.spread_bps / dec!(20_000);
+        let reference_price = match direction {
+            OrderDirection::Buy => mid_price + half_spread,
+            OrderDirection::Sell => mid_price - half_spread,
+        };
+        Ok(self
+            .slippage_model
+            .apply(reference_price, direction, qty, volume, rng))
+    }
+    /// Rounds a fill's price to the tick against its direction and its quantity down to the lot
+    /// step of its pair. A resting limit price is already on the tick, so it never fills past it.
+    fn round_fill(&self, mut fill: Fill) -> Fill {
+        if let Some(filters) = self.symbol_filters.get(&fill.pair) {
+            fill.price = filters.round_fill_price(fill.price, &fill.direction);
+            fill.qty = filters.round_qty(fill.qty);
+        }
+        fill
+    }
+    fn book_market_fill(&mut self, mut order: Order, fill: Fill) -> Result<Order, ExchangeError> {
+        order.price = Some(fill.price);
+        let qty = fill.qty;
+        self.apply_fill(fill)?;
+        order.record_fill(qty);
+        Ok(order)
+    }
+    /// Market orders the held balance of `base` back to exactly zero on the first pair (by
+    /// name) that trades it and has a price. A long is sold outright. A short is bought back
+    /// with the quantity grossed up for the taker fee and rounded up to the lot step, the fee
+    /// taken from the base received covers the rounding.
+    pub fn close_position(&mut self, base: &str) -> Result<Order, ExchangeError> {
+        if self.get_balance(base).is_zero() {
+            return Err(ExchangeError::InvalidAmount);
+        }
+        let mut pairs: Vec<&String> = self
+            .price_feeds
+            .keys()
+            .filter(|pair| matches!(self.get_asset_pair(pair), Ok((b, _)) if b == base))
+            .filter(|pair| self.latest_kline(pair).is_some())
+            .collect();
+        pairs.sort();
+        let pair = pairs.first().ok_or(ExchangeError::NoPriceFeed)?.to_string();
+        self.close_position_on(&pair, false)
+    }
+    /// Like `close_position`, on `pair`. With `borrow_deficit` a short the quote balance can't
+    /// buy back is still covered, the difference is added to the loan in the quote asset.
+    fn close_position_on(
+        &mut self,
+        pair: &str,
+        borrow_deficit: bool,
+    ) -> Result<Order, ExchangeError> {
+        let (base, _) = self.get_asset_pair(pair)?;
+        let balance = self.get_balance(base);
+        if balance.is_zero() {
+            return Err(ExchangeError::InvalidAmount);
+        }
+        let pair = pair.to_string();
+        let price = self.mark_price(&pair)?;
+
+        if balance > dec!(0) {
+            let order = self.assign_order_id(Order::new_market_sell(&pair, price, balance));
+            let fill = self.market_fill(&order, balance)?;
+            return self.book_market_fill(order, fill);
+        }
+        let short = balance * dec!(-1);
+        let taker = self.fee_rate(self.get_asset_pair(&pair)?.1, &OrderType::Market);
+        let mut qty = short / (dec!(1) - taker);
+        // Whole steps, so the fill's lot rounding can't take it back below the gross-up
+        if let Some(filters) = self.symbol_filters.get(&pair) {
+            qty = filters.round_qty_up(qty);
+        }
+        let order = self.assign_order_id(Order::new_market_buy(&pair, price, qty));
+        let mut fill = self.market_fill(&order, qty)?;
+        // The fee takes whatever the rounded fill bought beyond the short, leaving no dust
+        fill.fee = fill.qty - short;
+        if borrow_deficit {
+            let quote = self.get_asset_pair(&pair)?.1.to_string();
+            let deficit = fill.notional() - self.get_balance(&quote).max(dec!(0));
+            if deficit > dec!(0) {
+                self.wallet.add(&Transaction::loan(fill.ts, quote, deficit));
+            }
+        }
+        self.book_market_fill(order, fill)
+    }
+    /// The most recently consumed candle of `pair`.
+    pub fn latest_kline(&self, pair: &str) -> Option<&BinanceKline> {
+        self.price_feeds.get(pair)?.last_consumed()
+    }
+    /// The close of the latest consumed candle for `pair`.
+    fn mark_price(&self, pair: &str) -> Result<Decimal, ExchangeError> {
+        let kline_data = self
+            .latest_kline(pair)
+            .ok_or(ExchangeError::NoKlineDataAvailable)?;
+        let (_, _, _, _, close) = kline_data.get_ohlc();
+        Ok(close)
+    }
+    /// Win rate, average win and loss and profit factor of the trades closed so far, see
+    /// `TradeStats` for how fills are paired into trades.
+    pub fn trade_stats(&self) -> TradeStats {
+        TradeStats::from_fills(&self.fills)
+    }
+    /// The latest price of `pair` without advancing its feed: the close of the last consumed
+    /// candle, or before the first tick the open of the upcoming one, so it never looks ahead.
+    pub fn current_price(&self, pair: &str) -> Option<Decimal> {
+        if let Ok(price) = self.mark_price(pair) {
+            return Some(price);
+        }
+        let (_, open, _, _, _) = self.price_feeds.get(pair)?.peek()?.get_ohlc();
+        Some(open)
+    }
+    /// Tracks shorts when short selling is enabled.
+    fn pnl_tracker(&self) -> PnlTracker {
+        if self.short_selling {
+            PnlTracker::with_shorts()
+        } else {
+            PnlTracker::new()
+        }
+    }
+    /// Realized PnL of fills on `quote` pairs keyed by base asset, see `PnlTracker`.
+    pub fn realized_pnl(&self, quote: &str) -> HashMap<String, Decimal> {
+        let mut tracker = self.pnl_tracker();
+        for fill in &self.fills {
+            if let Ok((base, q)) = self.get_asset_pair(&fill.pair) {
+                if q == quote {
+                    tracker.record(base, fill);
+                }
+            }
+        }
+        tracker.realized().clone()
+    }
+    /// The average price paid for the position held through `symbol`'s fills, by quantity.
+    /// Sells reduce the position at that average without changing it, and a position sold
+    /// down to flat starts over. `None` without a position, see `PnlTracker`.
+    pub fn average_entry_price(&self, symbol: &str) -> Option<Decimal> {
+        let (base, _) = self.get_asset_pair(symbol).ok()?;
+        let mut tracker = self.pnl_tracker();
+        for fill in self.fills.iter().filter(|fill| fill.pair == symbol) {
+            tracker.record(base, fill);
+        }
+        tracker.average_cost(base)
+    }
+    /// The position held through `symbol`'s fills, built the same way as
+    /// `average_entry_price` and marked at `current_price`. Starting capital and deposits
+    /// aren't part of it, and the wallet is left as is. Flat without fills or a known pair,
+    /// and without a price the unrealized PnL is zero.
+    pub fn position(&self, symbol: &str) -> Position {
+        let Ok((base, _)) = self.get_asset_pair(symbol) else {
+            return Position::default();
+        };
+        let mut tracker = self.pnl_tracker();
+        for fill in self.fills.iter().filter(|fill| fill.pair == symbol) {
+            tracker.record(base, fill);
+        }
+        let Some(avg_entry) = tracker.average_cost(base) else {
+            return Position::default();
+        };
+        let qty = tracker.quantity(base);
+        let unrealized_pnl = self
+            .current_price(symbol)
+            .map(|price| (price - avg_entry) * qty)
+            .unwrap_or_default();
+        Position {
+            qty,
+            avg_entry,
+            unrealized_pnl,
+        }
+    }
+    /// Per-sale realized gains of `asset` from the transaction history, matching sales to lots
+    /// with `matching`, see `CostBasis`.
+    pub fn cost_basis(&self, asset: &str, matching: LotMatching) -> CostBasis {
+        CostBasis::from_transactions(self.wallet.get_transactions(), asset, matching)
+    }
+    /// Splits the equity change produced by fills on `quote` pairs into `(price_pnl, fee_cost)`,
+    /// with `price_pnl - fee_cost` being the net change. Quantities still held are marked at the
+    /// latest close of their pair, or at the last fill price when no candle has been consumed,
+    /// and fees taken in the base asset are valued the same way.
+    pub fn pnl_attribution(&self, quote: &str) -> (Decimal, Decimal) {
+        let mut price_pnl = dec!(0);
+        let mut fee_cost = dec!(0);
+        let mut net_base: HashMap<&str, (Decimal, Decimal)> = HashMap::new();
+        for fill in &self.fills {
+            if !matches!(self.get_asset_pair(&fill.pair), Ok((_, q)) if q == quote) {
+                continue;
+            }
+            let (qty, fees) = net_base.entry(&fill.pair).or_default();
+            match fill.direction {
+                OrderDirection::Buy => {
+                    price_pnl -= fill.notional();
+                    *qty += fill.qty;
+                    *fees += fill.fee;
+                }
+                OrderDirection::Sell => {
+                    price_pnl += fill.notional();
+                    *qty -= fill.qty;
+                    fee_cost += fill.fee;
+                }
+            }
+        }
+        for (pair, (qty, base_fees)) in net_base {
+            let last_fill_price = self
+                .fills
+                .iter()
+                .rev()
+                .find(|f| f.pair == pair)
+                .map(|f| f.price);
+            let mark = self
+                .mark_price(pair)
+                .ok()
+                .or(last_fill_price)
+                .unwrap_or_default();
+            price_pnl += qty * mark;
+            fee_cost += base_fees * mark;
+        }
+        (price_pnl, fee_cost)
+    }
+    /// Sums every wallet balance converted into `quote` using `prices`, keyed by pair symbol.
+    /// `quote` itself counts at face value, other assets need a direct pair such as BTCUSDT or,
+    /// failing that, the inverse pair. A non-zero balance with neither fails with
+    /// `NoConversionPath` rather than being silently left out of the total.
+    pub fn portfolio_value(
+        &self,
+        quote: &str,
+        prices: &HashMap<String, Decimal>,
+    ) -> Result<Decimal, ExchangeError> {
+        let mut total = dec!(0);
+        for (asset, balance) in self.wallet.get_wallets() {
+            if asset == quote {
+                total += balance;
+                continue;
+            }
+            if balance.is_zero() {
+                continue;
+            }
+            let direct = self.pair_price(prices, asset, quote);
+            let inverse = self.pair_price(prices, quote, asset);
+            total += match (direct, inverse) {
+                (Some(price), _) => balance * price,
+                (None, Some(price)) if !price.is_zero() => balance / price,
+                _ => return Err(ExchangeError::NoConversionPath(asset.clone())),
+            };
+        }
+        Ok(total)
+    }
+    /// The share of the portfolio value held in `quote` itself, valuing other assets at the
+    /// last close of their pair. Zero for an empty portfolio.
+    pub fn cash_ratio(&self, quote: &str) -> Result<Decimal, ExchangeError> {
+        let total = self.portfolio_value(quote, &self.latest_prices())?;
+        if total.is_zero() {
+            return Ok(dec!(0));
+        }
+        let cash = self.get_balance(quote);
+        Ok(cash / total)
+    }
+    /// The last close of every feed that has produced a bar, keyed by pair.
+    /// The price of `base` in `quote` among `prices`, keyed by pair however it's written,
+    /// "BTCUSDT", "BTC/USDT" or "BTC-USDT".
+    fn pair_price(
+        &self,
+        prices: &HashMap<String, Decimal>,
+        base: &str,
+        quote: &str,
+    ) -> Option<Decimal> {
+        if let Some(price) = prices.get(&format!("{}{}", base, quote)) {
+            return Some(*price);
+        }
+        prices
+            .iter()
+            .find(|(pair, _)| matches!(self.get_asset_pair(pair), Ok(assets) if assets == (base, quote)))
+            .map(|(_, price)| *price)
+    }
+    /// The key of the feed trading `base` against `quote`, however the pair is written.
+    fn feed_pair(&self, base: &str, quote: &str) -> Option<String> {
+        let concatenated = format!("{}{}", base, quote);
+        if self.price_feeds.contains_key(&concatenated) {
+            return Some(concatenated);
+        }
+        self.price_feeds
+            .keys()
+            .find(|pair| matches!(self.get_asset_pair(pair), Ok(assets) if assets == (base, quote)))
+            .cloned()
+    }
+    fn latest_prices(&self) -> HashMap<String, Decimal> {
+        self.price_feeds
+            .keys()
+            .filter_map(|pair| Some((pair.clone(), self.mark_price(pair).ok()?)))
+            .collect()
+    }
+    /// The held quantity of `base` valued at the latest close of `base`/`quote`.
+    pub fn position_notional(&self, base: &str, quote: &str) -> Result<Decimal, ExchangeError> {
+        let qty = self.get_balance(base);
+        if qty.is_zero() {
+            return Ok(dec!(0));
+        }
+        let pair = self
+            .feed_pair(base, quote)
+            .ok_or(ExchangeError::NoKlineDataAvailable)?;
+        Ok(qty * self.mark_price(&pair)?)
+    }
     pub fn place_limit_buy_order(
         &mut self,
         pair: &str,
@@ -192,167 +1343,854 @@ impl Exchange {
         )
     }
 
-    pub fn tick(&mut self) -> Result<(), ExchangeError> {
-        let mut transactions_to_be_added: Vec<Transaction> = vec![];
-        let active_orders = self.active_orders.clone();
-        for symbol in self.price_feeds.clone().keys() {
-            if let Some(kline_data) = self.price_feed_next(symbol.as_str()) {
-                let mut executed_orders: Vec<u64> = vec![];
-                let (timestamp, _, high, low, _) = kline_data.get_ohlc();
-                for order in &active_orders[symbol] {
-                    let is_executed = Self::tick_handle_order(
-                        &mut transactions_to_be_added,
-                        &symbol,
-                        timestamp,
-                        high,
-                        low,
-                        order,
-                    )?;
-                    if is_executed {
-                        executed_orders.push(order.id);
-                    }
-                }
-
-                self.active_orders
-                    .get_mut(symbol)
-                    .unwrap()
-                    .retain(|order| !executed_orders.contains(&order.id));
-            }
+    /// Places a limit order that stays dormant until a bar reaches `trigger_price`: at or above
+    /// it for buys, at or below it for sells. It can fill from the bar after the trigger.
+    pub fn place_stop_limit_order(
+        &mut self,
+        pair: &str,
+        trigger_price: Decimal,
+        price: Decimal,
+        qty: Decimal,
+        direction: OrderDirection,
+    ) -> Result<Order, ExchangeError> {
+        let mut order = self.place_order(pair, Some(price), qty, direction, OrderType::Limit)?;
+        order.trigger_price = Some(trigger_price);
+        order.activated = false;
+        if let Some(resting) = self.get_order_mut(order.id) {
+            resting.trigger_price = order.trigger_price;
+            resting.activated = false;
         }
-
-        for tx in transactions_to_be_added {
-            self.wallet.add(&tx);
+        Ok(order)
+    }
+    /// Places a limit order that must rest on the book. It is rejected if the latest candle
+    /// of `pair` already reaches its price, as it would fill right away as a taker. Once
+    /// resting it only fills as a maker, at its limit price even when a bar gaps past it.
+    pub fn place_post_only_order(
+        &mut self,
+        pair: &str,
+        price: Decimal,
+        qty: Decimal,
+        direction: OrderDirection,
+    ) -> Result<Order, ExchangeError> {
+        if let Some(kline_data) = self.latest_kline(pair) {
+            let (_, _, high, low, _) = kline_data.get_ohlc();
+            let bar_price = match direction {
+                OrderDirection::Buy => low,
+                OrderDirection::Sell => high,
+            };
+            if self.fill_condition.reached(bar_price, price, &direction) {
+                return Err(ExchangeError::PostOnlyWouldCross);
+            }
+        }
+        let mut order = self.place_order(pair, Some(price), qty, direction, OrderType::Limit)?;
+        order.post_only = true;
+        if let Some(resting) = self.get_order_mut(order.id) {
+            resting.post_only = true;
+        }
+        Ok(order)
+    }
+    /// Places a limit order that only reduces the position in the base of `pair`, like
+    /// `place_order` with the quantity capped at the position, so it can be placed for more
+    /// than is held. When it fills, the quantity is capped again at the position left and the
+    /// order ends there. Without a position to reduce the order is rejected.
+    pub fn place_reduce_only_order(
+        &mut self,
+        pair: &str,
+        price: Decimal,
+        qty: Decimal,
+        direction: OrderDirection,
+    ) -> Result<Order, ExchangeError> {
+        let position = self.reducible_qty(pair, &direction)?;
+        if position <= dec!(0) {
+            return Err(ExchangeError::InvalidAmount);
+        }
+        let mut order = self.place_order(
+            pair,
+            Some(price),
+            qty.min(position),
+            direction,
+            OrderType::Limit,
+        )?;
+        order.reduce_only = true;
+        if let Some(resting) = self.get_order_mut(order.id) {
+            resting.reduce_only = true;
+        }
+        Ok(order)
+    }
+    pub fn place_iceberg_limit_sell_order(
+        &mut self,
+        pair: &str,
+        price: Decimal,
+        total_qty: Decimal,
+        visible_qty: Decimal,
+    ) -> Result<Order, ExchangeError> {
+        let (price, total_qty) = self.validate_order(
+            pair,
+            Some(price),
+            total_qty,
+            &OrderDirection::Sell,
+            &OrderType::Limit,
+        )?;
+        let price = price.ok_or(ExchangeError::NoOrderPriceAvailable)?;
+        if visible_qty <= dec!(0) || visible_qty > total_qty {
+            return Err(ExchangeError::InvalidVisibleQty);
+        }
+
+        let new_order = self.assign_order_id(Order::new_iceberg_limit_sell(
+            pair,
+            price,
+            total_qty,
+            visible_qty,
+        ));
+        self.active_orders
+            .entry(pair.to_string())
+            .or_default()
+            .push(new_order.clone());
+        Ok(new_order)
+    }
+    pub fn place_oco_sell_order(
+        &mut self,
+        pair: &str,
+        take_profit_price: Decimal,
+        stop_price: Decimal,
+        qty: Decimal,
+    ) -> Result<(u64, u64), ExchangeError> {
+        // Both legs sell the same quantity, only one of them can ever fill
+        let (stop_price, qty) = self.validate_order(
+            pair,
+            Some(stop_price),
+            qty,
+            &OrderDirection::Sell,
+            &OrderType::StopLoss,
+        )?;
+        let stop_price = stop_price.ok_or(ExchangeError::NoOrderPriceAvailable)?;
+        let (take_profit_price, qty) = self.validate_order(
+            pair,
+            Some(take_profit_price),
+            qty,
+            &OrderDirection::Sell,
+            &OrderType::Limit,
+        )?;
+        let take_profit_price = take_profit_price.ok_or(ExchangeError::NoOrderPriceAvailable)?;
+        if take_profit_price <= stop_price {
+            return Err(ExchangeError::InvalidOcoPrices);
+        }
+
+        let mut take_profit =
+            self.assign_order_id(Order::new_limit_sell(pair, take_profit_price, qty));
+        let oco_group = Some(take_profit.id);
+        take_profit.oco_group = oco_group;
+        let mut stop_loss = self.assign_order_id(Order::new_stop_loss_sell(pair, stop_price, qty));
+        stop_loss.oco_group = oco_group;
+
+        let order_ids = (take_profit.id, stop_loss.id);
+        let orders = self.active_orders.entry(pair.to_string()).or_default();
+        orders.push(take_profit);
+        orders.push(stop_loss);
+        Ok(order_ids)
+    }
+
+    /// Ticks the feeds whose next bar closes earliest and returns the orders that filled on
+    /// this bar, fully or partially, with their status after the bar.
+    pub fn tick(&mut self) -> Result<Vec<Order>, ExchangeError> {
+        let (_, filled) = self.advance()?;
+        Ok(filled)
+    }
+
+    /// Ticks the feeds with the earliest next bar, returning that bar's close timestamp, or
+    /// `None` when every feed is exhausted, along with the orders that filled.
+    fn advance(&mut self) -> Result<(Option<i64>, Vec<Order>), ExchangeError> {
+        let mut newest_ts: Option<i64> = None;
+        let mut filled: Vec<Order> = vec![];
+        for price_feed in self.price_feeds.values_mut() {
+            price_feed.receive_streamed();
+        }
+        let next_symbols = self.next_symbols();
+        let next_close = next_symbols
+            .first()
+            .and_then(|symbol| self.price_feeds.get(symbol)?.peek())
+            .map(|kline_data| kline_data.get_close_timestamp());
+        // Interest up to this bar is charged on what was borrowed before its fills. Before the
+        // first bar there's no earlier time to accrue from.
+        if let Some(ts) = next_close.filter(|_| self.current_ts > 0) {
+            self.accrue_interest(self.current_ts, ts);
+        }
+        for symbol in next_symbols {
+            if let Some(kline_data) = self.price_feed_next(symbol.as_str()) {
+                newest_ts = newest_ts.max(Some(kline_data.get_close_timestamp()));
+                if let Some(symbol_orders) = self.active_orders.get_mut(&symbol) {
+                    for order in symbol_orders.iter_mut() {
+                        order.bars_rested += 1;
+                    }
+                }
+                for candle in self.tick_candles(&symbol, kline_data) {
+                    for order in self.tick_handle_candle(&symbol, &candle)? {
+                        // An order filling on several sub-bars is reported once, as it ended up
+                        filled.retain(|earlier| earlier.id != order.id);
+                        filled.push(order);
+                    }
+                }
+            }
+        }
+        if let Some(ts) = newest_ts {
+            self.current_ts = self.current_ts.max(ts);
+            filled.extend(self.liquidate()?);
+        }
+        if let (Some(ts), Some(quote)) = (newest_ts, self.equity_quote.as_deref()) {
+            let value = self.portfolio_value(quote, &self.latest_prices())?;
+            self.equity_curve.push((ts, value));
+        }
+        Ok((newest_ts, filled))
+    }
+    /// Charges the margin account interest from `from` to `to` on its quote loan and on what it
+    /// holds short, valued at the latest closes in the quote asset. The interest is paid from
+    /// the quote balance, and whatever the balance can't cover is added to the loan.
+    fn accrue_interest(&mut self, from: i64, to: i64) {
+        let (Some(quote), Some(rate)) = (self.margin_quote(), self.hourly_interest_rate) else {
+            return;
+        };
+        let short_value = self.short_value(&quote, &self.latest_prices());
+        let hours = Decimal::from(to - from) / dec!(3_600_000);
+        let interest = (self.borrowed(&quote) + short_value) * rate * hours;
+        if interest <= dec!(0) {
+            return;
+        }
+        let shortfall = interest - self.get_balance(&quote).max(dec!(0));
+        if shortfall > dec!(0) {
+            self.wallet
+                .add(&Transaction::loan(to, quote.clone(), shortfall));
         }
+        self.wallet.add(&Transaction::interest(to, quote, interest));
+    }
+    /// Checks the margin account after a bar. It's leveraged while it owes its quote asset or
+    /// holds anything short, and when its equity (the portfolio value less the loan) is below
+    /// the maintenance margin ratio of its gross position value, every position priced in the
+    /// quote asset is closed at market on the pair it was valued by, and the loan is repaid from
+    /// the proceeds, as far as they go. A short the quote balance can't buy back is covered by
+    /// borrowing the deficit, which stays on the loan. Returns the closing orders.
+    fn liquidate(&mut self) -> Result<Vec<Order>, ExchangeError> {
+        let (Some(quote), Some(ratio)) = (self.margin_quote(), self.maintenance_margin) else {
+            return Ok(vec![]);
+        };
+        let borrowed = self.borrowed(&quote);
+        let prices = self.latest_prices();
+        let mut positions: Vec<(String, Decimal)> = self
+            .wallet
+            .get_wallets()
+            .iter()
+            .filter(|(asset, balance)| **asset != quote && !balance.is_zero())
+            .filter_map(|(asset, balance)| {
+                let pair = self.feed_pair(asset, &quote)?;
+                let price = prices.get(&pair)?;
+                Some((pair, balance.abs() * price))
+            })
+            .collect();
+        let short = positions.iter().any(|(pair, _)| {
+            matches!(self.get_asset_pair(pair), Ok((base, _)) if self.get_balance(base) < dec!(0))
+        });
+        if borrowed <= dec!(0) && !short {
+            return Ok(vec![]);
+        }
+        let exposure: Decimal = positions.iter().map(|(_, value)| value).sum();
+        let equity = self.portfolio_value(&quote, &prices)? - borrowed;
+        if exposure.is_zero() || equity >= exposure * ratio {
+            return Ok(vec![]);
+        }
+        positions.sort();
+        let mut orders = vec![];
+        for (pair, _) in positions {
+            orders.push(self.close_position_on(&pair, true)?);
+        }
+        let repayment = self.borrowed(&quote).min(self.get_balance(&quote));
+        if repayment > dec!(0) {
+            self.repay(&quote, repayment)?;
+        }
+        Ok(orders)
+    }
+    /// The asset the margin account is kept in: the quote asset of the margin or, without
+    /// margin, the quote of the first pair (by name) trading an asset held short.
+    fn margin_quote(&self) -> Option<String> {
+        if let Some(margin) = &self.margin {
+            return Some(margin.quote.clone());
+        }
+        let mut pairs: Vec<&String> = self.price_feeds.keys().collect();
+        pairs.sort();
+        pairs.into_iter().find_map(|pair| {
+            let (base, quote) = self.get_asset_pair(pair).ok()?;
+            (self.get_balance(base) < dec!(0)).then(|| quote.to_string())
+        })
+    }
+    /// The feeds whose next bar closes earliest, in symbol order. Ticking only those keeps bars
+    /// of different symbols in chronological order, feeds on the same clock advance together.
+    fn next_symbols(&self) -> Vec<String> {
+        let next_close = |price_feed: &PriceFeed| {
+            price_feed
+                .peek()
+                .map(|kline_data| kline_data.get_close_timestamp())
+        };
+        let Some(earliest) = self.price_feeds.values().filter_map(next_close).min() else {
+            return vec![];
+        };
+        let mut symbols: Vec<String> = self
+            .price_feeds
+            .iter()
+            .filter(|(_, price_feed)| next_close(price_feed) == Some(earliest))
+            .map(|(symbol, _)| symbol.clone())
+            .collect();
+        symbols.sort();
+        symbols
+    }
+
+    /// Ticks bar by bar until every feed is exhausted, pausing after each bar for the time
+    /// since the previous one divided by `speed`, so 60.0 plays an hour of 1m bars in a minute.
+    /// Setting the flag from `replay_cancel_handle` stops the replay early. Returns the number
+    /// of ticks that consumed a bar.
+    pub fn replay_realtime(&mut self, speed: f64) -> Result<usize, ExchangeError> {
+        if !speed.is_finite() || speed <= 0.0 {
+            return Err(ExchangeError::InvalidReplaySpeed);
+        }
+        let result = self.replay_bars(speed);
+        self.replay_cancelled.store(false, AtomicOrdering::SeqCst);
+        result
+    }
+    pub fn replay_cancel_handle(&self) -> Arc<AtomicBool> {
+        self.replay_cancelled.clone()
+    }
+    fn replay_bars(&mut self, speed: f64) -> Result<usize, ExchangeError> {
+        let mut bars = 0;
+        let mut previous_ts: Option<i64> = None;
+        while !self.replay_cancelled.load(AtomicOrdering::SeqCst) {
+            let (Some(ts), _) = self.advance()? else {
+                break;
+            };
+            bars += 1;
+            if let Some(previous_ts) = previous_ts {
+                let interval_secs = (ts - previous_ts).max(0) as f64 / 1000.0;
+                self.replay_sleep(Duration::from_secs_f64(interval_secs / speed));
+            }
+            previous_ts = Some(ts);
+        }
+        Ok(bars)
+    }
+    /// Sleeps in short slices so a cancellation is noticed without waiting out a long bar.
+    fn replay_sleep(&self, duration: Duration) {
+        const POLL_INTERVAL: Duration = Duration::from_millis(10);
+        let deadline = Instant::now() + duration;
+        while !self.replay_cancelled.load(AtomicOrdering::SeqCst) {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            thread::sleep((deadline - now).min(POLL_INTERVAL));
+        }
+    }
+
+    /// The transactions booking `fill` takes, any margin loan it needs first, and the asset it
+    /// leaves short, if any.
+    fn fill_booking<'a>(
+        &self,
+        fill: &'a Fill,
+    ) -> Result<(Vec<Transaction>, Option<&'a str>), ExchangeError> {
+        let mut transactions: Vec<Transaction> = self.margin_loan(fill)?.into_iter().collect();
+        transactions.extend(self.fill_transactions(fill)?);
+        Ok((transactions, self.short_sale(fill)?))
+    }
+    /// Books a fill into the wallet, along with any margin loan it needs, failing without side
+    /// effects if it would overdraw it.
+    fn apply_fill(&mut self, fill: Fill) -> Result<(), ExchangeError> {
+        let (transactions, short) = self.fill_booking(&fill)?;
+        let booked = match short {
+            Some(base) => self.wallet.try_add_all_short(&transactions, base),
+            None => self.wallet.try_add_all(&transactions),
+        };
+        booked.map_err(|WalletError::InsufficientBalance(asset)| {
+            ExchangeError::InsufficientFunds(asset)
+        })?;
+        let (_, quote) = self.get_asset_pair(&fill.pair)?;
+        *self.traded_volume.entry(quote.to_string()).or_default() += fill.notional();
+        self.fills.push(fill);
         Ok(())
     }
 
+    /// The candles that decide fills for one bar of `symbol`: the attached sub-bars that fall
+    /// inside it, in order, or the bar itself when there are none.
+    fn tick_candles(&mut self, symbol: &str, kline_data: BinanceKline) -> Vec<BinanceKline> {
+        let subbars: Vec<BinanceKline> = match self.subbars.get_mut(symbol) {
+            Some(sub_feed) => sub_feed
+                .next_until(kline_data.get_close_timestamp())
+                .into_iter()
+                .filter(|subbar| subbar.get_open_timestamp() >= kline_data.get_open_timestamp())
+                .collect(),
+            None => vec![],
+        };
+        if subbars.is_empty() {
+            vec![kline_data]
+        } else {
+            subbars
+        }
+    }
+
+    /// Fills the orders on `symbol` that `kline_data` reaches, booking each fill into the wallet
+    /// as it happens. An order whose fill the wallet can no longer cover, because other fills
+    /// already spent the funds, is cancelled, as is a good-til-date order that expires before
+    /// the candle closes. Returns the orders that filled.
+    fn tick_handle_candle(
+        &mut self,
+        symbol: &str,
+        kline_data: &BinanceKline,
+    ) -> Result<Vec<Order>, ExchangeError> {
+        // With a participation rate, all orders on the pair share that slice of the volume
+        let mut liquidity = self
+            .participation_rate
+            .map(|rate| kline_data.get_volume() * rate);
+        let Some(symbol_orders) = self.active_orders.get(symbol) else {
+            return Ok(vec![]);
+        };
+        let mut filled: Vec<(u64, Decimal)> = vec![];
+        let mut cancelled: Vec<u64> = vec![];
+        let mut filled_oco_groups: Vec<u64> = vec![];
+        let mut activated: Vec<u64> = vec![];
+        let mut reduced: Vec<u64> = vec![];
+        let (timestamp, open, high, low, _) = kline_data.get_ohlc();
+        let close_ts = kline_data.get_close_timestamp();
+        for order in Self::tick_evaluation_order(symbol_orders) {
+            if order.expired_at(close_ts) {
+                cancelled.push(order.id);
+                continue;
+            }
+            if order
+                .oco_group
+                .is_some_and(|group| filled_oco_groups.contains(&group))
+            {
+                continue;
+            }
+            if !order.activated {
+                if Self::tick_trigger_crossed(high, low, &order)? {
+                    activated.push(order.id);
+                }
+                continue;
+            }
+            let prices = (open, high, low);
+            if let Some(mut fill) = self.tick_handle_order(timestamp, prices, &order)? {
+                if self.delay_fill(order.id) {
+                    continue;
+                }
+                if let Some(available) = liquidity {
+                    fill.qty = fill.qty.min(available);
+                }
+                if order.reduce_only {
+                    let reducible = self.reducible_qty(&order.pair, &order.direction)?;
+                    if reducible < fill.qty {
+                        fill.qty = reducible;
+                        reduced.push(order.id);
+                    }
+                }
+                let fill = self.round_fill(fill);
+                if fill.qty <= dec!(0) {
+                    if reduced.contains(&order.id) {
+                        cancelled.push(order.id);
+                    }
+                    continue;
+                }
+                let (_, quote) = self.get_asset_pair(&order.pair)?;
+                let fee_rate = self.fee_rate(quote, &order.order_type);
+                let fill_qty = fill.qty;
+                match self.apply_fill(fill.with_fee_rate(fee_rate)) {
+                    Ok(()) => {}
+                    Err(ExchangeError::InsufficientFunds(_)) => {
+                        cancelled.push(order.id);
+                        continue;
+                    }
+                    Err(error) => return Err(error),
+                }
+                if let Some(available) = liquidity.as_mut() {
+                    *available -= fill_qty;
+                }
+                filled.push((order.id, fill_qty));
+                if let Some(group) = order.oco_group {
+                    filled_oco_groups.push(group);
+                }
+            }
+        }
+
+        let symbol_orders = self.active_orders.get_mut(symbol).unwrap();
+        for order in symbol_orders.iter_mut() {
+            if let Some((_, qty)) = filled.iter().find(|(order_id, _)| *order_id == order.id) {
+                if reduced.contains(&order.id) {
+                    order.qty = order.filled_qty + qty;
+                }
+                order.record_fill(*qty);
+            }
+            if cancelled.contains(&order.id) {
+                order.cancelled();
+            }
+            if activated.contains(&order.id) {
+                order.activated = true;
+            }
+        }
+        let filled_orders: Vec<Order> = symbol_orders
+            .iter()
+            .filter(|order| filled.iter().any(|(order_id, _)| *order_id == order.id))
+            .cloned()
+            .collect();
+        self.filled_orders.extend(
+            filled_orders
+                .iter()
+                .filter(|order| order.status == OrderStatus::Filled)
+                .cloned(),
+        );
+        // Drop filled and cancelled orders along with the remaining legs of their OCO groups
+        let fill_delays = &mut self.fill_delays;
+        symbol_orders.retain(|order| {
+            let resting = order.status != OrderStatus::Filled
+                && order.status != OrderStatus::Cancelled
+                && !order
+                    .oco_group
+                    .is_some_and(|group| filled_oco_groups.contains(&group));
+            if !resting {
+                fill_delays.remove(&order.id);
+            }
+            resting
+        });
+        Ok(filled_orders)
+    }
+
+    /// How much of its base a reduce-only order can trade before the position crosses zero:
+    /// the long held for sells, the short for buys.
+    fn reducible_qty(
+        &self,
+        pair: &str,
+        direction: &OrderDirection,
+    ) -> Result<Decimal, ExchangeError> {
+        let (base, _) = self.get_asset_pair(pair)?;
+        let balance = self.get_balance(base);
+        Ok(match direction {
+            OrderDirection::Sell => balance.max(dec!(0)),
+            OrderDirection::Buy => (-balance).max(dec!(0)),
+        })
+    }
+    /// Counts down the jittered delay of an order that could fill on this bar, drawing the
+    /// delay the first time the order becomes eligible. Returns whether to hold the fill back.
+    fn delay_fill(&mut self, order_id: u64) -> bool {
+        let Some(max_bars) = self.fill_delay_jitter else {
+            return false;
+        };
+        let rng = &mut self.rng;
+        let remaining = self
+            .fill_delays
+            .entry(order_id)
+            .or_insert_with(|| rng.next_up_to(max_bars));
+        if *remaining > 0 {
+            *remaining -= 1;
+            return true;
+        }
+        self.fill_delays.remove(&order_id);
+        false
+    }
+
+    /// Stop losses are evaluated first: when a single candle reaches both legs of an OCO
+    /// pair we can't tell which price traded first, so we assume the worse outcome.
+    fn tick_evaluation_order(orders: &[Order]) -> Vec<Order> {
+        let mut orders = orders.to_vec();
+        orders.sort_by_key(|order| order.order_type != OrderType::StopLoss);
+        orders
+    }
+
     fn tick_handle_order(
-        mut transactions_to_be_added: &mut Vec<Transaction>,
-        symbol: &String,
+        &self,
         timestamp: i64,
-        high: &str,
-        low: &str,
+        (open, high, low): (Decimal, Decimal, Decimal),
         order: &Order,
-    ) -> Result<bool, ExchangeError> {
+    ) -> Result<Option<Fill>, ExchangeError> {
         let order_price = order.price.ok_or(ExchangeError::NoOrderPriceAvailable)?;
-        let (base, quote) = Exchange::get_asset_pair(&symbol)?;
-        match order.direction {
-            OrderDirection::Buy => Self::tick_handle_buy(
-                &mut transactions_to_be_added,
+        let fill_model = &self.fill_model;
+        let condition = &self.fill_condition;
+        let tick_size = self
+            .symbol_filters
+            .get(&order.pair)
+            .map(|filters| filters.tick_size);
+        match (&order.order_type, &order.direction) {
+            (OrderType::StopLoss, OrderDirection::Buy) => Ok(Self::tick_handle_stop_buy(
                 timestamp,
-                low,
+                high,
                 order,
                 order_price,
-                base,
-                quote,
-            ),
-            OrderDirection::Sell => Self::tick_handle_sell(
-                &mut transactions_to_be_added,
+            )),
+            (OrderType::StopLoss, OrderDirection::Sell) => Ok(Self::tick_handle_stop_sell(
                 timestamp,
-                high,
+                low,
                 order,
                 order_price,
-                base,
-                quote,
-            ),
+            )),
+            (_, OrderDirection::Buy) => {
+                let fill_price = Self::tick_fill_price(open, order, order_price, fill_model);
+                Ok(Self::tick_handle_buy(
+                    timestamp,
+                    low,
+                    order,
+                    order_price,
+                    fill_price,
+                    tick_size,
+                    condition,
+                ))
+            }
+            (_, OrderDirection::Sell) => {
+                let fill_price = Self::tick_fill_price(open, order, order_price, fill_model);
+                Ok(Self::tick_handle_sell(
+                    timestamp,
+                    high,
+                    order,
+                    order_price,
+                    fill_price,
+                    tick_size,
+                    condition,
+                ))
+            }
         }
     }
-    fn create_transaction_and_add_to_list(
-        ts: i64,
-        symbol: String,
+
+    fn round_to_tick(price: Decimal, tick_size: Option<Decimal>) -> Decimal {
+        match tick_size {
+            Some(tick_size) if tick_size > dec!(0) => (price / tick_size).round() * tick_size,
+            _ => price,
+        }
+    }
+
+    /// The price a crossed limit order is credited with. Under `FillModel::AtCandle` a bar
+    /// that opens past the limit fills at the open, the better price the gap offered. Taking
+    /// the open is taking liquidity, so post-only orders stay at their limit as makers.
+    fn tick_fill_price(
+        open: Decimal,
+        order: &Order,
         order_price: Decimal,
-        qty: Decimal,
-        transactions_to_be_added: &mut Vec<Transaction>,
-    ) {
-        transactions_to_be_added.push(Transaction::new(ts, symbol, order_price, qty));
+        fill_model: &FillModel,
+    ) -> Decimal {
+        if *fill_model == FillModel::AtLimit || order.post_only {
+            return order_price;
+        }
+        match order.direction {
+            OrderDirection::Buy => open.min(order_price),
+            OrderDirection::Sell => open.max(order_price),
+        }
+    }
+
+    fn tick_trigger_crossed(
+        high: Decimal,
+        low: Decimal,
+        order: &Order,
+    ) -> Result<bool, ExchangeError> {
+        let trigger_price = order
+            .trigger_price
+            .ok_or(ExchangeError::NoOrderPriceAvailable)?;
+        Ok(match order.direction {
+            OrderDirection::Buy => high >= trigger_price,
+            OrderDirection::Sell => low <= trigger_price,
+        })
+    }
+
+    /// Turns a fill into the pair of wallet transactions moving the base and quote assets.
+    /// The fee is taken out of the asset received.
+    fn fill_transactions(&self, fill: &Fill) -> Result<Vec<Transaction>, ExchangeError> {
+        let (base, quote) = self.get_asset_pair(&fill.pair)?;
+        let (base_qty, quote_qty, fee_asset) = match fill.direction {
+            OrderDirection::Buy => (fill.qty, fill.notional() * dec!(-1), base),
+            OrderDirection::Sell => (fill.qty * dec!(-1), fill.notional(), quote),
+        };
+        let mut transactions = vec![
+            Transaction::new(fill.ts, base.to_string(), fill.price, base_qty),
+            Transaction::new(fill.ts, quote.to_string(), fill.price, quote_qty),
+        ];
+        if !fill.fee.is_zero() {
+            transactions.push(Transaction::fee(
+                fill.ts,
+                fee_asset.to_string(),
+                fill.price,
+                fill.fee,
+            ));
+        }
+        let transactions = transactions
+            .into_iter()
+            .map(|tx| tx.with_fill(fill.order_id, base))
+            .collect();
+        Ok(transactions)
     }
 
     fn tick_handle_sell(
-        transactions_to_be_added: &mut Vec<Transaction>,
         timestamp: i64,
-        high_price_str: &str,
+        high: Decimal,
         order: &Order,
         order_price: Decimal,
-        base: &str,
-        quote: &str,
-    ) -> Result<bool, ExchangeError> {
-        let decimal_high =
-            Decimal::from_str_exact(high_price_str).map_err(|_| ExchangeError::InvalidPrice)?;
-        if decimal_high > order_price {
-            Self::create_transaction_and_add_to_list(
-                timestamp,
-                base.to_string(),
-                order_price,
-                order.qty * dec!(-1),
-                transactions_to_be_added,
-            );
-            Self::create_transaction_and_add_to_list(
-                timestamp,
-                quote.to_string(),
-                order_price,
-                order.qty * order_price,
-                transactions_to_be_added,
-            );
-            return Ok(true);
-        }
-        Ok(false)
+        fill_price: Decimal,
+        tick_size: Option<Decimal>,
+        condition: &FillCondition,
+    ) -> Option<Fill> {
+        condition
+            .reached(
+                Self::round_to_tick(high, tick_size),
+                Self::round_to_tick(order_price, tick_size),
+                &order.direction,
+            )
+            .then(|| Fill::new(timestamp, order, fill_price, order.active_qty()))
     }
 
     fn tick_handle_buy(
-        transactions_to_be_added: &mut Vec<Transaction>,
         timestamp: i64,
-        low_price_str: &str,
+        low: Decimal,
         order: &Order,
         order_price: Decimal,
-        base: &str,
-        quote: &str,
-    ) -> Result<bool, ExchangeError> {
-        let decimal_low =
-            Decimal::from_str_exact(low_price_str).map_err(|_| ExchangeError::InvalidPrice)?;
+        fill_price: Decimal,
+        tick_size: Option<Decimal>,
+        condition: &FillCondition,
+    ) -> Option<Fill> {
+        condition
+            .reached(
+                Self::round_to_tick(low, tick_size),
+                Self::round_to_tick(order_price, tick_size),
+                &order.direction,
+            )
+            .then(|| Fill::new(timestamp, order, fill_price, order.active_qty()))
+    }
 
-        if decimal_low < order_price {
-            Self::create_transaction_and_add_to_list(
-                timestamp,
-                base.to_string(),
-                order_price,
-                order.qty,
-                transactions_to_be_added,
-            );
-            Self::create_transaction_and_add_to_list(
-                timestamp,
-                quote.to_string(),
-                order_price,
-                (order.qty * order_price) * dec!(-1),
-                transactions_to_be_added,
-            );
-            return Ok(true);
+    fn tick_handle_stop_sell(
+        timestamp: i64,
+        low: Decimal,
+        order: &Order,
+        stop_price: Decimal,
+    ) -> Option<Fill> {
+        (low <= stop_price).then(|| Fill::new(timestamp, order, stop_price, order.active_qty()))
+    }
+
+    fn tick_handle_stop_buy(
+        timestamp: i64,
+        high: Decimal,
+        order: &Order,
+        stop_price: Decimal,
+    ) -> Option<Fill> {
+        (high >= stop_price).then(|| Fill::new(timestamp, order, stop_price, order.active_qty()))
+    }
+
+    /// Writes every fill as a CSV row with the fee charged and the balances of both assets of
+    /// the pair right after the fill was booked.
+    pub fn export_statement_csv(&self, path: &str) -> Result<(), TradeSimError> {
+        let mut f = File::create(path)?;
+        writeln!(
+            f,
+            "timestamp,pair,side,qty,price,fee,fee_asset,base_balance,quote_balance"
+        )?;
+        let mut balances: HashMap<String, Decimal> = HashMap::new();
+        let mut transactions = self.wallet.get_transactions().iter();
+        for fill in &self.fills {
+            let (base, quote) = self.get_asset_pair(&fill.pair)?;
+            // Replay the ledger up to and including this fill's legs and fee
+            for leg in self.fill_transactions(fill)? {
+                for tx in transactions.by_ref() {
+                    *balances.entry(tx.get_symbol().clone()).or_default() += tx.get_qty();
+                    if *tx == leg {
+                        break;
+                    }
+                }
+            }
+            let (side, fee_asset) = match fill.direction {
+                OrderDirection::Buy => ("BUY", base),
+                OrderDirection::Sell => ("SELL", quote),
+            };
+            let balance = |asset: &str| balances.get(asset).copied().unwrap_or_default();
+            writeln!(
+                f,
+                "{},{},{},{},{},{},{},{},{}",
+                fill.ts,
+                fill.pair,
+                side,
+                fill.qty,
+                fill.price,
+                fill.fee,
+                fee_asset,
+                balance(base),
+                balance(quote)
+            )?;
+        }
+        Ok(())
+    }
+    /// Total fees paid on `quote` pairs as a fraction of the traded notional.
+    pub fn fee_drag(&self, quote: &str) -> Decimal {
+        let mut fees = dec!(0);
+        let mut turnover = dec!(0);
+        for fill in &self.fills {
+            if !matches!(self.get_asset_pair(&fill.pair), Ok((_, q)) if q == quote) {
+                continue;
+            }
+            fees += fill.fee_in_quote();
+            turnover += fill.notional();
+        }
+        if turnover.is_zero() {
+            return dec!(0);
         }
-        Ok(false)
+        fees / turnover
     }
 
-    pub fn get_asset_pair(pair: &str) -> Result<(&str, &str), ExchangeError> {
-        const QUOTE_LIST: [&str; 32] = [
-            "AUD", "BIDR", "BKRW", "BNB", "BRL", "BTC", "BUSD", "BVND", "DAI", "DOGE", "DOT",
-            "ETH", "EUR", "GBP", "IDRT", "NGN", "PAX", "PLN", "RON", "RUB", "TRX", "TRY", "TUSD",
-            "UAH", "USDC", "USDP", "USDS", "USDT", "UST", "VAI", "XRP", "ZAR",
-        ];
-        for quote in QUOTE_LIST {
-            if pair.ends_with(quote) {
-                let base = pair
-                    .strip_suffix(quote)
-                    .ok_or(ExchangeError::FailedToObtainAssetPair)?;
-                return Ok((base, quote));
+    /// The percentage the price has to rise between a market buy and a market sell of `pair`
+    /// for the round trip to break even after taker fees on both legs. Each fee is taken from
+    /// the asset received, so they compound: 1 / (1 - taker)^2 - 1. The taker rate is the one
+    /// reached by the volume traded in the pair's quote.
+    pub fn fee_breakeven_pct(&self, pair: &str) -> Decimal {
+        let quote = self.get_asset_pair(pair).map(|(_, quote)| quote);
+        let taker = self.fee_rate(quote.unwrap_or_default(), &OrderType::Market);
+        let kept = (dec!(1) - taker) * (dec!(1) - taker);
+        if kept <= dec!(0) {
+            return Decimal::MAX;
+        }
+        (dec!(1) / kept - dec!(1)) * dec!(100)
+    }
+    /// Turnover of fills on `quote` pairs divided by the number of UTC days from the first of
+    /// those fills to the last, both included.
+    pub fn avg_daily_volume(&self, quote: &str) -> Decimal {
+        const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+        let fills: Vec<&Fill> = self
+            .fills
+            .iter()
+            .filter(|fill| matches!(self.get_asset_pair(&fill.pair), Ok((_, q)) if q == quote))
+            .collect();
+        let days = fills.iter().map(|fill| fill.ts.div_euclid(DAY_MS));
+        let (Some(first_day), Some(last_day)) = (days.clone().min(), days.max()) else {
+            return dec!(0);
+        };
+        let turnover: Decimal = fills.iter().map(|fill| fill.notional()).sum();
+        turnover / Decimal::from(last_day - first_day + 1)
+    }
+
+    /// Splits `pair` into its base and quote asset. Pairs written with a separator, "BTC/USDT"
+    /// or "BTC-USDT", are split on it, concatenated ones by matching the quote against
+    /// `quote_assets`.
+    pub fn get_asset_pair<'a>(&self, pair: &'a str) -> Result<(&'a str, &'a str), ExchangeError> {
+        if let Some((base, quote)) = pair.split_once(['/', '-']) {
+            if base.is_empty() || quote.is_empty() || quote.contains(['/', '-']) {
+                return Err(ExchangeError::FailedToObtainAssetPair(pair.to_string()));
             }
+            return Ok((base, quote));
+        }
+        // Prefer the longest quote so e.g. TUSD isn't mistaken for a shorter suffix, and
+        // never take the whole pair as the quote
+        let quote = self
+            .quote_assets
+            .iter()
+            .filter(|quote| pair.len() > quote.len() && pair.ends_with(quote.as_str()))
+            .max_by_key(|quote| quote.len());
+        if let Some(quote) = quote {
+            return Ok(pair.split_at(pair.len() - quote.len()));
         }
-        return Err(ExchangeError::FailedToObtainAssetPair);
+        Err(ExchangeError::FailedToObtainAssetPair(pair.to_string()))
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::exchange::kline_stream::KlineSource;
     use crate::exchange::order::OrderStatus;
     use crate::exchange::price_feed::BinanceKline;
+    use crate::exchange::transaction::TransactionKind;
+    use crate::test_util::{kline, ohlc_kline};
+    use std::error::Error;
 
     #[test]
     fn test_initialize_exchange_with_capital() {
@@ -367,7 +2205,7 @@ mod test {
         assert_eq!(wallets.get("USDC"), Some(&dec!(12_000)));
 
         let mut ex = Exchange::new();
-            ex.with_capital(vec![
+        ex.with_capital(vec![
             (String::from("BTC"), dec!(3)),
             (String::from("ETH"), dec!(40)),
             (String::from("USDC"), dec!(3_000)),
@@ -381,19 +2219,19 @@ mod test {
     #[test]
     fn test_place_limit_buy_order() {
         let mut ex = Exchange::new();
-            ex.with_capital(vec![(String::from("BTC"), dec!(12.0))]);
+        ex.with_capital(vec![(String::from("BTC"), dec!(12.0))]);
         let wallets = ex.get_wallet();
         assert_eq!(wallets.get("BTC"), Some(&dec!(12.0)));
 
         let place_order_pair = "ETHBTC";
         let place_order_price = dec!(0.0093);
         let place_order_qty = dec!(1);
-        let order = ex
+        let _order = ex
             .place_limit_buy_order(place_order_pair, place_order_price, place_order_qty)
             .unwrap();
         let order_map = ex.get_orders();
         if let Some((_, orders)) = order_map.iter().next() {
-            if let Some(order) = orders.get(0) {
+            if let Some(order) = orders.first() {
                 assert_eq!(order.order_type, OrderType::Limit);
                 assert_eq!(order.direction, OrderDirection::Buy);
                 assert_eq!(order.status, OrderStatus::Pending);
@@ -406,25 +2244,44 @@ mod test {
 
     #[test]
     fn test_extract_quote_and_base() {
-        let result = Exchange::get_asset_pair("BTCUSDT").unwrap();
+        let exchange = Exchange::new();
+        let result = exchange.get_asset_pair("BTCUSDT").unwrap();
         assert_eq!(result, ("BTC", "USDT"));
-        let result = Exchange::get_asset_pair("ETHBTC").unwrap();
+        let result = exchange.get_asset_pair("ETHBTC").unwrap();
         assert_eq!(result, ("ETH", "BTC"));
-        let result = Exchange::get_asset_pair("LINKBNB").unwrap();
+        let result = exchange.get_asset_pair("LINKBNB").unwrap();
         assert_eq!(result, ("LINK", "BNB"));
-        let result = Exchange::get_asset_pair("SANDETH").unwrap();
+        let result = exchange.get_asset_pair("SANDETH").unwrap();
         assert_eq!(result, ("SAND", "ETH"));
     }
 
+    #[test]
+    fn test_extract_quote_and_base_with_overlapping_suffixes() {
+        let pairs = [
+            ("BTCUSDT", ("BTC", "USDT")),
+            ("ETHBTC", ("ETH", "BTC")),
+            ("DOGEUSDT", ("DOGE", "USDT")),
+            ("BTCDOWNUSDT", ("BTCDOWN", "USDT")),
+            ("BTCTUSD", ("BTC", "TUSD")),
+            ("SHIBDOGE", ("SHIB", "DOGE")),
+        ];
+        let exchange = Exchange::new();
+        for (pair, split) in pairs {
+            assert_eq!(exchange.get_asset_pair(pair).unwrap(), split);
+        }
+        assert!(exchange.get_asset_pair("USDT").is_err());
+        assert!(exchange.get_asset_pair("BTCXYZ").is_err());
+    }
+
     #[test]
     fn test_tick_with_limit_buy() {
         let custom_kline_data = vec![BinanceKline::new(
             1626578400000,
-            "1.0000000",
-            "2.0000000",
-            "0.08000000",
-            "0.15000000",
-            "5000.00000000",
+            dec!(1.0000000),
+            dec!(2.0000000),
+            dec!(0.08000000),
+            dec!(0.15000000),
+            dec!(5000.00000000),
             1626578500000,
             "750.00000000",
             10,
@@ -437,9 +2294,9 @@ mod test {
         price_feed.add_price_data(custom_kline_data);
         let mut exchange = Exchange::new();
         exchange.with_capital(vec![
-                ("BTC".to_string(), dec!(1.0)),
-                ("USDT".to_string(), dec!(1.0)),
-            ]);
+            ("BTC".to_string(), dec!(1.0)),
+            ("USDT".to_string(), dec!(1.0)),
+        ]);
         exchange.add_price_feed("BTCUSDT".to_string(), price_feed);
 
         // Place a limit buy order
@@ -448,8 +2305,8 @@ mod test {
             .unwrap();
 
         // Call the tick() function
-        let result = exchange.tick();
-        assert!(result.is_ok());
+        let filled = exchange.tick().unwrap();
+        assert_eq!(filled.len(), 1);
 
         let wallets = exchange.get_wallet();
         assert_eq!(wallets["BTC"], dec!(2.0));
@@ -459,11 +2316,11 @@ mod test {
     fn test_tick_with_limit_sell() {
         let custom_kline_data = vec![BinanceKline::new(
             1626578400000,
-            "2.90000000",
-            "3.0000000",
-            "2.08000000",
-            "2.815000000",
-            "5000.00000000",
+            dec!(2.90000000),
+            dec!(3.0000000),
+            dec!(2.08000000),
+            dec!(2.815000000),
+            dec!(5000.00000000),
             1626578500000,
             "750.00000000",
             10,
@@ -475,7 +2332,8 @@ mod test {
         let mut price_feed = PriceFeed::new();
         price_feed.add_price_data(custom_kline_data);
         let mut exchange = Exchange::new();
-        exchange.with_capital(vec![
+        exchange
+            .with_capital(vec![
                 ("BTC".to_string(), dec!(1.0)),
                 ("USDT".to_string(), dec!(1.0)),
             ])
@@ -487,11 +2345,2895 @@ mod test {
             .unwrap();
 
         // Call the tick() function
-        let result = exchange.tick();
-        assert!(result.is_ok());
+        let filled = exchange.tick().unwrap();
+        assert_eq!(filled.len(), 1);
 
         let wallets = exchange.get_wallet();
         assert_eq!(wallets["BTC"], dec!(0.0));
         assert_eq!(wallets["USDT"], dec!(3.0));
     }
+
+    fn btc_usdt_exchange(klines: Vec<BinanceKline>) -> Exchange {
+        let mut price_feed = PriceFeed::new();
+        price_feed.add_price_data(klines);
+        let mut exchange = Exchange::new();
+        exchange
+            .with_capital(vec![("BTC".to_string(), dec!(1.0))])
+            .add_price_feed("BTCUSDT".to_string(), price_feed);
+        exchange
+    }
+
+    #[test]
+    fn test_place_oco_sell_order_links_both_legs() {
+        let mut exchange = btc_usdt_exchange(vec![]);
+        let (take_profit_id, stop_id) = exchange
+            .place_oco_sell_order("BTCUSDT", dec!(3), dec!(2), dec!(1))
+            .unwrap();
+
+        let orders = &exchange.get_orders()["BTCUSDT"];
+        assert_eq!(orders.len(), 2);
+        let take_profit = orders.iter().find(|o| o.id == take_profit_id).unwrap();
+        let stop = orders.iter().find(|o| o.id == stop_id).unwrap();
+        assert_eq!(take_profit.order_type, OrderType::Limit);
+        assert_eq!(stop.order_type, OrderType::StopLoss);
+        assert_eq!(take_profit.oco_group, Some(take_profit_id));
+        assert_eq!(take_profit.oco_group, stop.oco_group);
+    }
+
+    #[test]
+    fn test_oco_legs_are_held_to_the_symbol_filters() {
+        let mut exchange = btc_usdt_exchange(vec![]);
+        exchange.with_symbol_filters("BTCUSDT", SymbolFilters::new(dec!(1), dec!(0.1), dec!(0.5)));
+        let (take_profit_id, stop_id) = exchange
+            .place_oco_sell_order("BTCUSDT", dec!(3.2), dec!(2.2), dec!(0.99))
+            .unwrap();
+        let take_profit = exchange.get_order(take_profit_id).unwrap();
+        assert_eq!(
+            (take_profit.price, take_profit.qty),
+            (Some(dec!(3.5)), dec!(0.9))
+        );
+        let stop = exchange.get_order(stop_id).unwrap();
+        assert_eq!((stop.price, stop.qty), (Some(dec!(2.5)), dec!(0.9)));
+
+        // The stop leg is worth less than the minimum notional
+        assert!(matches!(
+            exchange.place_oco_sell_order("BTCUSDT", dec!(3), dec!(2), dec!(0.4)),
+            Err(ExchangeError::BelowMinNotional(_))
+        ));
+    }
+
+    #[test]
+    fn test_place_oco_sell_order_rejects_invalid_prices() {
+        let mut exchange = btc_usdt_exchange(vec![]);
+        let result = exchange.place_oco_sell_order("BTCUSDT", dec!(2), dec!(3), dec!(1));
+        assert!(matches!(result, Err(ExchangeError::InvalidOcoPrices)));
+        let result = exchange.place_oco_sell_order("BTCUSDT", dec!(3), dec!(2), dec!(2));
+        assert!(matches!(result, Err(ExchangeError::InsufficientFunds(_))));
+        assert!(exchange.get_orders().is_empty());
+    }
+
+    #[test]
+    fn test_oco_take_profit_cancels_stop() {
+        let mut exchange =
+            btc_usdt_exchange(vec![ohlc_kline(dec!(2.5), dec!(3.5), dec!(2.4), dec!(3.2))]);
+        exchange
+            .place_oco_sell_order("BTCUSDT", dec!(3), dec!(2), dec!(1))
+            .unwrap();
+
+        exchange.tick().unwrap();
+
+        let wallets = exchange.get_wallet();
+        assert_eq!(wallets["BTC"], dec!(0.0));
+        assert_eq!(wallets["USDT"], dec!(3));
+        assert!(exchange.get_orders()["BTCUSDT"].is_empty());
+    }
+
+    #[test]
+    fn test_oco_stop_cancels_take_profit() {
+        let mut exchange = btc_usdt_exchange(vec![
+            ohlc_kline(dec!(2.5), dec!(2.6), dec!(1.5), dec!(1.8)),
+            ohlc_kline(dec!(1.8), dec!(3.5), dec!(1.8), dec!(3.2)),
+        ]);
+        exchange
+            .place_oco_sell_order("BTCUSDT", dec!(3), dec!(2), dec!(1))
+            .unwrap();
+
+        exchange.tick().unwrap();
+        assert!(exchange.get_orders()["BTCUSDT"].is_empty());
+
+        // The take profit would have filled here, but it was cancelled with the stop
+        exchange.tick().unwrap();
+        let wallets = exchange.get_wallet();
+        assert_eq!(wallets["BTC"], dec!(0.0));
+        assert_eq!(wallets["USDT"], dec!(2));
+    }
+
+    #[test]
+    fn test_oco_candle_reaching_both_legs_prefers_stop() {
+        let mut exchange =
+            btc_usdt_exchange(vec![ohlc_kline(dec!(2.5), dec!(3.5), dec!(1.5), dec!(2.5))]);
+        let (_, stop_id) = exchange
+            .place_oco_sell_order("BTCUSDT", dec!(3), dec!(2), dec!(1))
+            .unwrap();
+        assert_eq!(exchange.get_orders()["BTCUSDT"].last().unwrap().id, stop_id);
+
+        exchange.tick().unwrap();
+
+        let wallets = exchange.get_wallet();
+        assert_eq!(wallets["BTC"], dec!(0.0));
+        assert_eq!(wallets["USDT"], dec!(2));
+        assert_eq!(exchange.get_transactions().len(), 3);
+        assert!(exchange.get_orders()["BTCUSDT"].is_empty());
+    }
+
+    #[test]
+    fn test_fee_drag_is_fees_over_turnover() {
+        let mut exchange =
+            btc_usdt_exchange(vec![ohlc_kline(dec!(2.5), dec!(3.5), dec!(1.5), dec!(2.5))]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .with_fees(FeeSchedule::new(dec!(0.001), dec!(0.002)));
+        assert_eq!(exchange.fee_drag("USDT"), dec!(0));
+
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+        exchange
+            .place_limit_sell_order("BTCUSDT", dec!(3), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+
+        assert_eq!(exchange.get_fills().len(), 2);
+        // 0.001 BTC at 2 on the buy and 0.003 USDT on the sell, over 5 USDT traded
+        assert_eq!(exchange.fee_drag("USDT"), dec!(0.001));
+        assert_eq!(exchange.fee_drag("BTC"), dec!(0));
+    }
+
+    #[test]
+    fn test_iceberg_sell_fills_one_slice_per_tick() {
+        let mut exchange = btc_usdt_exchange(vec![
+            ohlc_kline(dec!(2.5), dec!(3), dec!(2.4), dec!(2.8)),
+            ohlc_kline(dec!(2.8), dec!(3), dec!(2.6), dec!(2.9)),
+            ohlc_kline(dec!(2.9), dec!(3), dec!(2.7), dec!(2.9)),
+        ]);
+        exchange.with_capital(vec![("BTC".to_string(), dec!(2))]);
+        let order = exchange
+            .place_iceberg_limit_sell_order("BTCUSDT", dec!(2), dec!(3), dec!(1))
+            .unwrap();
+        assert_eq!(order.visible_qty, Some(dec!(1)));
+
+        exchange.tick().unwrap();
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(2));
+        assert_eq!(exchange.get_wallet()["USDT"], dec!(2));
+        let resting = &exchange.get_orders()["BTCUSDT"][0];
+        assert_eq!(resting.filled_qty, dec!(1));
+        assert_eq!(resting.status, OrderStatus::PartiallyFilled(33));
+
+        exchange.tick().unwrap();
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(1));
+        assert_eq!(exchange.get_wallet()["USDT"], dec!(4));
+        assert_eq!(exchange.get_orders()["BTCUSDT"].len(), 1);
+
+        exchange.tick().unwrap();
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(0));
+        assert_eq!(exchange.get_wallet()["USDT"], dec!(6));
+        assert!(exchange.get_orders()["BTCUSDT"].is_empty());
+        assert_eq!(exchange.get_fills().len(), 3);
+    }
+
+    #[test]
+    fn test_iceberg_last_slice_is_the_remainder() {
+        let mut exchange = btc_usdt_exchange(vec![
+            ohlc_kline(dec!(2.5), dec!(3), dec!(2.4), dec!(2.8)),
+            ohlc_kline(dec!(2.8), dec!(3), dec!(2.6), dec!(2.9)),
+        ]);
+        exchange
+            .place_iceberg_limit_sell_order("BTCUSDT", dec!(2), dec!(1), dec!(0.6))
+            .unwrap();
+
+        exchange.tick().unwrap();
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(0.4));
+        exchange.tick().unwrap();
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(0));
+        assert_eq!(exchange.get_fills()[1].qty, dec!(0.4));
+        assert!(exchange.get_orders()["BTCUSDT"].is_empty());
+    }
+
+    #[test]
+    fn test_iceberg_rejects_invalid_visible_qty() {
+        let mut exchange = btc_usdt_exchange(vec![]);
+        let result = exchange.place_iceberg_limit_sell_order("BTCUSDT", dec!(2), dec!(1), dec!(0));
+        assert!(matches!(result, Err(ExchangeError::InvalidVisibleQty)));
+        let result = exchange.place_iceberg_limit_sell_order("BTCUSDT", dec!(2), dec!(1), dec!(2));
+        assert!(matches!(result, Err(ExchangeError::InvalidVisibleQty)));
+    }
+
+    #[test]
+    fn test_iceberg_is_held_to_the_symbol_filters() {
+        let mut exchange = btc_usdt_exchange(vec![]);
+        exchange.with_symbol_filters("BTCUSDT", SymbolFilters::new(dec!(1), dec!(0.1), dec!(0.5)));
+        let order = exchange
+            .place_iceberg_limit_sell_order("BTCUSDT", dec!(2.2), dec!(0.99), dec!(0.3))
+            .unwrap();
+        assert_eq!((order.price, order.qty), (Some(dec!(2.5)), dec!(0.9)));
+        assert_eq!(exchange.get_order(order.id).unwrap().qty, dec!(0.9));
+
+        let result =
+            exchange.place_iceberg_limit_sell_order("BTCUSDT", dec!(2), dec!(0.4), dec!(0.1));
+        assert!(matches!(result, Err(ExchangeError::BelowMinNotional(_))));
+    }
+
+    fn timed_kline(open_ts: i64, close_ts: i64, high: Decimal, low: Decimal) -> BinanceKline {
+        kline(
+            open_ts,
+            close_ts,
+            [dec!(2.5), high, low, dec!(2.5)],
+            dec!(5000),
+        )
+    }
+
+    fn subbar_exchange(subbars: Vec<BinanceKline>) -> Exchange {
+        let mut exchange = btc_usdt_exchange(vec![timed_kline(0, 119_999, dec!(3.5), dec!(1.5))]);
+        let mut sub_feed = PriceFeed::new();
+        sub_feed.add_price_data(subbars);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .with_subbars("BTCUSDT".to_string(), sub_feed);
+        exchange
+    }
+
+    #[test]
+    fn test_subbars_decide_which_crossing_order_fills_first() {
+        let dip_then_rally = vec![
+            timed_kline(0, 59_999, dec!(2.6), dec!(1.5)),
+            timed_kline(60_000, 119_999, dec!(3.5), dec!(2.4)),
+        ];
+        let mut exchange = subbar_exchange(dip_then_rally);
+        let buy = exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+        let sell = exchange
+            .place_limit_sell_order("BTCUSDT", dec!(3), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+        let fills = exchange.get_fills();
+        assert_eq!(fills.len(), 2);
+        assert_eq!((fills[0].order_id, fills[0].ts), (buy.id, 59_999));
+        assert_eq!((fills[1].order_id, fills[1].ts), (sell.id, 119_999));
+
+        let rally_then_dip = vec![
+            timed_kline(0, 59_999, dec!(3.5), dec!(2.4)),
+            timed_kline(60_000, 119_999, dec!(2.6), dec!(1.5)),
+        ];
+        let mut exchange = subbar_exchange(rally_then_dip);
+        let buy = exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+        let sell = exchange
+            .place_limit_sell_order("BTCUSDT", dec!(3), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+        let fills = exchange.get_fills();
+        assert_eq!(fills.len(), 2);
+        assert_eq!(fills[0].order_id, sell.id);
+        assert_eq!(fills[1].order_id, buy.id);
+    }
+
+    #[test]
+    fn test_subbars_resolve_oco_legs_in_traded_order() {
+        let mut exchange = subbar_exchange(vec![
+            timed_kline(0, 59_999, dec!(3.5), dec!(2.4)),
+            timed_kline(60_000, 119_999, dec!(2.6), dec!(1.5)),
+        ]);
+        exchange
+            .place_oco_sell_order("BTCUSDT", dec!(3), dec!(2), dec!(1))
+            .unwrap();
+
+        exchange.tick().unwrap();
+
+        // Without sub-bars the stop would be assumed to trade first
+        let wallets = exchange.get_wallet();
+        assert_eq!(wallets["BTC"], dec!(0));
+        assert_eq!(wallets["USDT"], dec!(13));
+        assert!(exchange.get_orders()["BTCUSDT"].is_empty());
+    }
+
+    fn consumed_candle_exchange(volume: Decimal) -> Exchange {
+        let mut exchange = btc_usdt_exchange(vec![volume_kline(volume)]);
+        exchange.with_capital(vec![("USDT".to_string(), dec!(10))]);
+        exchange.tick().unwrap();
+        exchange
+    }
+
+    fn place_buy(exchange: &mut Exchange, price: Decimal, time_in_force: TimeInForce) -> Order {
+        exchange
+            .place_order_with_time_in_force(
+                "BTCUSDT",
+                Some(price),
+                dec!(1),
+                OrderDirection::Buy,
+                OrderType::Limit,
+                time_in_force,
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_ioc_order_against_current_candle() {
+        // Fully satisfied
+        let mut exchange = consumed_candle_exchange(dec!(5000));
+        let order = place_buy(&mut exchange, dec!(2), TimeInForce::IOC);
+        assert_eq!(order.status, OrderStatus::Filled);
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(2));
+        assert_eq!(exchange.get_wallet()["USDT"], dec!(8));
+        assert!(exchange.get_orders().is_empty());
+
+        // Partially satisfied, the rest is cancelled
+        let mut exchange = consumed_candle_exchange(dec!(0.4));
+        let order = place_buy(&mut exchange, dec!(2), TimeInForce::IOC);
+        assert_eq!(order.status, OrderStatus::PartiallyFilled(40));
+        assert_eq!(order.filled_qty, dec!(0.4));
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(1.4));
+        assert_eq!(exchange.get_wallet()["USDT"], dec!(9.2));
+        assert!(exchange.get_orders().is_empty());
+
+        // Not satisfied
+        let mut exchange = consumed_candle_exchange(dec!(5000));
+        let order = place_buy(&mut exchange, dec!(1), TimeInForce::IOC);
+        assert_eq!(order.status, OrderStatus::Cancelled);
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(1));
+        assert!(exchange.get_fills().is_empty());
+        assert!(exchange.get_orders().is_empty());
+    }
+
+    #[test]
+    fn test_fok_order_against_current_candle() {
+        // Fully satisfied
+        let mut exchange = consumed_candle_exchange(dec!(5000));
+        let order = place_buy(&mut exchange, dec!(2), TimeInForce::FOK);
+        assert_eq!(order.status, OrderStatus::Filled);
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(2));
+        assert_eq!(exchange.get_wallet()["USDT"], dec!(8));
+
+        // Partially satisfied is killed entirely
+        let mut exchange = consumed_candle_exchange(dec!(0.4));
+        let order = place_buy(&mut exchange, dec!(2), TimeInForce::FOK);
+        assert_eq!(order.status, OrderStatus::Cancelled);
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(1));
+        assert_eq!(exchange.get_wallet()["USDT"], dec!(10));
+        assert!(exchange.get_fills().is_empty());
+
+        // Not satisfied
+        let mut exchange = consumed_candle_exchange(dec!(5000));
+        let order = place_buy(&mut exchange, dec!(1), TimeInForce::FOK);
+        assert_eq!(order.status, OrderStatus::Cancelled);
+        assert!(exchange.get_fills().is_empty());
+        assert!(exchange.get_orders().is_empty());
+    }
+
+    #[test]
+    fn test_gtc_order_rests_regardless_of_current_candle() {
+        for (volume, price) in [
+            (dec!(5000), dec!(2)),
+            (dec!(0.4), dec!(2)),
+            (dec!(5000), dec!(1)),
+        ] {
+            let mut exchange = consumed_candle_exchange(volume);
+            let order = place_buy(&mut exchange, price, TimeInForce::GTC);
+            assert_eq!(order.status, OrderStatus::Pending);
+            assert_eq!(exchange.get_orders()["BTCUSDT"].len(), 1);
+            assert!(exchange.get_fills().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_ioc_order_without_consumed_candle_is_cancelled() {
+        let mut exchange = btc_usdt_exchange(vec![]);
+        exchange.with_capital(vec![("USDT".to_string(), dec!(10))]);
+        let order = place_buy(&mut exchange, dec!(2), TimeInForce::IOC);
+        assert_eq!(order.status, OrderStatus::Cancelled);
+        assert!(exchange.get_orders().is_empty());
+    }
+
+    #[test]
+    fn test_stale_feeds_reports_lagging_feed() {
+        let mut btc_feed = PriceFeed::new();
+        btc_feed.add_price_data(vec![timed_kline(0, 59_999, dec!(3), dec!(2))]);
+        let mut eth_feed = PriceFeed::new();
+        eth_feed.add_price_data(vec![
+            timed_kline(0, 59_999, dec!(3), dec!(2)),
+            timed_kline(60_000, 119_999, dec!(3), dec!(2)),
+            timed_kline(120_000, 179_999, dec!(3), dec!(2)),
+        ]);
+        let mut exchange = Exchange::new();
+        exchange
+            .add_price_feed("BTCUSDT".to_string(), btc_feed)
+            .add_price_feed("ETHUSDT".to_string(), eth_feed);
+        assert!(exchange.stale_feeds(0).is_empty());
+
+        exchange.tick().unwrap();
+        assert!(exchange.stale_feeds(0).is_empty());
+
+        exchange.tick().unwrap();
+        exchange.tick().unwrap();
+        assert_eq!(exchange.stale_feeds(60_000), vec!["BTCUSDT".to_string()]);
+        assert!(exchange.stale_feeds(120_000).is_empty());
+    }
+
+    #[test]
+    fn test_maker_fee_is_deducted_from_received_asset() {
+        let mut exchange =
+            btc_usdt_exchange(vec![ohlc_kline(dec!(2.5), dec!(3.5), dec!(1.5), dec!(2.5))]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .with_fees(FeeSchedule::new(dec!(0.001), dec!(0.002)));
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(1.999));
+        assert_eq!(exchange.get_wallet()["USDT"], dec!(8));
+        assert_eq!(exchange.get_fills()[0].fee, dec!(0.001));
+    }
+
+    #[test]
+    fn test_maker_fee_on_sell_is_charged_in_quote() {
+        let mut exchange =
+            btc_usdt_exchange(vec![ohlc_kline(dec!(2.5), dec!(3.5), dec!(1.5), dec!(2.5))]);
+        exchange.with_fees(FeeSchedule::new(dec!(0.001), dec!(0.002)));
+        exchange
+            .place_limit_sell_order("BTCUSDT", dec!(3), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(0));
+        assert_eq!(exchange.get_wallet()["USDT"], dec!(2.997));
+    }
+
+    #[test]
+    fn test_taker_fee_on_stop_and_immediate_fills() {
+        let mut exchange =
+            btc_usdt_exchange(vec![ohlc_kline(dec!(2.5), dec!(2.6), dec!(1.5), dec!(2.5))]);
+        exchange.with_fees(FeeSchedule::new(dec!(0.001), dec!(0.002)));
+        exchange
+            .place_oco_sell_order("BTCUSDT", dec!(3), dec!(2), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+        assert_eq!(exchange.get_wallet()["USDT"], dec!(1.996));
+
+        let mut exchange = consumed_candle_exchange(dec!(5000));
+        exchange.with_fees(FeeSchedule::new(dec!(0.001), dec!(0.002)));
+        place_buy(&mut exchange, dec!(2), TimeInForce::IOC);
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(1.998));
+    }
+
+    #[test]
+    fn test_zero_fees_reproduce_fee_free_fills() {
+        let mut exchange =
+            btc_usdt_exchange(vec![ohlc_kline(dec!(2.5), dec!(3.5), dec!(1.5), dec!(2.5))]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .with_fees(FeeSchedule::new(dec!(0), dec!(0)));
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(2));
+        assert_eq!(exchange.get_wallet()["USDT"], dec!(8));
+        assert_eq!(exchange.get_fills()[0].fee, dec!(0));
+    }
+
+    #[test]
+    fn test_fee_drag_with_fees() {
+        let mut exchange =
+            btc_usdt_exchange(vec![ohlc_kline(dec!(2.5), dec!(3.5), dec!(1.5), dec!(2.5))]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .with_fees(FeeSchedule::new(dec!(0.001), dec!(0.002)));
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+        exchange
+            .place_limit_sell_order("BTCUSDT", dec!(3), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+
+        // 0.001 BTC at 2 plus 0.003 USDT over 5 USDT of turnover
+        assert_eq!(exchange.fee_drag("USDT"), dec!(0.001));
+    }
+
+    #[test]
+    fn test_place_order_errors_mention_the_pair() {
+        let mut exchange = Exchange::new();
+        exchange.with_capital(vec![("ETH".to_string(), dec!(1))]);
+
+        let error = exchange
+            .place_limit_buy_order("ETHBTC", dec!(0.07), dec!(1))
+            .unwrap_err();
+        assert!(matches!(&error, ExchangeError::InsufficientFunds(pair) if pair == "ETHBTC"));
+        assert!(error.to_string().contains("ETHBTC"));
+
+        let error = exchange
+            .place_limit_sell_order("ETHFOO", dec!(0.07), dec!(1))
+            .unwrap_err();
+        assert!(matches!(&error, ExchangeError::FailedToObtainAssetPair(pair) if pair == "ETHFOO"));
+        assert!(error.to_string().contains("ETHFOO"));
+    }
+
+    fn volume_kline(volume: Decimal) -> BinanceKline {
+        kline(
+            1626578400000,
+            1626578500000,
+            [dec!(2.5), dec!(3.5), dec!(1.5), dec!(2.5)],
+            volume,
+        )
+    }
+
+    #[test]
+    fn test_partial_fill_bounded_by_candle_volume() {
+        let mut exchange = btc_usdt_exchange(vec![volume_kline(dec!(1)), volume_kline(dec!(10))]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .with_participation_rate(dec!(0.5));
+        let order = exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+
+        exchange.tick().unwrap();
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(1.5));
+        assert_eq!(exchange.get_wallet()["USDT"], dec!(9));
+        let resting = &exchange.get_orders()["BTCUSDT"][0];
+        assert_eq!(resting.id, order.id);
+        assert_eq!(resting.status, OrderStatus::PartiallyFilled(50));
+        assert_eq!(resting.remaining_qty(), dec!(0.5));
+
+        exchange.tick().unwrap();
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(2));
+        assert_eq!(exchange.get_wallet()["USDT"], dec!(8));
+        assert!(exchange.get_orders()["BTCUSDT"].is_empty());
+        assert_eq!(exchange.get_fills().len(), 2);
+    }
+
+    #[test]
+    fn test_sell_fill_bounded_by_candle_volume() {
+        for (volume, sold) in [(dec!(1), dec!(0.25)), (dec!(100), dec!(2))] {
+            let mut exchange = btc_usdt_exchange(vec![volume_kline(volume)]);
+            // Tops the helper's 1 BTC up to 2
+            exchange
+                .with_capital(vec![("BTC".to_string(), dec!(1))])
+                .with_participation_rate(dec!(0.25));
+            exchange
+                .place_limit_sell_order("BTCUSDT", dec!(3), dec!(2))
+                .unwrap();
+
+            exchange.tick().unwrap();
+            assert_eq!(exchange.get_wallet()["BTC"], dec!(2) - sold);
+            assert_eq!(exchange.get_wallet()["USDT"], sold * dec!(3));
+            let fully_filled = sold == dec!(2);
+            assert_eq!(exchange.get_orders()["BTCUSDT"].is_empty(), fully_filled);
+        }
+    }
+
+    #[test]
+    fn test_orders_share_candle_participation() {
+        let mut exchange = btc_usdt_exchange(vec![volume_kline(dec!(2))]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .with_participation_rate(dec!(0.5));
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(0.6))
+            .unwrap();
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(0.6))
+            .unwrap();
+
+        exchange.tick().unwrap();
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(2));
+        let resting = &exchange.get_orders()["BTCUSDT"];
+        assert_eq!(resting.len(), 1);
+        assert_eq!(resting[0].remaining_qty(), dec!(0.2));
+    }
+
+    #[test]
+    fn test_position_notional_uses_last_close() {
+        let mut exchange =
+            btc_usdt_exchange(vec![ohlc_kline(dec!(2.5), dec!(3.5), dec!(1.5), dec!(2.4))]);
+        exchange.with_capital(vec![("BTC".to_string(), dec!(0.5))]);
+        assert!(matches!(
+            exchange.position_notional("BTC", "USDT"),
+            Err(ExchangeError::NoKlineDataAvailable)
+        ));
+
+        exchange.tick().unwrap();
+        assert_eq!(
+            exchange.position_notional("BTC", "USDT").unwrap(),
+            dec!(1.5) * dec!(2.4)
+        );
+        assert_eq!(exchange.position_notional("ETH", "USDT").unwrap(), dec!(0));
+    }
+
+    #[test]
+    fn test_get_order_book_for_pair() {
+        let mut exchange = btc_usdt_exchange(vec![]);
+        exchange.with_capital(vec![("USDT".to_string(), dec!(10))]);
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(2))
+            .unwrap();
+        exchange
+            .place_limit_sell_order("BTCUSDT", dec!(3), dec!(1))
+            .unwrap();
+
+        let book = exchange.get_order_book("BTCUSDT");
+        assert_eq!(book.bids.len(), 1);
+        assert_eq!(book.best_bid().unwrap().qty, dec!(3));
+        assert_eq!(book.spread(), Some(dec!(1)));
+        assert!(exchange.get_order_book("ETHUSDT").bids.is_empty());
+    }
+
+    #[test]
+    fn test_get_order_by_id() {
+        let mut exchange = btc_usdt_exchange(vec![]);
+        exchange.with_capital(vec![("ETH".to_string(), dec!(1))]);
+        let btc_order = exchange
+            .place_limit_sell_order("BTCUSDT", dec!(3), dec!(1))
+            .unwrap();
+        let eth_order = exchange
+            .place_limit_sell_order("ETHBTC", dec!(0.07), dec!(1))
+            .unwrap();
+
+        assert_eq!(exchange.get_order(btc_order.id), Some(&btc_order));
+        assert_eq!(exchange.get_order(eth_order.id), Some(&eth_order));
+        assert_eq!(
+            exchange.get_order_status(eth_order.id),
+            Some(OrderStatus::Pending)
+        );
+        assert_eq!(exchange.get_order(u64::MAX), None);
+        assert_eq!(exchange.get_order_status(u64::MAX), None);
+    }
+
+    #[test]
+    fn test_replay_realtime_processes_all_bars_quickly() {
+        let mut exchange = btc_usdt_exchange(vec![
+            timed_kline(0, 1_000, dec!(2.5), dec!(2.4)),
+            timed_kline(1_000, 2_000, dec!(2.5), dec!(2.4)),
+            timed_kline(2_000, 3_000, dec!(2.5), dec!(1.5)),
+        ]);
+        exchange.with_capital(vec![("USDT".to_string(), dec!(10))]);
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+
+        let started = Instant::now();
+        assert_eq!(exchange.replay_realtime(1_000.0).unwrap(), 3);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(2));
+        assert_eq!(exchange.replay_realtime(1_000.0).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_replay_realtime_can_be_cancelled() {
+        let hourly_bars = (0..3)
+            .map(|hour| {
+                timed_kline(
+                    hour * 3_600_000,
+                    (hour + 1) * 3_600_000,
+                    dec!(2.5),
+                    dec!(2.4),
+                )
+            })
+            .collect();
+        let mut exchange = btc_usdt_exchange(hourly_bars);
+        let cancel = exchange.replay_cancel_handle();
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            cancel.store(true, AtomicOrdering::SeqCst);
+        });
+
+        let started = Instant::now();
+        assert_eq!(exchange.replay_realtime(1.0).unwrap(), 2);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        canceller.join().unwrap();
+    }
+
+    #[test]
+    fn test_replay_realtime_rejects_invalid_speed() {
+        let mut exchange = btc_usdt_exchange(vec![]);
+        for speed in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                exchange.replay_realtime(speed),
+                Err(ExchangeError::InvalidReplaySpeed)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_buying_power_excludes_reserved_funds() {
+        let mut exchange = btc_usdt_exchange(vec![]);
+        exchange.with_capital(vec![("USDT".to_string(), dec!(10))]);
+        assert_eq!(exchange.buying_power("USDT"), dec!(10));
+
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(2))
+            .unwrap();
+        exchange
+            .place_oco_sell_order("BTCUSDT", dec!(3), dec!(2), dec!(1))
+            .unwrap();
+        assert_eq!(exchange.reserved("USDT"), dec!(4));
+        assert_eq!(exchange.reserved("BTC"), dec!(1));
+        assert_eq!(exchange.buying_power("USDT"), dec!(6));
+        assert_eq!(exchange.buying_power("BUSD"), dec!(0));
+    }
+
+    #[test]
+    fn test_buying_power_includes_the_margin_allowance() {
+        let mut exchange = Exchange::new();
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .add_price_feed("BTCUSDT".to_string(), PriceFeed::new())
+            .with_leverage("USDT", dec!(3));
+        // 10 of equity lends another 20 at 3x
+        assert_eq!(exchange.buying_power("USDT"), dec!(30));
+
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(2))
+            .unwrap();
+        assert_eq!(exchange.buying_power("USDT"), dec!(26));
+        assert_eq!(exchange.buying_power("BUSD"), dec!(0));
+    }
+
+    #[test]
+    fn test_fills_cannot_double_spend_the_same_funds() {
+        let mut exchange =
+            btc_usdt_exchange(vec![ohlc_kline(dec!(2.5), dec!(3.5), dec!(1.5), dec!(2.5))]);
+        exchange.with_capital(vec![("USDT".to_string(), dec!(2))]);
+        let first = exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+        let second = exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+
+        exchange.tick().unwrap();
+
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(2));
+        assert_eq!(exchange.get_wallet()["USDT"], dec!(0));
+        assert_eq!(exchange.get_fills().len(), 1);
+        assert_eq!(exchange.get_fills()[0].order_id, first.id);
+        assert_eq!(exchange.get_order(second.id), None);
+    }
+
+    #[test]
+    fn test_deposit_increases_balance() {
+        let mut exchange = btc_usdt_exchange(vec![timed_kline(0, 59_999, dec!(3), dec!(2))]);
+        exchange.tick().unwrap();
+        exchange.deposit("USDT", dec!(25)).unwrap();
+        exchange.deposit("USDT", dec!(5)).unwrap();
+
+        assert_eq!(exchange.get_wallet()["USDT"], dec!(30));
+        let deposit = exchange.get_transactions().last().unwrap();
+        assert_eq!(deposit.get_ts(), &59_999);
+        assert_eq!(deposit.get_qty(), &dec!(5));
+        assert!(matches!(
+            exchange.deposit("USDT", dec!(-5)),
+            Err(ExchangeError::InvalidAmount)
+        ));
+    }
+
+    #[test]
+    fn test_withdraw() {
+        let mut exchange = btc_usdt_exchange(vec![]);
+        exchange.with_capital(vec![("USDT".to_string(), dec!(10))]);
+
+        exchange.withdraw("USDT", dec!(4)).unwrap();
+        assert_eq!(exchange.get_wallet()["USDT"], dec!(6));
+        assert_eq!(
+            exchange.get_transactions().last().unwrap().get_qty(),
+            &dec!(-4)
+        );
+
+        // 4 of the remaining 6 are reserved by the resting buy
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(2))
+            .unwrap();
+        assert!(matches!(
+            exchange.withdraw("USDT", dec!(3)),
+            Err(ExchangeError::InsufficientFunds(symbol)) if symbol == "USDT"
+        ));
+        assert!(matches!(
+            exchange.withdraw("ETH", dec!(1)),
+            Err(ExchangeError::InsufficientFunds(_))
+        ));
+        assert_eq!(exchange.get_wallet()["USDT"], dec!(6));
+        exchange.withdraw("USDT", dec!(2)).unwrap();
+        assert_eq!(exchange.get_wallet()["USDT"], dec!(4));
+    }
+
+    #[test]
+    fn test_cancel_by_tag_only_cancels_that_strategy() {
+        let mut exchange = btc_usdt_exchange(vec![]);
+        exchange.with_capital(vec![
+            ("USDT".to_string(), dec!(10)),
+            ("ETH".to_string(), dec!(1)),
+        ]);
+        let mean_reversion = [
+            exchange
+                .place_tagged_order(
+                    "mean-reversion",
+                    "BTCUSDT",
+                    Some(dec!(2)),
+                    dec!(1),
+                    OrderDirection::Buy,
+                    OrderType::Limit,
+                )
+                .unwrap(),
+            exchange
+                .place_tagged_order(
+                    "mean-reversion",
+                    "ETHBTC",
+                    Some(dec!(0.07)),
+                    dec!(1),
+                    OrderDirection::Sell,
+                    OrderType::Limit,
+                )
+                .unwrap(),
+        ];
+        let momentum = exchange
+            .place_tagged_order(
+                "momentum",
+                "BTCUSDT",
+                Some(dec!(3)),
+                dec!(1),
+                OrderDirection::Sell,
+                OrderType::Limit,
+            )
+            .unwrap();
+        let untagged = exchange
+            .place_limit_buy_order("BTCUSDT", dec!(1), dec!(1))
+            .unwrap();
+        assert_eq!(momentum.client_tag.as_deref(), Some("momentum"));
+
+        let mut cancelled = exchange.cancel_by_tag("mean-reversion");
+        cancelled.sort_by_key(|order| order.id);
+        assert_eq!(
+            cancelled.iter().map(|order| order.id).collect::<Vec<u64>>(),
+            mean_reversion
+                .iter()
+                .map(|order| order.id)
+                .collect::<Vec<u64>>()
+        );
+        assert!(cancelled
+            .iter()
+            .all(|order| order.status == OrderStatus::Cancelled));
+        assert!(exchange.get_order(momentum.id).is_some());
+        assert!(exchange.get_order(untagged.id).is_some());
+        assert!(exchange.cancel_by_tag("mean-reversion").is_empty());
+    }
+
+    #[test]
+    fn test_pnl_attribution_reconciles_round_trip_with_fees() {
+        let mut exchange =
+            btc_usdt_exchange(vec![ohlc_kline(dec!(2.5), dec!(3.5), dec!(1.5), dec!(2.5))]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .with_fees(FeeSchedule::new(dec!(0.001), dec!(0.002)));
+        let equity = |exchange: &Exchange| {
+            exchange.get_wallet()["USDT"] + exchange.get_wallet()["BTC"] * dec!(2.5)
+        };
+        let starting_equity = equity(&exchange);
+        assert_eq!(exchange.pnl_attribution("USDT"), (dec!(0), dec!(0)));
+
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+        exchange
+            .place_limit_sell_order("BTCUSDT", dec!(3), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+
+        let (price_pnl, fee_cost) = exchange.pnl_attribution("USDT");
+        assert_eq!(price_pnl, dec!(1));
+        // 0.003 USDT on the sell plus 0.001 BTC marked at 2.5 on the buy
+        assert_eq!(fee_cost, dec!(0.0055));
+        assert_eq!(price_pnl - fee_cost, equity(&exchange) - starting_equity);
+    }
+
+    #[test]
+    fn test_realized_pnl_per_base_asset() {
+        let mut exchange = btc_usdt_exchange(vec![
+            ohlc_kline(dec!(100), dec!(101), dec!(99), dec!(100)),
+            ohlc_kline(dec!(150), dec!(151), dec!(149), dec!(150)),
+        ]);
+        exchange.with_capital(vec![("USDT".to_string(), dec!(100))]);
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(100), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+        exchange
+            .place_limit_sell_order("BTCUSDT", dec!(150), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+
+        let realized = exchange.realized_pnl("USDT");
+        assert_eq!(realized.len(), 1);
+        assert_eq!(realized["BTC"], dec!(50));
+        assert!(exchange.realized_pnl("BUSD").is_empty());
+    }
+
+    fn jittered_fill_ts(seed: u64) -> i64 {
+        let mut exchange = Exchange::new();
+        let klines = (0..6)
+            .map(|i| timed_kline(i * 60, i * 60 + 59, dec!(3), dec!(1)))
+            .collect();
+        let mut price_feed = PriceFeed::new();
+        price_feed.add_price_data(klines);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .add_price_feed("BTCUSDT".to_string(), price_feed)
+            .with_seed(seed)
+            .with_fill_delay_jitter(4);
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+        for _ in 0..6 {
+            exchange.tick().unwrap();
+        }
+        assert_eq!(exchange.get_fills().len(), 1);
+        exchange.get_fills()[0].ts
+    }
+
+    #[test]
+    fn test_fill_delay_jitter_is_deterministic_per_seed() {
+        // Every bar crosses the order, without jitter it would fill on the first one
+        let fill_ts = jittered_fill_ts(3);
+        assert_eq!(fill_ts, 239);
+        assert_eq!(jittered_fill_ts(3), fill_ts);
+    }
+
+    #[test]
+    fn test_fill_delays_are_dropped_with_their_orders() {
+        let mut exchange =
+            btc_usdt_exchange(vec![ohlc_kline(dec!(2), dec!(3), dec!(1), dec!(2)); 3]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .with_seed(3)
+            .with_fill_delay_jitter(4);
+        let order = exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+        assert!(exchange.fill_delays.contains_key(&order.id));
+
+        exchange.cancel_all();
+        assert!(exchange.fill_delays.is_empty());
+    }
+
+    #[test]
+    fn test_portfolio_value_in_quote_currency() {
+        let mut exchange = Exchange::new();
+        exchange.with_capital(vec![
+            ("BTC".to_string(), dec!(0.5)),
+            ("ETH".to_string(), dec!(2)),
+            ("USDT".to_string(), dec!(100)),
+        ]);
+        let prices = HashMap::from([
+            ("BTCUSDT".to_string(), dec!(20000)),
+            ("ETHUSDT".to_string(), dec!(1500)),
+        ]);
+        assert_eq!(
+            exchange.portfolio_value("USDT", &prices).unwrap(),
+            dec!(13100)
+        );
+
+        // BTC is valued through the inverse pair, ETH has no conversion at all
+        let prices = HashMap::from([("USDTBTC".to_string(), dec!(0.5))]);
+        let result = exchange.portfolio_value("USDT", &prices);
+        assert!(matches!(result, Err(ExchangeError::NoConversionPath(asset)) if asset == "ETH"));
+        exchange.withdraw("ETH", dec!(2)).unwrap();
+        assert_eq!(
+            exchange.portfolio_value("USDT", &prices).unwrap(),
+            dec!(101)
+        );
+    }
+
+    #[test]
+    fn test_orphan_order_symbols() {
+        let mut exchange = btc_usdt_exchange(vec![ohlc_kline(dec!(2), dec!(3), dec!(1), dec!(2))]);
+        exchange.with_capital(vec![("USDT".to_string(), dec!(10))]);
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(1), dec!(1))
+            .unwrap();
+        assert!(exchange.orphan_order_symbols().is_empty());
+
+        exchange
+            .place_limit_buy_order("ETHUSDT", dec!(1), dec!(1))
+            .unwrap();
+        assert_eq!(exchange.orphan_order_symbols(), vec!["ETHUSDT".to_string()]);
+    }
+
+    #[test]
+    fn test_bars_to_fill() {
+        let mut exchange = btc_usdt_exchange(vec![
+            ohlc_kline(dec!(5), dec!(6), dec!(4), dec!(5)),
+            ohlc_kline(dec!(5), dec!(6), dec!(4), dec!(5)),
+            ohlc_kline(dec!(3), dec!(4), dec!(1), dec!(2)),
+        ]);
+        exchange.with_capital(vec![("USDT".to_string(), dec!(10))]);
+        let order = exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+        assert_eq!(order.bars_to_fill(), None);
+        for _ in 0..3 {
+            exchange.tick().unwrap();
+        }
+        let filled = &exchange.get_filled_orders()[0];
+        assert_eq!(filled.id, order.id);
+        assert_eq!(filled.bars_to_fill(), Some(3));
+
+        let immediate = place_buy(&mut exchange, dec!(3), TimeInForce::IOC);
+        assert_eq!(immediate.bars_to_fill(), Some(0));
+    }
+
+    #[test]
+    fn test_stop_limit_entry_is_dormant_until_triggered() {
+        let mut exchange = btc_usdt_exchange(vec![
+            ohlc_kline(dec!(3.5), dec!(4), dec!(3), dec!(3.5)),
+            ohlc_kline(dec!(5), dec!(5.5), dec!(4.8), dec!(5)),
+            ohlc_kline(dec!(4.5), dec!(5), dec!(4), dec!(4.5)),
+        ]);
+        exchange.with_capital(vec![("USDT".to_string(), dec!(10))]);
+        let order = exchange
+            .place_stop_limit_order("BTCUSDT", dec!(5), dec!(4.5), dec!(1), OrderDirection::Buy)
+            .unwrap();
+        assert!(!order.activated);
+        assert!(exchange.get_order_book("BTCUSDT").best_bid().is_none());
+
+        // Trades through the limit but the trigger hasn't been reached
+        exchange.tick().unwrap();
+        assert!(!exchange.get_order(order.id).unwrap().activated);
+
+        // Reaches the trigger and starts resting
+        exchange.tick().unwrap();
+        assert!(exchange.get_order(order.id).unwrap().activated);
+        assert!(exchange.get_fills().is_empty());
+
+        exchange.tick().unwrap();
+        assert_eq!(exchange.get_order_status(order.id), None);
+        assert_eq!(exchange.get_fills()[0].price, dec!(4.5));
+        assert_eq!(exchange.get_wallet().get("BTC").unwrap(), &dec!(2));
+    }
+
+    #[test]
+    fn test_tick_with_uninitialized_feed() {
+        let mut exchange = Exchange::new();
+        exchange.add_price_feed("BTCUSDT".to_string(), PriceFeed::new());
+        assert!(exchange.tick().is_ok());
+    }
+
+    #[test]
+    fn test_export_statement_csv() {
+        let mut exchange = btc_usdt_exchange(vec![
+            ohlc_kline(dec!(2), dec!(3), dec!(1), dec!(2)),
+            ohlc_kline(dec!(4), dec!(5), dec!(3), dec!(4)),
+        ]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .with_fees(FeeSchedule::new(dec!(0.01), dec!(0.02)));
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+        exchange.deposit("USDT", dec!(5)).unwrap();
+        exchange
+            .place_limit_sell_order("BTCUSDT", dec!(4), dec!(0.5))
+            .unwrap();
+        exchange.tick().unwrap();
+
+        let path = std::env::temp_dir().join("exchange_statement.csv");
+        exchange
+            .export_statement_csv(path.to_str().unwrap())
+            .unwrap();
+        let statement = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let rows: Vec<&str> = statement.lines().collect();
+        assert_eq!(
+            rows,
+            vec![
+                "timestamp,pair,side,qty,price,fee,fee_asset,base_balance,quote_balance",
+                "1626578500000,BTCUSDT,BUY,1,2,0.01,BTC,1.99,8",
+                "1626578500000,BTCUSDT,SELL,0.5,4,0.020,USDT,1.49,14.980",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rolling_pnl_over_equity_curve() {
+        let closes = [dec!(2), dec!(3), dec!(5), dec!(4), dec!(6)];
+        let mut exchange = btc_usdt_exchange(
+            closes
+                .iter()
+                .map(|&close| ohlc_kline(close, close, close, close))
+                .collect(),
+        );
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .enable_equity_tracking("USDT");
+        for _ in 0..closes.len() {
+            exchange.tick().unwrap();
+        }
+        // 1 BTC plus 10 USDT marked at each close
+        let values: Vec<Decimal> = exchange.equity_curve().iter().map(|(_, v)| *v).collect();
+        assert_eq!(
+            values,
+            vec![dec!(12), dec!(13), dec!(15), dec!(14), dec!(16)]
+        );
+
+        assert_eq!(
+            exchange.rolling_pnl(2, "USDT"),
+            vec![dec!(3), dec!(1), dec!(1)]
+        );
+        assert_eq!(exchange.rolling_pnl(5, "USDT"), vec![]);
+        assert_eq!(exchange.rolling_pnl(1, "BTC"), vec![]);
+    }
+
+    #[derive(Debug)]
+    struct MockProvider {
+        klines: Vec<BinanceKline>,
+    }
+
+    impl PriceProvider for MockProvider {
+        fn fetch(
+            &self,
+            _symbol: &str,
+            _interval: &str,
+            limit: i32,
+        ) -> Result<Vec<BinanceKline>, Box<dyn Error + Send + Sync>> {
+            Ok(self.klines.iter().take(limit as usize).cloned().collect())
+        }
+    }
+
+    #[test]
+    fn test_price_feed_from_mock_provider() {
+        let mut exchange = Exchange::new();
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .with_price_provider(Arc::new(MockProvider {
+                klines: vec![
+                    ohlc_kline(dec!(2), dec!(3), dec!(1), dec!(2)),
+                    ohlc_kline(dec!(4), dec!(5), dec!(3), dec!(4)),
+                ],
+            }))
+            .with_price_feed("BTCUSDT".to_string(), "1h".to_string(), 1)
+            .unwrap();
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+        assert_eq!(exchange.get_wallet().get("BTC").unwrap(), &dec!(1));
+
+        // Only `limit` klines were loaded
+        assert!(exchange.price_feed_next("BTCUSDT").is_none());
+    }
+
+    #[test]
+    fn test_limit_orders_fill_when_price_is_touched() {
+        let mut exchange = btc_usdt_exchange(vec![ohlc_kline(dec!(3), dec!(4), dec!(2), dec!(3))]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .with_fill_condition(FillCondition::Touch);
+        let buy = exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+        let sell = exchange
+            .place_limit_sell_order("BTCUSDT", dec!(4), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+
+        assert_eq!(exchange.get_order_status(buy.id), None);
+        assert_eq!(exchange.get_order_status(sell.id), None);
+        let prices: Vec<Decimal> = exchange.get_fills().iter().map(|f| f.price).collect();
+        assert_eq!(prices, vec![dec!(2), dec!(4)]);
+        assert_eq!(exchange.get_wallet().get("USDT").unwrap(), &dec!(12));
+    }
+
+    #[test]
+    fn test_avg_daily_volume() {
+        let day = 24 * 60 * 60 * 1000;
+        let mut price_feed = PriceFeed::new();
+        price_feed.add_price_data(vec![
+            timed_kline(day - 120_000, day - 60_001, dec!(3), dec!(1)),
+            timed_kline(day, day + 59_999, dec!(3), dec!(1)),
+        ]);
+        let mut exchange = Exchange::new();
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .with_fill_condition(FillCondition::Touch)
+            .add_price_feed("BTCUSDT".to_string(), price_feed);
+        assert_eq!(exchange.avg_daily_volume("USDT"), dec!(0));
+
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+        exchange
+            .place_limit_sell_order("BTCUSDT", dec!(3), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+
+        // 2 + 3 USDT turned over across two days
+        assert_eq!(exchange.avg_daily_volume("USDT"), dec!(2.5));
+    }
+
+    #[test]
+    fn test_market_order_fills_at_last_close() {
+        let mut exchange =
+            btc_usdt_exchange(vec![ohlc_kline(dec!(2), dec!(3), dec!(1), dec!(2.5))]);
+        exchange.with_capital(vec![("USDT".to_string(), dec!(10))]);
+        assert!(matches!(
+            exchange.place_market_buy_order("BTCUSDT", dec!(2), dec!(1)),
+            Err(ExchangeError::NoKlineDataAvailable)
+        ));
+
+        exchange.tick().unwrap();
+        let order = exchange
+            .place_market_buy_order("BTCUSDT", dec!(2), dec!(2))
+            .unwrap();
+        assert_eq!(order.status, OrderStatus::Filled);
+        assert_eq!(exchange.get_fills()[0].price, dec!(2.5));
+        assert_eq!(exchange.get_wallet().get("BTC").unwrap(), &dec!(3));
+        assert_eq!(exchange.get_wallet().get("USDT").unwrap(), &dec!(5));
+        assert!(exchange.get_orders().values().flatten().next().is_none());
+    }
+
+    #[test]
+    fn test_close_position_goes_exactly_flat() {
+        let mut exchange = Exchange::new();
+        let mut price_feed = PriceFeed::new();
+        price_feed.add_price_data(vec![ohlc_kline(dec!(3), dec!(3.1), dec!(2.9), dec!(3.07))]);
+        exchange
+            .with_capital(vec![("BTC".to_string(), dec!(0.123456789))])
+            .with_fees(FeeSchedule::new(dec!(0.001), dec!(0.00075)))
+            .add_price_feed("BTCUSDT".to_string(), price_feed);
+        assert!(matches!(
+            exchange.close_position("BTC"),
+            Err(ExchangeError::NoPriceFeed)
+        ));
+        exchange.tick().unwrap();
+
+        let order = exchange.close_position("BTC").unwrap();
+        assert_eq!(order.direction, OrderDirection::Sell);
+        assert_eq!(order.qty, dec!(0.123456789));
+        assert_eq!(exchange.get_wallet().get("BTC").unwrap(), &dec!(0));
+        assert!(matches!(
+            exchange.close_position("BTC"),
+            Err(ExchangeError::InvalidAmount)
+        ));
+    }
+
+    #[test]
+    fn test_close_short_off_the_lot_step_goes_exactly_flat() {
+        let mut exchange = Exchange::new();
+        let mut price_feed = PriceFeed::new();
+        price_feed.add_price_data(vec![ohlc_kline(dec!(10), dec!(10), dec!(10), dec!(10)); 2]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(100))])
+            .with_fill_condition(FillCondition::Touch)
+            .with_fees(FeeSchedule::new(dec!(0.001), dec!(0.001)))
+            .add_price_feed("BTCUSDT".to_string(), price_feed);
+        exchange.enable_short_selling();
+        exchange
+            .place_limit_sell_order("BTCUSDT", dec!(10), dec!(1.005))
+            .unwrap();
+        exchange.tick().unwrap();
+        assert_eq!(exchange.get_balance("BTC"), dec!(-1.005));
+
+        exchange.with_symbol_filters("BTCUSDT", SymbolFilters::new(dec!(0), dec!(0.01), dec!(0)));
+        let order = exchange.close_position("BTC").unwrap();
+        let fill = exchange.get_fills().last().unwrap();
+        assert_eq!(order.direction, OrderDirection::Buy);
+        assert_eq!(fill.qty, dec!(1.01));
+        assert_eq!(fill.fee, dec!(0.005));
+        assert_eq!(exchange.get_balance("BTC"), dec!(0));
+        assert!(exchange.wallet.verify_integrity().is_ok());
+    }
+
+    fn gapping_exchange(fill_model: FillModel) -> Exchange {
+        // Opens at 1.5, well below the resting buy at 2 and its take profit at 3
+        let mut exchange = btc_usdt_exchange(vec![
+            ohlc_kline(dec!(1.5), dec!(1.8), dec!(1.2), dec!(1.6)),
+            ohlc_kline(dec!(3.5), dec!(3.8), dec!(3.2), dec!(3.6)),
+        ]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .with_fill_model(fill_model);
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+        exchange
+            .place_limit_sell_order("BTCUSDT", dec!(3), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+        exchange
+    }
+
+    #[test]
+    fn test_fill_model_at_limit_ignores_gaps() {
+        let exchange = gapping_exchange(FillModel::AtLimit);
+        let prices: Vec<Decimal> = exchange.get_fills().iter().map(|f| f.price).collect();
+        assert_eq!(prices, vec![dec!(2), dec!(3)]);
+        assert_eq!(exchange.get_wallet().get("USDT").unwrap(), &dec!(11));
+    }
+
+    #[test]
+    fn test_fill_model_at_candle_credits_the_gap() {
+        let exchange = gapping_exchange(FillModel::AtCandle);
+        let prices: Vec<Decimal> = exchange.get_fills().iter().map(|f| f.price).collect();
+        assert_eq!(prices, vec![dec!(1.5), dec!(3.5)]);
+        assert_eq!(exchange.get_wallet().get("USDT").unwrap(), &dec!(12));
+    }
+
+    #[test]
+    fn test_post_only_order_fills_at_its_limit_through_a_gap() {
+        let mut exchange = btc_usdt_exchange(vec![
+            ohlc_kline(dec!(2.5), dec!(3), dec!(2.2), dec!(2.5)),
+            ohlc_kline(dec!(1.5), dec!(1.8), dec!(1.2), dec!(1.6)),
+        ]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .with_fill_model(FillModel::AtCandle)
+            .with_fees(FeeSchedule::new(dec!(0.001), dec!(0.002)));
+        exchange.tick().unwrap();
+        exchange
+            .place_post_only_order("BTCUSDT", dec!(2), dec!(1), OrderDirection::Buy)
+            .unwrap();
+        exchange.tick().unwrap();
+
+        let fill = &exchange.get_fills()[0];
+        assert_eq!(fill.price, dec!(2));
+        assert_eq!(fill.fee, dec!(0.001));
+    }
+
+    #[test]
+    fn test_cash_ratio() {
+        let mut exchange = btc_usdt_exchange(vec![ohlc_kline(dec!(2), dec!(3), dec!(1), dec!(2))]);
+        let mut eth_feed = PriceFeed::new();
+        eth_feed.add_price_data(vec![ohlc_kline(dec!(5), dec!(5), dec!(5), dec!(5))]);
+        exchange
+            .with_capital(vec![
+                ("USDT".to_string(), dec!(8)),
+                ("ETH".to_string(), dec!(2)),
+            ])
+            .add_price_feed("ETHUSDT".to_string(), eth_feed);
+        exchange.tick().unwrap();
+
+        // 8 USDT out of 8 + 1 BTC * 2 + 2 ETH * 5
+        assert_eq!(exchange.cash_ratio("USDT").unwrap(), dec!(0.4));
+        assert!(Exchange::new().cash_ratio("USDT").unwrap().is_zero());
+    }
+
+    fn slipped_market_prices(slippage_model: SlippageModel, seed: u64) -> Vec<Decimal> {
+        let mut exchange =
+            btc_usdt_exchange(vec![ohlc_kline(dec!(2), dec!(3), dec!(1), dec!(2.5))]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .with_seed(seed)
+            .with_slippage_model(slippage_model);
+        exchange.tick().unwrap();
+        exchange
+            .place_market_buy_order("BTCUSDT", dec!(2.5), dec!(1))
+            .unwrap();
+        exchange
+            .place_market_sell_order("BTCUSDT", dec!(2.5), dec!(1))
+            .unwrap();
+        exchange.get_fills().iter().map(|fill| fill.price).collect()
+    }
+
+    #[test]
+    fn test_market_orders_pay_slippage() {
+        assert_eq!(
+            slipped_market_prices(SlippageModel::FixedBps(dec!(20)), 0),
+            vec![dec!(2.505), dec!(2.495)]
+        );
+
+        let random = slipped_market_prices(SlippageModel::RandomBps(dec!(20)), 5);
+        assert_eq!(
+            random,
+            slipped_market_prices(SlippageModel::RandomBps(dec!(20)), 5)
+        );
+        assert!(random[0] > dec!(2.5) && random[0] <= dec!(2.505));
+        assert!(random[1] < dec!(2.5) && random[1] >= dec!(2.495));
+    }
+
+    #[test]
+    fn test_limit_price_fills_against_sub_tick_klines() {
+        let kline = || {
+            ohlc_kline(
+                dec!(0.081000000),
+                dec!(0.082000000),
+                dec!(0.080000004),
+                dec!(0.081000000),
+            )
+        };
+        let place_buy = |exchange: &mut Exchange| {
+            exchange
+                .with_capital(vec![("USDT".to_string(), dec!(1))])
+                .with_fill_condition(FillCondition::Touch)
+                .place_limit_buy_order("BTCUSDT", dec!(0.08000000), dec!(1))
+                .unwrap()
+        };
+
+        let mut exchange = btc_usdt_exchange(vec![kline()]);
+        let order = place_buy(&mut exchange);
+        exchange.tick().unwrap();
+        assert!(exchange.get_order(order.id).is_some());
+
+        let mut exchange = btc_usdt_exchange(vec![kline()]);
+        exchange.with_tick_size("BTCUSDT", dec!(0.00000001));
+        let order = place_buy(&mut exchange);
+        exchange.tick().unwrap();
+        assert!(exchange.get_order(order.id).is_none());
+        assert_eq!(exchange.get_fills()[0].price, dec!(0.08000000));
+
+        // A sub-tick limit price is rounded down on placement, so it doesn't cross the bar
+        let mut exchange = btc_usdt_exchange(vec![kline()]);
+        exchange.with_tick_size("BTCUSDT", dec!(0.00000001));
+        let order = exchange
+            .with_capital(vec![("USDT".to_string(), dec!(1))])
+            .place_limit_buy_order("BTCUSDT", dec!(0.079999999), dec!(1))
+            .unwrap();
+        assert_eq!(order.price, Some(dec!(0.07999999)));
+        exchange.tick().unwrap();
+        assert!(exchange.get_order(order.id).is_some());
+    }
+
+    #[test]
+    fn test_round_trip_at_a_flat_price_loses_the_spread() {
+        let mut exchange =
+            btc_usdt_exchange(vec![ohlc_kline(dec!(100), dec!(100), dec!(100), dec!(100))]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(1000))])
+            .with_spread(dec!(20));
+        exchange.tick().unwrap();
+        exchange
+            .place_market_buy_order("BTCUSDT", dec!(100), dec!(1))
+            .unwrap();
+        exchange
+            .place_market_sell_order("BTCUSDT", dec!(100), dec!(1))
+            .unwrap();
+
+        let prices: Vec<Decimal> = exchange.get_fills().iter().map(|f| f.price).collect();
+        assert_eq!(prices, vec![dec!(100.1), dec!(99.9)]);
+        // 20 bps of 100 USDT
+        assert_eq!(exchange.get_wallet().get("USDT").unwrap(), &dec!(999.8));
+    }
+
+    #[test]
+    fn test_fee_breakeven_pct() {
+        let mut exchange = Exchange::new();
+        assert_eq!(exchange.fee_breakeven_pct("BTCUSDT"), dec!(0));
+
+        exchange.with_fees(FeeSchedule::new(dec!(0.001), dec!(0.2)));
+        // Keeping 0.8 of each leg leaves 0.64, so the price must rise by 1 / 0.64
+        assert_eq!(exchange.fee_breakeven_pct("BTCUSDT"), dec!(56.25));
+    }
+
+    #[test]
+    fn test_exchange_json_round_trip() {
+        let mut exchange = btc_usdt_exchange(vec![
+            ohlc_kline(dec!(2), dec!(3), dec!(1), dec!(2)),
+            ohlc_kline(dec!(2), dec!(2.5), dec!(1.5), dec!(2)),
+        ]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .with_fill_condition(FillCondition::Touch);
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(1), dec!(2))
+            .unwrap();
+        exchange
+            .place_limit_sell_order("BTCUSDT", dec!(2.5), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+
+        let mut restored = Exchange::from_json(&exchange.to_json().unwrap()).unwrap();
+        assert_eq!(restored.get_orders(), exchange.get_orders());
+        assert_eq!(restored.get_wallet(), exchange.get_wallet());
+        assert_eq!(restored.get_transactions(), exchange.get_transactions());
+        assert_eq!(restored.get_fills(), exchange.get_fills());
+
+        // Feeds resume from their cursor and new ids don't collide with restored orders
+        let new_order = restored
+            .place_limit_buy_order("BTCUSDT", dec!(1.5), dec!(1))
+            .unwrap();
+        let max_restored_id = exchange.get_orders().values().flatten().map(|o| o.id).max();
+        assert!(Some(new_order.id) > max_restored_id);
+        restored.tick().unwrap();
+        assert!(restored.get_order(new_order.id).is_none());
+        assert!(restored.price_feed_next("BTCUSDT").is_none());
+    }
+
+    fn stochastic_step(exchange: &mut Exchange) {
+        exchange.tick().unwrap();
+        exchange
+            .place_market_buy_order("BTCUSDT", dec!(2), dec!(0.1))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_restored_snapshot_continues_deterministically() {
+        let stochastic_exchange = || {
+            let mut exchange =
+                btc_usdt_exchange(vec![ohlc_kline(dec!(2), dec!(3), dec!(1), dec!(2)); 4]);
+            exchange
+                .with_capital(vec![("USDT".to_string(), dec!(10))])
+                .with_seed(11)
+                .with_slippage_model(SlippageModel::RandomBps(dec!(100)));
+            exchange
+        };
+
+        let mut uninterrupted = stochastic_exchange();
+        for _ in 0..4 {
+            stochastic_step(&mut uninterrupted);
+        }
+
+        let mut interrupted = stochastic_exchange();
+        for _ in 0..2 {
+            stochastic_step(&mut interrupted);
+        }
+        let mut restored = Exchange::from_json(&interrupted.to_json().unwrap()).unwrap();
+        for _ in 0..2 {
+            stochastic_step(&mut restored);
+        }
+
+        let prices = |exchange: &Exchange| -> Vec<Decimal> {
+            exchange.get_fills().iter().map(|fill| fill.price).collect()
+        };
+        assert_eq!(prices(&restored), prices(&uninterrupted));
+        assert_eq!(restored.get_wallet(), uninterrupted.get_wallet());
+    }
+
+    #[test]
+    fn test_snapshot_without_newer_fields_restores_defaults() {
+        let exchange = btc_usdt_exchange(vec![]);
+        let mut snapshot: serde_json::Value =
+            serde_json::from_str(&exchange.to_json().unwrap()).unwrap();
+        for field in [
+            "current_ts",
+            "next_order_id",
+            "quote_assets",
+            "traded_volume",
+        ] {
+            snapshot.as_object_mut().unwrap().remove(field);
+        }
+
+        let restored = Exchange::from_json(&snapshot.to_string()).unwrap();
+        assert_eq!(restored.now(), 0);
+        assert_eq!(restored.traded_volume("USDT"), dec!(0));
+        assert_eq!(restored.get_asset_pair("BTCUSDT").unwrap(), ("BTC", "USDT"));
+    }
+
+    #[test]
+    fn test_bars_processed_per_feed() {
+        let mut exchange =
+            btc_usdt_exchange(vec![ohlc_kline(dec!(2), dec!(3), dec!(1), dec!(2)); 3]);
+        let mut eth_feed = PriceFeed::new();
+        eth_feed.add_price_data(vec![ohlc_kline(dec!(5), dec!(5), dec!(5), dec!(5)); 5]);
+        exchange.add_price_feed("ETHUSDT".to_string(), eth_feed);
+        for _ in 0..2 {
+            exchange.tick().unwrap();
+        }
+        exchange.price_feed_next("ETHUSDT");
+
+        let bars = exchange.bars_processed();
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars["BTCUSDT"], 2);
+        assert_eq!(bars["ETHUSDT"], 3);
+    }
+
+    #[test]
+    fn test_fill_condition_touch_vs_cross() {
+        let touching_exchange = |fill_condition: FillCondition| {
+            let mut exchange =
+                btc_usdt_exchange(vec![ohlc_kline(dec!(3), dec!(4), dec!(2), dec!(3))]);
+            exchange
+                .with_capital(vec![("USDT".to_string(), dec!(10))])
+                .with_fill_condition(fill_condition);
+            exchange
+                .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+                .unwrap();
+            exchange
+                .place_limit_sell_order("BTCUSDT", dec!(4), dec!(1))
+                .unwrap();
+            exchange.tick().unwrap();
+            exchange
+        };
+        assert_eq!(touching_exchange(FillCondition::Touch).get_fills().len(), 2);
+
+        assert_eq!(FillCondition::default(), FillCondition::Cross);
+        let exchange = touching_exchange(FillCondition::Cross);
+        assert!(exchange.get_fills().is_empty());
+        assert_eq!(exchange.get_orders()["BTCUSDT"].len(), 2);
+    }
+
+    #[test]
+    fn test_tick_returns_filled_orders() {
+        let mut exchange = btc_usdt_exchange(vec![volume_kline(dec!(1)), volume_kline(dec!(10))]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .with_participation_rate(dec!(0.5));
+        let partial = exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+        let queued = exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+
+        let filled = exchange.tick().unwrap();
+        assert_eq!(filled.len(), 1);
+        assert_eq!(filled[0].id, partial.id);
+        assert_eq!(filled[0].status, OrderStatus::PartiallyFilled(50));
+
+        let filled = exchange.tick().unwrap();
+        let statuses: Vec<(u64, OrderStatus)> = filled
+            .iter()
+            .map(|order| (order.id, order.status.clone()))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                (partial.id, OrderStatus::Filled),
+                (queued.id, OrderStatus::Filled)
+            ]
+        );
+        assert!(exchange.tick().unwrap().is_empty());
+    }
+
+    fn close_kline(close_ts: i64, close: Decimal) -> BinanceKline {
+        kline(close_ts - 3_599_999, close_ts, [close; 4], dec!(5000))
+    }
+
+    fn beta_exchange(capital: Vec<(String, Decimal)>) -> Exchange {
+        let closes = [dec!(100), dec!(110), dec!(99), dec!(120), dec!(114)];
+        let klines = closes
+            .iter()
+            .enumerate()
+            .map(|(i, &close)| close_kline((i as i64 + 1) * 3_600_000 - 1, close))
+            .collect();
+        let mut exchange = btc_usdt_exchange(klines);
+        exchange
+            .with_capital(capital)
+            .enable_equity_tracking("USDT");
+        for _ in 0..closes.len() {
+            exchange.tick().unwrap();
+        }
+        exchange
+    }
+
+    #[test]
+    fn test_portfolio_beta_tracks_reference() {
+        let exchange = beta_exchange(vec![]);
+        let beta = exchange.portfolio_beta("BTCUSDT", "USDT").unwrap();
+        assert!((beta - dec!(1)).abs() < dec!(0.0000001));
+
+        // Half the portfolio in cash at the start moves roughly half as much
+        let exchange = beta_exchange(vec![("USDT".to_string(), dec!(100))]);
+        let beta = exchange.portfolio_beta("BTCUSDT", "USDT").unwrap();
+        assert!(beta > dec!(0.4) && beta < dec!(0.6));
+
+        assert_eq!(exchange.portfolio_beta("BTCUSDT", "BTC"), None);
+        assert_eq!(exchange.portfolio_beta("ETHUSDT", "USDT"), None);
+    }
+
+    #[test]
+    fn test_now_follows_consumed_bars() {
+        let mut exchange = btc_usdt_exchange(vec![
+            close_kline(3_599_999, dec!(2)),
+            close_kline(7_199_999, dec!(3)),
+        ]);
+        assert_eq!(exchange.now(), 0);
+        exchange.tick().unwrap();
+        assert_eq!(exchange.now(), 3_599_999);
+        exchange.tick().unwrap();
+        assert_eq!(exchange.now(), 7_199_999);
+
+        // Exhausted feeds leave the clock where it was
+        exchange.tick().unwrap();
+        assert_eq!(exchange.now(), 7_199_999);
+
+        exchange.deposit("USDT", dec!(5)).unwrap();
+        exchange.withdraw("USDT", dec!(2)).unwrap();
+        let stamps: Vec<i64> = exchange
+            .wallet
+            .get_transactions()
+            .iter()
+            .filter(|transaction| transaction.get_symbol() == "USDT")
+            .map(|transaction| *transaction.get_ts())
+            .collect();
+        assert_eq!(stamps, vec![7_199_999, 7_199_999]);
+    }
+
+    #[test]
+    fn test_current_price_reads_without_consuming() {
+        let mut exchange = btc_usdt_exchange(vec![
+            ohlc_kline(dec!(2), dec!(3), dec!(1), dec!(2.5)),
+            ohlc_kline(dec!(2.5), dec!(5), dec!(2), dec!(4)),
+        ]);
+        assert_eq!(exchange.current_price("BTCUSDT"), Some(dec!(2)));
+        exchange.tick().unwrap();
+        assert_eq!(exchange.current_price("BTCUSDT"), Some(dec!(2.5)));
+        assert_eq!(exchange.current_price("BTCUSDT"), Some(dec!(2.5)));
+        exchange.tick().unwrap();
+        assert_eq!(exchange.current_price("BTCUSDT"), Some(dec!(4)));
+        assert_eq!(exchange.current_price("ETHUSDT"), None);
+    }
+
+    #[test]
+    fn test_gtd_order_expires_once_clock_passes() {
+        let mut exchange = btc_usdt_exchange(vec![
+            close_kline(3_599_999, dec!(2)),
+            close_kline(7_199_999, dec!(2)),
+            close_kline(10_799_999, dec!(1)),
+        ]);
+        exchange.with_capital(vec![("USDT".to_string(), dec!(10))]);
+        let order = place_buy(&mut exchange, dec!(1), TimeInForce::GTD(7_199_999));
+        assert_eq!(exchange.reserved("USDT"), dec!(1));
+
+        exchange.tick().unwrap();
+        exchange.tick().unwrap();
+        assert_eq!(exchange.now(), 7_199_999);
+        assert!(exchange.get_order(order.id).is_some());
+
+        // The third bar would have filled it, but it closes after the expiry
+        assert!(exchange.tick().unwrap().is_empty());
+        assert!(exchange.get_order(order.id).is_none());
+        assert_eq!(exchange.reserved("USDT"), dec!(0));
+        assert_eq!(exchange.get_wallet()["USDT"], dec!(10));
+    }
+
+    #[test]
+    fn test_gtd_order_fills_before_expiry() {
+        let mut exchange = btc_usdt_exchange(vec![close_kline(3_599_999, dec!(1))]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .with_fill_condition(FillCondition::Touch);
+        let order = place_buy(&mut exchange, dec!(1), TimeInForce::GTD(3_599_999));
+
+        let filled = exchange.tick().unwrap();
+        assert_eq!(filled[0].id, order.id);
+        assert_eq!(filled[0].status, OrderStatus::Filled);
+    }
+
+    #[test]
+    fn test_symbol_filters_on_placement() {
+        let mut exchange = btc_usdt_exchange(vec![]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(100))])
+            .with_symbol_filters(
+                "BTCUSDT",
+                SymbolFilters::new(dec!(10), dec!(0.1), dec!(0.01)),
+            );
+
+        assert!(matches!(
+            exchange.place_limit_buy_order("BTCUSDT", dec!(20), dec!(0.4)),
+            Err(ExchangeError::BelowMinNotional(_))
+        ));
+        assert!(matches!(
+            exchange.place_limit_buy_order("BTCUSDT", dec!(20), dec!(0.05)),
+            Err(ExchangeError::InvalidLotSize(_))
+        ));
+        assert!(exchange.get_orders().is_empty());
+
+        // Off the step, the quantity is rounded down to it
+        let order = exchange
+            .place_limit_buy_order("BTCUSDT", dec!(20), dec!(1.27))
+            .unwrap();
+        assert_eq!(order.qty, dec!(1.2));
+        assert_eq!(exchange.get_order(order.id).unwrap().qty, dec!(1.2));
+        assert_eq!(exchange.reserved("USDT"), dec!(24));
+    }
+
+    #[test]
+    fn test_fills_rounded_to_symbol_precision() {
+        let mut exchange = btc_usdt_exchange(vec![
+            ohlc_kline(dec!(2), dec!(2), dec!(2), dec!(2)),
+            volume_kline(dec!(1)),
+        ]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .with_slippage_model(SlippageModel::FixedBps(dec!(3)))
+            .with_participation_rate(dec!(0.3333))
+            .with_symbol_filters(
+                "BTCUSDT",
+                SymbolFilters::new(dec!(0), dec!(0.01), dec!(0.01)),
+            );
+        exchange.tick().unwrap();
+
+        // 3 bps of slippage on 2 fills at 2.0006, rounded up to the next tick
+        exchange
+            .place_market_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+        assert_eq!(exchange.get_fills()[0].price, dec!(2.01));
+        assert_eq!(exchange.get_wallet()["USDT"], dec!(7.99));
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(2));
+
+        // Placed at 2.006, rests at 2.00 and fills 0.33 of 0.3333 of the candle volume
+        let order = exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2.006), dec!(1))
+            .unwrap();
+        assert_eq!(order.price, Some(dec!(2.00)));
+        exchange.tick().unwrap();
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(2.33));
+        assert_eq!(exchange.get_wallet()["USDT"], dec!(7.33));
+    }
+
+    #[test]
+    fn test_interleaved_feeds_tick_in_time_order() {
+        let hour = 3_600_000;
+        let mut exchange = btc_usdt_exchange(vec![
+            close_kline(hour - 1, dec!(3)),
+            close_kline(3 * hour - 1, dec!(1)),
+        ]);
+        let mut eth_feed = PriceFeed::new();
+        eth_feed.add_price_data(vec![
+            close_kline(2 * hour - 1, dec!(1)),
+            close_kline(4 * hour - 1, dec!(1)),
+        ]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .add_price_feed("ETHUSDT".to_string(), eth_feed);
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+        exchange
+            .place_limit_buy_order("ETHUSDT", dec!(2), dec!(1))
+            .unwrap();
+
+        let mut clock = vec![];
+        for _ in 0..4 {
+            exchange.tick().unwrap();
+            clock.push(exchange.now());
+        }
+        assert_eq!(
+            clock,
+            vec![hour - 1, 2 * hour - 1, 3 * hour - 1, 4 * hour - 1]
+        );
+
+        let fills: Vec<(&str, i64)> = exchange
+            .get_fills()
+            .iter()
+            .map(|fill| (fill.pair.as_str(), fill.ts))
+            .collect();
+        assert_eq!(
+            fills,
+            vec![("ETHUSDT", 2 * hour - 1), ("BTCUSDT", 3 * hour - 1)]
+        );
+        assert_eq!(exchange.bars_processed()["BTCUSDT"], 2);
+        assert_eq!(exchange.bars_processed()["ETHUSDT"], 2);
+    }
+
+    #[test]
+    fn test_post_only_order_rejected_on_cross() {
+        let mut exchange =
+            btc_usdt_exchange(vec![ohlc_kline(dec!(2.5), dec!(3), dec!(2), dec!(2.5))]);
+        exchange.with_capital(vec![("USDT".to_string(), dec!(10))]);
+        exchange.tick().unwrap();
+
+        assert!(matches!(
+            exchange.place_post_only_order("BTCUSDT", dec!(2.1), dec!(1), OrderDirection::Buy),
+            Err(ExchangeError::PostOnlyWouldCross)
+        ));
+        assert!(matches!(
+            exchange.place_post_only_order("BTCUSDT", dec!(2.9), dec!(1), OrderDirection::Sell),
+            Err(ExchangeError::PostOnlyWouldCross)
+        ));
+        assert!(exchange.get_orders().is_empty());
+
+        let order = exchange
+            .place_post_only_order("BTCUSDT", dec!(1.9), dec!(1), OrderDirection::Buy)
+            .unwrap();
+        let resting = exchange.get_order(order.id).unwrap();
+        assert!(resting.post_only);
+        assert_eq!(resting.status, OrderStatus::Pending);
+    }
+
+    #[test]
+    fn test_reduce_only_sell_capped_at_position() {
+        let mut exchange = btc_usdt_exchange(vec![
+            ohlc_kline(dec!(2.5), dec!(3.5), dec!(1.5), dec!(2.5)),
+            ohlc_kline(dec!(2.5), dec!(3.5), dec!(1.5), dec!(2.5)),
+        ]);
+        let order = exchange
+            .place_reduce_only_order("BTCUSDT", dec!(2), dec!(3), OrderDirection::Sell)
+            .unwrap();
+        assert_eq!(order.qty, dec!(1));
+        assert!(exchange.get_order(order.id).unwrap().reduce_only);
+
+        let filled = exchange.tick().unwrap();
+        assert_eq!(filled[0].id, order.id);
+        assert_eq!(filled[0].status, OrderStatus::Filled);
+        assert_eq!(filled[0].qty, dec!(1));
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(0));
+        assert_eq!(exchange.get_wallet()["USDT"], dec!(2));
+
+        // With the position already flat there's nothing to reduce
+        assert!(matches!(
+            exchange.place_reduce_only_order("BTCUSDT", dec!(2), dec!(1), OrderDirection::Sell),
+            Err(ExchangeError::InvalidAmount)
+        ));
+        assert!(exchange.get_orders()["BTCUSDT"].is_empty());
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(0));
+    }
+
+    #[test]
+    fn test_average_entry_price_over_accumulation() {
+        let flat = ohlc_kline(dec!(25), dec!(35), dec!(5), dec!(25));
+        let mut exchange = btc_usdt_exchange(vec![flat.clone(), flat.clone(), flat]);
+        exchange.with_capital(vec![("USDT".to_string(), dec!(1000))]);
+        assert_eq!(exchange.average_entry_price("BTCUSDT"), None);
+
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(10), dec!(1))
+            .unwrap();
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(20), dec!(3))
+            .unwrap();
+        exchange.tick().unwrap();
+        // (10 + 60) / 4, starting capital has no entry price
+        assert_eq!(exchange.average_entry_price("BTCUSDT"), Some(dec!(17.5)));
+
+        exchange
+            .place_limit_sell_order("BTCUSDT", dec!(30), dec!(2))
+            .unwrap();
+        exchange.tick().unwrap();
+        assert_eq!(exchange.average_entry_price("BTCUSDT"), Some(dec!(17.5)));
+
+        exchange
+            .place_limit_sell_order("BTCUSDT", dec!(30), dec!(2))
+            .unwrap();
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(6), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+        assert_eq!(exchange.average_entry_price("BTCUSDT"), Some(dec!(6)));
+    }
+
+    #[test]
+    fn test_equity_curve_snapshots_each_tick() {
+        let hour = 3_600_000;
+        let mut exchange = btc_usdt_exchange(vec![
+            close_kline(hour - 1, dec!(2)),
+            close_kline(2 * hour - 1, dec!(3)),
+            close_kline(3 * hour - 1, dec!(4)),
+        ]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .enable_equity_tracking("USDT");
+        exchange.tick().unwrap();
+        exchange
+            .place_market_buy_order("BTCUSDT", dec!(2), dec!(2))
+            .unwrap();
+        for _ in 0..3 {
+            exchange.tick().unwrap();
+        }
+
+        // Ticks past the end of the feed record nothing
+        assert_eq!(exchange.equity_curve().len(), 3);
+        // 3 BTC and 6 USDT marked at the last close
+        assert_eq!(exchange.equity_curve()[2], (3 * hour - 1, dec!(18)));
+    }
+
+    #[test]
+    fn test_get_balance_and_has_asset() {
+        let mut exchange = btc_usdt_exchange(vec![]);
+        exchange.with_capital(vec![("USDT".to_string(), dec!(0))]);
+        assert_eq!(exchange.get_balance("BTC"), dec!(1));
+        assert!(exchange.has_asset("BTC"));
+        assert_eq!(exchange.get_balance("USDT"), dec!(0));
+        assert!(!exchange.has_asset("USDT"));
+        assert_eq!(exchange.get_balance("ETH"), dec!(0));
+        assert!(!exchange.has_asset("ETH"));
+    }
+
+    #[test]
+    fn test_order_ids_are_per_exchange() {
+        let mut first = btc_usdt_exchange(vec![]);
+        let mut second = btc_usdt_exchange(vec![]);
+        let ids = |exchange: &mut Exchange| {
+            (0..2)
+                .map(|_| {
+                    exchange
+                        .place_limit_sell_order("BTCUSDT", dec!(3), dec!(0.1))
+                        .unwrap()
+                        .id
+                })
+                .collect::<Vec<u64>>()
+        };
+        assert_eq!(ids(&mut first), vec![1, 2]);
+        assert_eq!(ids(&mut second), vec![1, 2]);
+        assert_eq!(ids(&mut first), vec![3, 4]);
+    }
+
+    #[test]
+    fn test_cancel_all_orders_by_pair_and_instance() {
+        let mut exchange = btc_usdt_exchange(vec![]);
+        exchange.with_capital(vec![
+            ("USDT".to_string(), dec!(10)),
+            ("ETH".to_string(), dec!(2)),
+        ]);
+        let btc_orders = [
+            exchange
+                .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+                .unwrap(),
+            exchange
+                .place_limit_sell_order("BTCUSDT", dec!(5), dec!(1))
+                .unwrap(),
+        ];
+        let eth_orders = [
+            exchange
+                .place_limit_sell_order("ETHBTC", dec!(0.07), dec!(1))
+                .unwrap(),
+            exchange
+                .place_limit_sell_order("ETHBTC", dec!(0.08), dec!(1))
+                .unwrap(),
+        ];
+        assert_eq!(exchange.reserved("USDT"), dec!(2));
+        assert_eq!(exchange.reserved("ETH"), dec!(2));
+
+        assert!(exchange.cancel_all_orders("SOLUSDT").is_empty());
+        let cancelled = exchange.cancel_all_orders("BTCUSDT");
+        assert_eq!(
+            cancelled.iter().map(|order| order.id).collect::<Vec<u64>>(),
+            btc_orders
+                .iter()
+                .map(|order| order.id)
+                .collect::<Vec<u64>>()
+        );
+        assert!(cancelled
+            .iter()
+            .all(|order| order.status == OrderStatus::Cancelled));
+        assert_eq!(exchange.reserved("USDT"), dec!(0));
+        assert_eq!(exchange.buying_power("USDT"), dec!(10));
+        assert_eq!(exchange.reserved("ETH"), dec!(2));
+
+        let cancelled = exchange.cancel_all();
+        assert_eq!(
+            cancelled.iter().map(|order| order.id).collect::<Vec<u64>>(),
+            eth_orders
+                .iter()
+                .map(|order| order.id)
+                .collect::<Vec<u64>>()
+        );
+        assert_eq!(exchange.reserved("ETH"), dec!(0));
+        assert!(exchange
+            .get_orders()
+            .values()
+            .all(|orders| orders.is_empty()));
+        assert!(exchange.cancel_all().is_empty());
+    }
+
+    #[test]
+    fn test_place_orders_reports_each_result() {
+        let mut exchange = btc_usdt_exchange(vec![]);
+        exchange.with_capital(vec![("USDT".to_string(), dec!(5))]);
+        let results = exchange.place_orders(vec![
+            OrderRequest {
+                pair: "BTCUSDT".to_string(),
+                price: Some(dec!(2)),
+                qty: dec!(1),
+                direction: OrderDirection::Buy,
+                order_type: OrderType::Limit,
+            },
+            OrderRequest {
+                pair: "BTCUSDT".to_string(),
+                price: Some(dec!(2)),
+                qty: dec!(100),
+                direction: OrderDirection::Buy,
+                order_type: OrderType::Limit,
+            },
+            OrderRequest {
+                pair: "BTCUSDT".to_string(),
+                price: Some(dec!(3)),
+                qty: dec!(1),
+                direction: OrderDirection::Sell,
+                order_type: OrderType::Limit,
+            },
+        ]);
+
+        assert_eq!(results.len(), 3);
+        let buy = results[0].as_ref().unwrap();
+        assert_eq!(buy.price, Some(dec!(2)));
+        assert!(matches!(
+            results[1],
+            Err(ExchangeError::InsufficientFunds(ref pair)) if pair == "BTCUSDT"
+        ));
+        let sell = results[2].as_ref().unwrap();
+        assert_eq!(sell.direction, OrderDirection::Sell);
+        let resting: Vec<u64> = exchange.get_orders()["BTCUSDT"]
+            .iter()
+            .map(|order| order.id)
+            .collect();
+        assert_eq!(resting, vec![buy.id, sell.id]);
+    }
+
+    #[test]
+    fn test_open_orders_filtered_by_pair_direction_and_type() {
+        let mut exchange = btc_usdt_exchange(vec![]);
+        exchange.with_capital(vec![
+            ("USDT".to_string(), dec!(10)),
+            ("ETH".to_string(), dec!(2)),
+        ]);
+        let btc_buy = exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+        let btc_sell = exchange
+            .place_limit_sell_order("BTCUSDT", dec!(5), dec!(1))
+            .unwrap();
+        let btc_stop = exchange
+            .place_order(
+                "BTCUSDT",
+                Some(dec!(1)),
+                dec!(1),
+                OrderDirection::Sell,
+                OrderType::StopLoss,
+            )
+            .unwrap();
+        let eth_sell = exchange
+            .place_limit_sell_order("ETHBTC", dec!(0.07), dec!(1))
+            .unwrap();
+        let ids = |filter: OrderFilter| -> Vec<u64> {
+            exchange
+                .open_orders(filter)
+                .iter()
+                .map(|order| order.id)
+                .collect()
+        };
+
+        assert_eq!(
+            ids(OrderFilter::default()),
+            vec![btc_buy.id, btc_sell.id, btc_stop.id, eth_sell.id]
+        );
+        assert_eq!(
+            ids(OrderFilter {
+                pair: Some("BTCUSDT".to_string()),
+                ..OrderFilter::default()
+            }),
+            vec![btc_buy.id, btc_sell.id, btc_stop.id]
+        );
+        assert_eq!(
+            ids(OrderFilter {
+                direction: Some(OrderDirection::Sell),
+                ..OrderFilter::default()
+            }),
+            vec![btc_sell.id, btc_stop.id, eth_sell.id]
+        );
+        assert_eq!(
+            ids(OrderFilter {
+                order_type: Some(OrderType::StopLoss),
+                ..OrderFilter::default()
+            }),
+            vec![btc_stop.id]
+        );
+        assert_eq!(
+            ids(OrderFilter {
+                pair: Some("BTCUSDT".to_string()),
+                direction: Some(OrderDirection::Sell),
+                order_type: Some(OrderType::Limit),
+            }),
+            vec![btc_sell.id]
+        );
+        assert!(ids(OrderFilter {
+            pair: Some("ETHBTC".to_string()),
+            direction: Some(OrderDirection::Buy),
+            ..OrderFilter::default()
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn test_market_preview_matches_the_placed_order() {
+        let mut exchange = btc_usdt_exchange(vec![ohlc_kline(dec!(2), dec!(2), dec!(2), dec!(2))]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .with_fees(FeeSchedule::new(dec!(0.001), dec!(0.002)))
+            .with_slippage_model(SlippageModel::FixedBps(dec!(3)))
+            .with_symbol_filters(
+                "BTCUSDT",
+                SymbolFilters::new(dec!(0), dec!(0.01), dec!(0.01)),
+            );
+        exchange.tick().unwrap();
+        let request = |qty, direction| OrderRequest {
+            pair: "BTCUSDT".to_string(),
+            price: None,
+            qty,
+            direction,
+            order_type: OrderType::Market,
+        };
+
+        let preview = exchange
+            .simulate_order(&request(dec!(2.005), OrderDirection::Buy))
+            .unwrap();
+        exchange
+            .place_market_buy_order("BTCUSDT", dec!(2), dec!(2.005))
+            .unwrap();
+        let fill = &exchange.get_fills()[0];
+        assert_eq!((preview.price, preview.qty), (fill.price, fill.qty));
+        assert_eq!((preview.price, preview.qty), (dec!(2.01), dec!(2)));
+        assert_eq!(preview.fee, fill.fee_in_quote());
+
+        // An unfunded preview is rejected the same way the order is
+        let previewed = exchange.simulate_order(&request(dec!(5), OrderDirection::Sell));
+        let placed = exchange.place_market_sell_order("BTCUSDT", dec!(2), dec!(5));
+        assert!(matches!(
+            (previewed, placed),
+            (
+                Err(ExchangeError::InsufficientFunds(previewed)),
+                Err(ExchangeError::InsufficientFunds(placed)),
+            ) if previewed == "BTCUSDT" && placed == "BTCUSDT"
+        ));
+    }
+
+    #[test]
+    fn test_simulate_order_previews_without_placing() {
+        let mut exchange =
+            btc_usdt_exchange(vec![ohlc_kline(dec!(10), dec!(10), dec!(10), dec!(10))]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(100))])
+            .with_fees(FeeSchedule::new(dec!(0.001), dec!(0.002)))
+            .with_spread(dec!(20));
+        exchange.tick().unwrap();
+        let wallet = exchange.get_wallet().clone();
+        let request = |price, qty, direction, order_type| OrderRequest {
+            pair: "BTCUSDT".to_string(),
+            price,
+            qty,
+            direction,
+            order_type,
+        };
+
+        // Market buys pay the ask, half the 20bps spread above the close, as takers
+        let preview = exchange
+            .simulate_order(&request(
+                None,
+                dec!(2),
+                OrderDirection::Buy,
+                OrderType::Market,
+            ))
+            .unwrap();
+        assert_eq!(preview.price, dec!(10.01));
+        assert_eq!(preview.notional, dec!(20.02));
+        assert_eq!(preview.fee, dec!(0.04004));
+
+        // Limit orders rest as makers at their limit
+        let preview = exchange
+            .simulate_order(&request(
+                Some(dec!(9)),
+                dec!(2),
+                OrderDirection::Buy,
+                OrderType::Limit,
+            ))
+            .unwrap();
+        assert_eq!(preview.price, dec!(9));
+        assert_eq!(preview.fee, dec!(0.018));
+
+        assert!(matches!(
+            exchange.simulate_order(&request(
+                Some(dec!(9)),
+                dec!(20),
+                OrderDirection::Buy,
+                OrderType::Limit,
+            )),
+            Err(ExchangeError::InsufficientFunds(_))
+        ));
+        assert!(matches!(
+            exchange.simulate_order(&request(
+                None,
+                dec!(2),
+                OrderDirection::Sell,
+                OrderType::Market,
+            )),
+            Err(ExchangeError::InsufficientFunds(_))
+        ));
+
+        assert_eq!(exchange.get_wallet(), &wallet);
+        assert!(exchange
+            .get_orders()
+            .values()
+            .all(|orders| orders.is_empty()));
+        assert!(exchange.get_fills().is_empty());
+        let order = exchange
+            .place_market_buy_order("BTCUSDT", dec!(10), dec!(2))
+            .unwrap();
+        assert_eq!(order.id, 1);
+        assert_eq!(order.price, Some(dec!(10.01)));
+    }
+
+    #[test]
+    fn test_fee_tier_drops_once_volume_crosses_threshold() {
+        let bar = ohlc_kline(dec!(5), dec!(5), dec!(5), dec!(5));
+        let mut exchange = btc_usdt_exchange(vec![bar.clone(), bar]);
+        let mut fees = FeeSchedule::new(dec!(0.002), dec!(0.004));
+        fees.with_tiers(vec![
+            (dec!(10), dec!(0.001), dec!(0.002)),
+            (dec!(1000), dec!(0), dec!(0.001)),
+        ]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(100))])
+            .with_fill_condition(FillCondition::Touch)
+            .with_fees(fees);
+        assert_eq!(exchange.current_fee_tier("USDT").maker, dec!(0.002));
+
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(5), dec!(2))
+            .unwrap();
+        exchange.tick().unwrap();
+        assert_eq!(exchange.traded_volume("USDT"), dec!(10));
+        assert_eq!(exchange.get_fills()[0].fee, dec!(0.004));
+        let tier = exchange.current_fee_tier("USDT");
+        assert_eq!(tier.volume_threshold, dec!(10));
+        assert_eq!(tier.maker, dec!(0.001));
+
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(5), dec!(2))
+            .unwrap();
+        exchange.tick().unwrap();
+        assert_eq!(exchange.get_fills()[1].fee, dec!(0.002));
+        assert_eq!(exchange.traded_volume("USDT"), dec!(20));
+    }
+
+    #[test]
+    fn test_traded_volume_is_tracked_per_quote() {
+        let bar = ohlc_kline(dec!(5), dec!(5), dec!(5), dec!(5));
+        let mut exchange = btc_usdt_exchange(vec![bar.clone()]);
+        let mut price_feed = PriceFeed::new();
+        price_feed.add_price_data(vec![bar]);
+        let mut fees = FeeSchedule::new(dec!(0.002), dec!(0.004));
+        fees.with_tiers(vec![(dec!(10), dec!(0.001), dec!(0.002))]);
+        exchange
+            .with_capital(vec![
+                ("USDT".to_string(), dec!(100)),
+                ("BTC".to_string(), dec!(100)),
+            ])
+            .add_price_feed("ETHBTC".to_string(), price_feed)
+            .with_fill_condition(FillCondition::Touch)
+            .with_fees(fees);
+
+        exchange
+            .place_limit_buy_order("ETHBTC", dec!(5), dec!(4))
+            .unwrap();
+        exchange.tick().unwrap();
+        assert_eq!(exchange.traded_volume("BTC"), dec!(20));
+        assert_eq!(exchange.traded_volume("USDT"), dec!(0));
+        assert_eq!(exchange.current_fee_tier("BTC").maker, dec!(0.001));
+        assert_eq!(exchange.current_fee_tier("USDT").maker, dec!(0.002));
+    }
+
+    #[test]
+    fn test_fill_books_trade_and_fee_transactions() {
+        let mut exchange = btc_usdt_exchange(vec![ohlc_kline(dec!(5), dec!(5), dec!(5), dec!(5))]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(100))])
+            .with_fill_condition(FillCondition::Touch)
+            .with_fees(FeeSchedule::new(dec!(0.001), dec!(0.002)));
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(5), dec!(2))
+            .unwrap();
+        exchange.tick().unwrap();
+
+        let booked: Vec<(String, Decimal, TransactionKind)> = exchange
+            .get_transactions()
+            .iter()
+            .filter(|transaction| *transaction.get_ts() != 0)
+            .map(|transaction| {
+                (
+                    transaction.get_symbol().clone(),
+                    *transaction.get_qty(),
+                    transaction.get_kind(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            booked,
+            vec![
+                ("BTC".to_string(), dec!(2), TransactionKind::Trade),
+                ("USDT".to_string(), dec!(-10), TransactionKind::Trade),
+                ("BTC".to_string(), dec!(-0.002), TransactionKind::Fee),
+            ]
+        );
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(2.998));
+        assert!(exchange.wallet.verify_integrity().is_ok());
+    }
+
+    #[test]
+    fn test_funds_check_includes_the_fee() {
+        let mut exchange = Exchange::new();
+        let mut price_feed = PriceFeed::new();
+        price_feed.add_price_data(vec![ohlc_kline(dec!(5), dec!(5), dec!(5), dec!(5)); 3]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(20))])
+            .with_fill_condition(FillCondition::Touch)
+            .with_fees(FeeSchedule::new(dec!(0.01), dec!(0.02)))
+            .add_price_feed("BTCUSDT".to_string(), price_feed);
+        exchange.enable_short_selling();
+        exchange
+            .place_limit_sell_order("BTCUSDT", dec!(5), dec!(2))
+            .unwrap();
+        exchange.tick().unwrap();
+        assert_eq!(exchange.get_balance("BTC"), dec!(-2));
+
+        // The buy fee comes out of the BTC bought, buying back exactly the short would leave
+        // the fee short, at the maker rate for a limit and the taker rate for a market order
+        assert!(matches!(
+            exchange.place_limit_buy_order("BTCUSDT", dec!(5), dec!(2)),
+            Err(ExchangeError::InsufficientFunds(_))
+        ));
+        assert!(matches!(
+            exchange.place_market_buy_order("BTCUSDT", dec!(5), dec!(2.03)),
+            Err(ExchangeError::InsufficientFunds(_))
+        ));
+        let order = exchange
+            .place_market_buy_order("BTCUSDT", dec!(5), dec!(2.05))
+            .unwrap();
+        assert_eq!(order.status, OrderStatus::Filled);
+        let fill = exchange.get_fills().last().unwrap();
+        assert_eq!(fill.fee, dec!(2.05) * dec!(0.02));
+        assert_eq!(exchange.get_balance("BTC"), dec!(2.05) - fill.fee - dec!(2));
+        assert!(exchange.get_balance("BTC") >= dec!(0));
+
+        // Without a short, the whole quote balance can be spent, the fee is taken in BTC
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(5), exchange.get_balance("USDT") / dec!(5))
+            .unwrap();
+        exchange.tick().unwrap();
+        assert_eq!(exchange.get_balance("USDT"), dec!(0));
+        assert!(exchange.wallet.verify_integrity().is_ok());
+    }
+
+    #[test]
+    fn test_custom_quote_asset() {
+        let mut exchange = Exchange::new();
+        assert!(exchange.get_asset_pair("BARFOO").is_err());
+        exchange.with_quote_asset("FOO").with_quote_asset("FOO");
+        assert_eq!(exchange.get_asset_pair("BARFOO").unwrap(), ("BAR", "FOO"));
+        assert_eq!(exchange.get_asset_pair("BTCUSDT").unwrap(), ("BTC", "USDT"));
+
+        // Orders on the new pair draw on the custom quote
+        exchange.with_capital(vec![("FOO".to_string(), dec!(10))]);
+        exchange
+            .place_limit_buy_order("BARFOO", dec!(2), dec!(3))
+            .unwrap();
+        assert_eq!(exchange.reserved("FOO"), dec!(6));
+    }
+
+    #[test]
+    fn test_extract_quote_and_base_with_separators() {
+        let exchange = Exchange::new();
+        assert_eq!(
+            exchange.get_asset_pair("BTC/USDT").unwrap(),
+            ("BTC", "USDT")
+        );
+        assert_eq!(
+            exchange.get_asset_pair("BTC-USDT").unwrap(),
+            ("BTC", "USDT")
+        );
+        // No quote list lookup, so unknown quotes split too
+        assert_eq!(exchange.get_asset_pair("BAR/FOO").unwrap(), ("BAR", "FOO"));
+        assert_eq!(exchange.get_asset_pair("BTCUSDT").unwrap(), ("BTC", "USDT"));
+        for malformed in ["/USDT", "BTC-", "BTC/USDT/ETH", "BTC-USDT/ETH"] {
+            assert!(exchange.get_asset_pair(malformed).is_err(), "{}", malformed);
+        }
+    }
+
+    struct MockKlineSocket(std::sync::mpsc::Receiver<String>);
+
+    impl KlineSource for MockKlineSocket {
+        fn next_message(&mut self) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+            Ok(self.0.recv().ok())
+        }
+    }
+
+    fn kline_event(open_ts: i64, low: &str, closed: bool) -> String {
+        format!(
+            r#"{{"e":"kline","E":0,"s":"BTCUSDT","k":{{"t":{},"T":{},"s":"BTCUSDT","i":"1m","f":0,"L":0,"o":"3","c":"3","h":"3.5","l":"{}","v":"100","n":10,"x":{},"q":"300","V":"50","Q":"150","B":"0"}}}}"#,
+            open_ts,
+            open_ts + 59_999,
+            low,
+            closed
+        )
+    }
+
+    #[test]
+    fn test_streamed_candles_flow_through_tick() {
+        let (socket, messages) = std::sync::mpsc::channel();
+        let mut exchange = Exchange::new();
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(10))])
+            .add_price_feed(
+                "BTCUSDT".to_string(),
+                PriceFeed::stream_from(MockKlineSocket(messages)),
+            );
+        let order = exchange
+            .place_limit_buy_order("BTCUSDT", dec!(2), dec!(1))
+            .unwrap();
+        // Nothing has arrived yet
+        assert!(exchange.tick().unwrap().is_empty());
+        assert_eq!(exchange.now(), 0);
+
+        let tick_until = |exchange: &mut Exchange, close_ts: i64| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            let mut filled = vec![];
+            while exchange.now() < close_ts && Instant::now() < deadline {
+                filled.extend(exchange.tick().unwrap());
+                thread::sleep(Duration::from_millis(1));
+            }
+            assert_eq!(exchange.now(), close_ts);
+            filled
+        };
+
+        // Updates of a candle that's still open are ignored
+        socket.send(kline_event(0, "1", false)).unwrap();
+        socket.send(kline_event(0, "2.5", true)).unwrap();
+        assert!(tick_until(&mut exchange, 59_999).is_empty());
+
+        socket.send(kline_event(60_000, "1.5", true)).unwrap();
+        let filled = tick_until(&mut exchange, 119_999);
+        assert_eq!(filled.len(), 1);
+        assert_eq!(filled[0].id, order.id);
+        assert_eq!(exchange.get_wallet()["BTC"], dec!(1));
+        assert_eq!(exchange.bars_processed()["BTCUSDT"], 2);
+    }
+
+    #[test]
+    fn test_position_accumulates_and_partially_closes() {
+        let flat = ohlc_kline(dec!(25), dec!(35), dec!(5), dec!(25));
+        let mut exchange = btc_usdt_exchange(vec![flat.clone(), flat.clone(), flat]);
+        exchange.with_capital(vec![("USDT".to_string(), dec!(1000))]);
+        assert_eq!(exchange.position("BTCUSDT"), Position::default());
+
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(10), dec!(1))
+            .unwrap();
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(20), dec!(3))
+            .unwrap();
+        exchange.tick().unwrap();
+        // Marked at the close of 25: 4 * (25 - 17.5)
+        assert_eq!(
+            exchange.position("BTCUSDT"),
+            Position {
+                qty: dec!(4),
+                avg_entry: dec!(17.5),
+                unrealized_pnl: dec!(30),
+            }
+        );
+
+        exchange
+            .place_limit_sell_order("BTCUSDT", dec!(30), dec!(3))
+            .unwrap();
+        exchange.tick().unwrap();
+        assert_eq!(
+            exchange.position("BTCUSDT"),
+            Position {
+                qty: dec!(1),
+                avg_entry: dec!(17.5),
+                unrealized_pnl: dec!(7.5),
+            }
+        );
+        // The wallet still holds the starting BTC on top of the position
+        assert_eq!(exchange.get_balance("BTC"), dec!(2));
+    }
+
+    #[test]
+    fn test_leverage_allows_buys_beyond_the_balance() {
+        let flat = ohlc_kline(dec!(100), dec!(100), dec!(100), dec!(100));
+        let mut price_feed = PriceFeed::new();
+        price_feed.add_price_data(vec![flat.clone(), flat.clone(), flat]);
+        let mut exchange = Exchange::new();
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(100))])
+            .with_fill_condition(FillCondition::Touch)
+            .add_price_feed("BTCUSDT".to_string(), price_feed);
+        exchange.tick().unwrap();
+
+        // 300 USDT of BTC is out of reach at 1x
+        assert_eq!(exchange.max_position_size("USDT").unwrap(), dec!(100));
+        assert!(matches!(
+            exchange.place_limit_buy_order("BTCUSDT", dec!(100), dec!(3)),
+            Err(ExchangeError::InsufficientFunds(_))
+        ));
+
+        exchange.with_leverage("USDT", dec!(3));
+        assert_eq!(exchange.max_position_size("USDT").unwrap(), dec!(300));
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(100), dec!(3))
+            .unwrap();
+        exchange.tick().unwrap();
+        assert_eq!(exchange.get_balance("BTC"), dec!(3));
+        assert_eq!(exchange.get_balance("USDT"), dec!(0));
+        assert_eq!(exchange.borrowed("USDT"), dec!(200));
+        // Equity of 300 - 200 is fully used
+        assert_eq!(exchange.max_position_size("USDT").unwrap(), dec!(0));
+        assert!(exchange.wallet.verify_integrity().is_ok());
+
+        exchange
+            .place_limit_sell_order("BTCUSDT", dec!(100), dec!(3))
+            .unwrap();
+        exchange.tick().unwrap();
+        assert!(matches!(
+            exchange.repay("USDT", dec!(250)),
+            Err(ExchangeError::InvalidAmount)
+        ));
+        exchange.repay("USDT", dec!(200)).unwrap();
+        assert_eq!(exchange.get_balance("USDT"), dec!(100));
+        assert_eq!(exchange.borrowed("USDT"), dec!(0));
+    }
+
+    #[test]
+    fn test_short_selling_opens_and_covers_a_short() {
+        let mut exchange = Exchange::new();
+        let mut price_feed = PriceFeed::new();
+        price_feed.add_price_data(vec![
+            ohlc_kline(dec!(100), dec!(100), dec!(100), dec!(100)),
+            ohlc_kline(dec!(80), dec!(80), dec!(80), dec!(80)),
+        ]);
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(100))])
+            .with_fill_condition(FillCondition::Touch)
+            .add_price_feed("BTCUSDT".to_string(), price_feed);
+        assert!(matches!(
+            exchange.place_limit_sell_order("BTCUSDT", dec!(100), dec!(1)),
+            Err(ExchangeError::InsufficientFunds(_))
+        ));
+
+        exchange.enable_short_selling();
+        // The equity of 100 USDT backs a short worth 100 at most
+        assert!(matches!(
+            exchange.place_limit_sell_order("BTCUSDT", dec!(100), dec!(2)),
+            Err(ExchangeError::InsufficientFunds(_))
+        ));
+        exchange
+            .place_limit_sell_order("BTCUSDT", dec!(100), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+        assert_eq!(exchange.get_balance("BTC"), dec!(-1));
+        assert_eq!(exchange.get_balance("USDT"), dec!(200));
+        assert_eq!(exchange.position("BTCUSDT").qty, dec!(-1));
+        assert_eq!(exchange.borrowed("BTC"), dec!(1));
+        assert!(matches!(
+            exchange.repay("BTC", dec!(1)),
+            Err(ExchangeError::InsufficientFunds(_))
+        ));
+        assert!(exchange.wallet.verify_integrity().is_ok());
+
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(80), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+        assert_eq!(exchange.get_balance("BTC"), dec!(0));
+        assert_eq!(exchange.borrowed("BTC"), dec!(0));
+        assert_eq!(exchange.get_balance("USDT"), dec!(120));
+        assert_eq!(exchange.realized_pnl("USDT")["BTC"], dec!(20));
+    }
+
+    #[test]
+    fn test_leveraged_position_is_liquidated_below_maintenance_margin() {
+        // However the pair is written, the position is valued and liquidated through its feed
+        for pair in ["BTCUSDT", "BTC/USDT"] {
+            let mut price_feed = PriceFeed::new();
+            price_feed.add_price_data(
+                [dec!(100), dec!(90), dec!(75), dec!(70)]
+                    .iter()
+                    .map(|&price| ohlc_kline(price, price, price, price))
+                    .collect(),
+            );
+            let mut exchange = Exchange::new();
+            exchange
+                .with_capital(vec![("USDT".to_string(), dec!(100))])
+                .with_fill_condition(FillCondition::Touch)
+                .add_price_feed(pair.to_string(), price_feed);
+            exchange
+                .with_leverage("USDT", dec!(3))
+                .with_maintenance_margin(dec!(0.25));
+            exchange
+                .place_limit_buy_order(pair, dec!(100), dec!(3))
+                .unwrap();
+            exchange.tick().unwrap();
+            assert_eq!(exchange.borrowed("USDT"), dec!(200));
+            assert_eq!(
+                exchange.position_notional("BTC", "USDT").unwrap(),
+                dec!(300)
+            );
+
+            // Equity of 270 - 200 is still above a quarter of 270
+            let filled = exchange.tick().unwrap();
+            assert!(filled.is_empty());
+            assert_eq!(exchange.get_balance("BTC"), dec!(3));
+
+            // 225 - 200 is below a quarter of 225: sold at the close and the loan repaid
+            let filled = exchange.tick().unwrap();
+            assert_eq!(filled.len(), 1);
+            assert_eq!(filled[0].direction, OrderDirection::Sell);
+            assert_eq!(filled[0].price, Some(dec!(75)));
+            assert_eq!(exchange.get_balance("BTC"), dec!(0));
+            assert_eq!(exchange.borrowed("USDT"), dec!(0));
+            assert_eq!(exchange.get_balance("USDT"), dec!(25));
+            assert!(exchange.wallet.verify_integrity().is_ok());
+
+            // Nothing left to liquidate
+            assert!(exchange.tick().unwrap().is_empty());
+            assert_eq!(exchange.get_balance("USDT"), dec!(25));
+        }
+    }
+
+    #[test]
+    fn test_liquidation_sells_on_the_margin_quote_pair() {
+        let feed = || {
+            let mut price_feed = PriceFeed::new();
+            price_feed.add_price_data(
+                [dec!(100), dec!(75)]
+                    .iter()
+                    .map(|&price| ohlc_kline(price, price, price, price))
+                    .collect(),
+            );
+            price_feed
+        };
+        let mut exchange = Exchange::new();
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(100))])
+            .with_fill_condition(FillCondition::Touch)
+            .add_price_feed("BTCBUSD".to_string(), feed())
+            .add_price_feed("BTCUSDT".to_string(), feed());
+        exchange
+            .with_leverage("USDT", dec!(3))
+            .with_maintenance_margin(dec!(0.25));
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(100), dec!(3))
+            .unwrap();
+        exchange.tick().unwrap();
+        assert_eq!(exchange.borrowed("USDT"), dec!(200));
+
+        // BTCBUSD sorts first, but the proceeds have to land in USDT to repay the loan
+        let filled = exchange.tick().unwrap();
+        assert_eq!(filled.len(), 1);
+        assert_eq!(filled[0].pair, "BTCUSDT");
+        assert_eq!(exchange.get_balance("BTC"), dec!(0));
+        assert_eq!(exchange.get_balance("BUSD"), dec!(0));
+        assert_eq!(exchange.borrowed("USDT"), dec!(0));
+        assert_eq!(exchange.get_balance("USDT"), dec!(25));
+    }
+
+    #[test]
+    fn test_short_is_liquidated_below_maintenance_margin() {
+        let mut price_feed = PriceFeed::new();
+        price_feed.add_price_data(
+            [dec!(100), dec!(150), dec!(250), dec!(250)]
+                .iter()
+                .map(|&price| ohlc_kline(price, price, price, price))
+                .collect(),
+        );
+        let mut exchange = Exchange::new();
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(100))])
+            .with_fill_condition(FillCondition::Touch)
+            .add_price_feed("BTCUSDT".to_string(), price_feed);
+        exchange
+            .enable_short_selling()
+            .with_maintenance_margin(dec!(0.25));
+        exchange
+            .place_limit_sell_order("BTCUSDT", dec!(100), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+        assert_eq!(exchange.get_balance("BTC"), dec!(-1));
+
+        // Equity of 200 - 150 is still above a quarter of 150
+        assert!(exchange.tick().unwrap().is_empty());
+
+        // 200 - 250 is below it: bought back at the close, borrowing what 200 USDT can't pay
+        let filled = exchange.tick().unwrap();
+        assert_eq!(filled.len(), 1);
+        assert_eq!(filled[0].direction, OrderDirection::Buy);
+        assert_eq!(filled[0].price, Some(dec!(250)));
+        assert_eq!(exchange.get_balance("BTC"), dec!(0));
+        assert_eq!(exchange.get_balance("USDT"), dec!(0));
+        assert_eq!(exchange.borrowed("USDT"), dec!(50));
+        assert!(exchange.wallet.verify_integrity().is_ok());
+
+        assert!(exchange.tick().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_interest_accrues_on_a_borrowed_balance() {
+        let hour = 3_600_000;
+        let mut exchange = btc_usdt_exchange(
+            (1..=4)
+                .map(|i| close_kline(i * hour - 1, dec!(100)))
+                .collect(),
+        );
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(100))])
+            .with_fill_condition(FillCondition::Touch)
+            .with_leverage("USDT", dec!(3))
+            .with_borrow_interest(dec!(0.001));
+        exchange.tick().unwrap();
+        // 1 BTC of capital and 100 USDT back a buy of 2 more
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(100), dec!(2))
+            .unwrap();
+        exchange.tick().unwrap();
+        assert_eq!(exchange.borrowed("USDT"), dec!(100));
+        exchange.deposit("USDT", dec!(10)).unwrap();
+
+        // 0.1% an hour of 100 over two more hourly bars
+        exchange.tick().unwrap();
+        exchange.tick().unwrap();
+        assert_eq!(exchange.get_balance("USDT"), dec!(9.8));
+        assert_eq!(exchange.borrowed("USDT"), dec!(100));
+        let charges = exchange
+            .get_transactions()
+            .iter()
+            .filter(|tx| tx.get_kind() == TransactionKind::Interest)
+            .count();
+        assert_eq!(charges, 2);
+        assert!(exchange.wallet.verify_integrity().is_ok());
+    }
+
+    #[test]
+    fn test_interest_accrues_on_a_short_without_leverage() {
+        let hour = 3_600_000;
+        let mut price_feed = PriceFeed::new();
+        price_feed.add_price_data(
+            (1..=3)
+                .map(|i| close_kline(i * hour - 1, dec!(100)))
+                .collect(),
+        );
+        let mut exchange = Exchange::new();
+        exchange
+            .with_capital(vec![("USDT".to_string(), dec!(100))])
+            .with_fill_condition(FillCondition::Touch)
+            .add_price_feed("BTCUSDT".to_string(), price_feed);
+        exchange
+            .enable_short_selling()
+            .with_borrow_interest(dec!(0.001));
+        exchange
+            .place_limit_sell_order("BTCUSDT", dec!(100), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+        assert_eq!(exchange.get_balance("BTC"), dec!(-1));
+        assert_eq!(exchange.get_balance("USDT"), dec!(200));
+
+        // 0.1% an hour of the 100 USDT held short over two more hourly bars
+        exchange.tick().unwrap();
+        exchange.tick().unwrap();
+        assert_eq!(exchange.get_balance("USDT"), dec!(199.8));
+        assert!(exchange.wallet.verify_integrity().is_ok());
+    }
+
+    #[test]
+    fn test_cost_basis_from_fills() {
+        let flat = ohlc_kline(dec!(25), dec!(35), dec!(5), dec!(25));
+        let mut exchange = btc_usdt_exchange(vec![flat.clone(), flat.clone(), flat]);
+        exchange.with_capital(vec![("USDT".to_string(), dec!(1000))]);
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(10), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+        exchange
+            .place_limit_buy_order("BTCUSDT", dec!(20), dec!(1))
+            .unwrap();
+        exchange.tick().unwrap();
+        exchange
+            .place_limit_sell_order("BTCUSDT", dec!(30), dec!(1.5))
+            .unwrap();
+        exchange.tick().unwrap();
+
+        // 1 @ 10 + 0.5 @ 20 against 1 @ 20 + 0.5 @ 10, the starting BTC has no lot
+        let fifo = exchange.cost_basis("BTC", LotMatching::Fifo);
+        assert_eq!(fifo.total_gain(), dec!(25));
+        let lifo = exchange.cost_basis("BTC", LotMatching::Lifo);
+        assert_eq!(lifo.total_gain(), dec!(20));
+        assert_eq!(lifo.open_qty(), dec!(0.5));
+    }
 }
@@ -0,0 +1,80 @@
+use rust_decimal::prelude::Decimal;
+use serde::{Deserialize, Serialize};
+
+/**
+FeeSchedule holds the trading fee rates charged by the exchange, as fractions of the traded
+amount (0.001 is 0.1%). Orders resting on the book pay the maker rate when they fill, orders
+that take liquidity immediately pay the taker rate. The default schedule charges nothing.
+
+Tiers discount the rates by traded volume: once the exchange's cumulative fill notional reaches
+a tier's threshold its rates replace the base maker and taker rates.
+*/
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct FeeSchedule {
+    pub maker: Decimal,
+    pub taker: Decimal,
+    #[serde(default)]
+    pub tiers: Vec<FeeTier>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct FeeTier {
+    pub volume_threshold: Decimal,
+    pub maker: Decimal,
+    pub taker: Decimal,
+}
+
+impl FeeSchedule {
+    pub fn new(maker: Decimal, taker: Decimal) -> Self {
+        FeeSchedule {
+            maker,
+            taker,
+            tiers: vec![],
+        }
+    }
+    /// Adds `(volume_threshold, maker, taker)` tiers, rates in the same units as the base rates.
+    pub fn with_tiers(&mut self, tiers: Vec<(Decimal, Decimal, Decimal)>) -> &mut Self {
+        self.tiers.extend(
+            tiers
+                .into_iter()
+                .map(|(volume_threshold, maker, taker)| FeeTier {
+                    volume_threshold,
+                    maker,
+                    taker,
+                }),
+        );
+        self
+    }
+    /// The tier with the highest threshold `volume` has reached, the base rates below them all.
+    pub fn tier_for(&self, volume: Decimal) -> FeeTier {
+        self.tiers
+            .iter()
+            .filter(|tier| tier.volume_threshold <= volume)
+            .max_by_key(|tier| tier.volume_threshold)
+            .cloned()
+            .unwrap_or(FeeTier {
+                volume_threshold: Decimal::ZERO,
+                maker: self.maker,
+                taker: self.taker,
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_tier_for_picks_highest_reached_threshold() {
+        let mut fees = FeeSchedule::new(dec!(0.002), dec!(0.004));
+        fees.with_tiers(vec![
+            (dec!(1000), dec!(0), dec!(0.001)),
+            (dec!(10), dec!(0.001), dec!(0.002)),
+        ]);
+        assert_eq!(fees.tier_for(dec!(9.99)).taker, dec!(0.004));
+        assert_eq!(fees.tier_for(dec!(10)).taker, dec!(0.002));
+        assert_eq!(fees.tier_for(dec!(999)).taker, dec!(0.002));
+        assert_eq!(fees.tier_for(dec!(5000)).taker, dec!(0.001));
+    }
+}
@@ -0,0 +1,92 @@
+use crate::exchange::order::{Order, OrderDirection};
+use rust_decimal::prelude::Decimal;
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
+
+/// Which price a resting limit order is credited with when the bar trades through it.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum FillModel {
+    // Always fill at the limit price.
+    #[default]
+    AtLimit,
+    // Fill at the bar's open when it gaps past the limit (below it for buys, above it for
+    // sells), otherwise at the limit price.
+    AtCandle,
+}
+
+/// When a bar counts as reaching a resting limit order's price.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum FillCondition {
+    // The bar's low (for buys) or high (for sells) only has to touch the limit.
+    Touch,
+    // The bar has to trade through the limit.
+    #[default]
+    Cross,
+}
+
+impl FillCondition {
+    pub fn reached(
+        &self,
+        bar_price: Decimal,
+        limit_price: Decimal,
+        direction: &OrderDirection,
+    ) -> bool {
+        match (self, direction) {
+            (FillCondition::Touch, OrderDirection::Buy) => bar_price <= limit_price,
+            (FillCondition::Touch, OrderDirection::Sell) => bar_price >= limit_price,
+            (FillCondition::Cross, OrderDirection::Buy) => bar_price < limit_price,
+            (FillCondition::Cross, OrderDirection::Sell) => bar_price > limit_price,
+        }
+    }
+}
+
+/**
+A Fill records a single execution of an order: when it happened, at what price, for how much
+and the fee that was charged on it. The fee is denominated in the asset received by the fill,
+so the base asset for buys and the quote asset for sells.
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Fill {
+    pub ts: i64,
+    pub order_id: u64,
+    pub pair: String,
+    pub direction: OrderDirection,
+    pub price: Decimal,
+    pub qty: Decimal,
+    pub fee: Decimal,
+}
+
+impl Fill {
+    pub fn new(ts: i64, order: &Order, price: Decimal, qty: Decimal) -> Self {
+        Fill {
+            ts,
+            order_id: order.id,
+            pair: order.pair.clone(),
+            direction: order.direction.clone(),
+            price,
+            qty,
+            fee: dec!(0),
+        }
+    }
+    /// Charges `fee_rate` on the asset this fill receives.
+    pub fn with_fee_rate(mut self, fee_rate: Decimal) -> Self {
+        self.fee = self.received_qty() * fee_rate;
+        self
+    }
+    /// The amount of the received asset before fees: base for buys, quote for sells.
+    pub fn received_qty(&self) -> Decimal {
+        match self.direction {
+            OrderDirection::Buy => self.qty,
+            OrderDirection::Sell => self.notional(),
+        }
+    }
+    pub fn notional(&self) -> Decimal {
+        self.price * self.qty
+    }
+    pub fn fee_in_quote(&self) -> Decimal {
+        match self.direction {
+            OrderDirection::Buy => self.fee * self.price,
+            OrderDirection::Sell => self.fee,
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use crate::exchange::price_feed::{BinanceKline, PriceFeedError};
+use rust_decimal::prelude::Decimal;
+use serde::Deserialize;
+use std::error::Error;
+
+/**
+A KlineSource yields the raw messages of a live kline stream, one Binance kline event per
+message. `PriceFeed::stream_from` reads it on a background thread and buffers the closed
+candles for `next()`. BinanceKlineStream, behind the `websocket` feature, reads Binance's
+WebSocket API, tests can hand in canned messages instead.
+*/
+pub trait KlineSource: Send + 'static {
+    /// The next message, `None` once the stream has ended.
+    fn next_message(&mut self) -> Result<Option<String>, Box<dyn Error + Send + Sync>>;
+}
+
+#[derive(Deserialize)]
+struct KlineEvent {
+    k: StreamedKline,
+}
+
+// The kline payload of a stream event, see Binance's "Kline/Candlestick Streams".
+#[derive(Deserialize)]
+struct StreamedKline {
+    #[serde(rename = "t")]
+    open_timestamp: i64,
+    #[serde(rename = "T")]
+    close_timestamp: i64,
+    #[serde(rename = "o")]
+    open: Decimal,
+    #[serde(rename = "h")]
+    high: Decimal,
+    #[serde(rename = "l")]
+    low: Decimal,
+    #[serde(rename = "c")]
+    close: Decimal,
+    #[serde(rename = "v")]
+    volume: Decimal,
+    #[serde(rename = "n")]
+    trades: i32,
+    #[serde(rename = "x")]
+    closed: bool,
+    #[serde(rename = "q")]
+    quote_volume: String,
+    #[serde(rename = "V")]
+    bid_volume: String,
+    #[serde(rename = "Q")]
+    ask_volume: String,
+}
+
+/// The candle in a kline stream event, `None` while the candle is still open since only closed
+/// candles are final.
+pub fn parse_kline_event(message: &str) -> Result<Option<BinanceKline>, PriceFeedError> {
+    let event: KlineEvent = serde_json::from_str(message)
+        .map_err(|error| PriceFeedError::MalformedResponse(error.to_string()))?;
+    let k = event.k;
+    if !k.closed {
+        return Ok(None);
+    }
+    Ok(Some(BinanceKline::new(
+        k.open_timestamp,
+        k.open,
+        k.high,
+        k.low,
+        k.close,
+        k.volume,
+        k.close_timestamp,
+        &k.quote_volume,
+        k.trades,
+        &k.bid_volume,
+        &k.ask_volume,
+        "0",
+    )))
+}
+
+#[cfg(feature = "websocket")]
+pub use binance::BinanceKlineStream;
+
+#[cfg(feature = "websocket")]
+mod binance {
+    use super::KlineSource;
+    use std::error::Error;
+    use std::net::TcpStream;
+    use tungstenite::stream::MaybeTlsStream;
+    use tungstenite::{Message, WebSocket};
+
+    const BINANCE_STREAM: &str = "wss://stream.binance.com:9443/ws";
+
+    /// Binance's kline WebSocket stream for one symbol and interval.
+    pub struct BinanceKlineStream {
+        socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    }
+
+    impl BinanceKlineStream {
+        pub fn connect(symbol: &str, interval: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+            BinanceKlineStream::connect_to(BINANCE_STREAM, symbol, interval)
+        }
+        /// Connects to another Binance compatible stream endpoint, e.g. the testnet.
+        pub fn connect_to(
+            base_url: &str,
+            symbol: &str,
+            interval: &str,
+        ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+            let url = format!("{}/{}@kline_{}", base_url, symbol.to_lowercase(), interval);
+            let (socket, _) = tungstenite::connect(url)?;
+            Ok(BinanceKlineStream { socket })
+        }
+    }
+
+    impl KlineSource for BinanceKlineStream {
+        fn next_message(&mut self) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+            loop {
+                // Pings are answered by tungstenite while reading
+                match self.socket.read() {
+                    Ok(Message::Text(text)) => return Ok(Some(text)),
+                    Ok(Message::Close(_)) => return Ok(None),
+                    Ok(_) => continue,
+                    Err(tungstenite::Error::ConnectionClosed) => return Ok(None),
+                    Err(error) => return Err(Box::new(error)),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_parse_kline_event_only_yields_closed_candles() {
+        let event = |closed: bool| {
+            format!(
+                r#"{{"e":"kline","E":1672515782136,"s":"BTCUSDT","k":{{"t":1672515780000,"T":1672515839999,"s":"BTCUSDT","i":"1m","f":100,"L":200,"o":"16500.10","c":"16510.00","h":"16520.50","l":"16490.00","v":"12.5","n":100,"x":{},"q":"206300.5","V":"6.1","Q":"100700.2","B":"0"}}}}"#,
+                closed
+            )
+        };
+        assert!(parse_kline_event(&event(false)).unwrap().is_none());
+
+        let kline = parse_kline_event(&event(true)).unwrap().unwrap();
+        assert_eq!(kline.get_open_timestamp(), 1672515780000);
+        assert_eq!(
+            kline.get_ohlc(),
+            (
+                1672515839999,
+                dec!(16500.10),
+                dec!(16520.50),
+                dec!(16490.00),
+                dec!(16510.00)
+            )
+        );
+        assert_eq!(kline.get_volume(), dec!(12.5));
+        assert!(matches!(
+            parse_kline_event(r#"{"result":null,"id":1}"#),
+            Err(PriceFeedError::MalformedResponse(_))
+        ));
+    }
+}
@@ -0,0 +1,46 @@
+use rust_decimal::prelude::Decimal;
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
+
+/**
+Margin lets buys paid in one quote asset spend more than its balance, up to `leverage` times the
+account's equity: the value of the wallet in the quote asset less what has been borrowed. The
+part of a fill the balance can't cover is borrowed in the quote asset as the fill books, and
+stays on the wallet's borrowed ledger until it's repaid.
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Margin {
+    pub quote: String,
+    pub leverage: Decimal,
+}
+
+impl Margin {
+    /// Leverage below 1 is raised to 1, which never borrows.
+    pub fn new(quote: &str, leverage: Decimal) -> Self {
+        Margin {
+            quote: quote.to_string(),
+            leverage: leverage.max(dec!(1)),
+        }
+    }
+    /// How much more can be borrowed with `equity` when `borrowed` is already owed.
+    pub fn borrow_capacity(&self, equity: Decimal, borrowed: Decimal) -> Decimal {
+        (equity * (self.leverage - dec!(1)) - borrowed).max(dec!(0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_borrow_capacity() {
+        let margin = Margin::new("USDT", dec!(3));
+        assert_eq!(margin.borrow_capacity(dec!(100), dec!(0)), dec!(200));
+        assert_eq!(margin.borrow_capacity(dec!(100), dec!(150)), dec!(50));
+        assert_eq!(margin.borrow_capacity(dec!(50), dec!(150)), dec!(0));
+        assert_eq!(
+            Margin::new("USDT", dec!(0.5)).borrow_capacity(dec!(100), dec!(0)),
+            dec!(0)
+        );
+    }
+}
@@ -1,5 +1,19 @@
+pub mod cost_basis;
+#[allow(clippy::module_inception)]
 pub mod exchange;
+pub mod fee;
+pub mod fill;
+pub mod kline_stream;
+pub mod margin;
 pub mod order;
+pub mod order_book;
+pub mod pnl;
+pub mod price_feed;
+pub mod price_provider;
+pub mod rate_limiter;
+pub mod rng;
+pub mod slippage;
+pub mod symbol_filters;
+pub mod trade_stats;
 pub mod transaction;
 pub mod wallet;
-pub mod price_feed;
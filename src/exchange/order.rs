@@ -1,31 +1,76 @@
 use chrono::Utc;
-use rust_decimal::prelude::Decimal;
-use std::sync::atomic::{AtomicU64, Ordering};
+use rust_decimal::prelude::{Decimal, ToPrimitive};
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum OrderType {
     Market,
     Limit,
+    // Triggers once the market trades through `price`, then fills at `price`.
+    StopLoss,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum OrderDirection {
     Buy,
     Sell,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum OrderStatus {
     Pending,
     PartiallyFilled(u8),
     Filled,
+    Cancelled,
 }
 
-// Create a static atomic counter for order IDs
-static ORDER_ID_COUNTER: AtomicU64 = AtomicU64::new(1);
+impl fmt::Display for OrderType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderType::Market => write!(f, "MARKET"),
+            OrderType::Limit => write!(f, "LIMIT"),
+            OrderType::StopLoss => write!(f, "STOP_LOSS"),
+        }
+    }
+}
+
+impl fmt::Display for OrderDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderDirection::Buy => write!(f, "BUY"),
+            OrderDirection::Sell => write!(f, "SELL"),
+        }
+    }
+}
+
+impl fmt::Display for OrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderStatus::Pending => write!(f, "Pending"),
+            OrderStatus::PartiallyFilled(percent) => write!(f, "PartiallyFilled {}%", percent),
+            OrderStatus::Filled => write!(f, "Filled"),
+            OrderStatus::Cancelled => write!(f, "Cancelled"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TimeInForce {
+    // Good-Til-Cancelled: rests on the book until it fills.
+    GTC,
+    // Immediate-Or-Cancel: fills what it can against the current candle, the rest is cancelled.
+    IOC,
+    // Fill-Or-Kill: fills completely against the current candle or not at all.
+    FOK,
+    // Good-Til-Date: rests like GTC until the simulated clock passes the expiry timestamp.
+    GTD(i64),
+}
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Order {
+    // Assigned by the exchange the order is placed on, 0 until then.
     pub id: u64,
     pub ts: i64,
     pub order_type: OrderType,
@@ -34,9 +79,68 @@ pub struct Order {
     pub price: Option<Decimal>,
     pub qty: Decimal,
     pub status: OrderStatus,
+    // Orders sharing a group are one-cancels-other: the first leg to fill cancels the rest.
+    // The group is the id of its first leg.
+    pub oco_group: Option<u64>,
+    // Iceberg orders only expose this much of the remaining quantity per tick.
+    pub visible_qty: Option<Decimal>,
+    pub filled_qty: Decimal,
+    pub time_in_force: TimeInForce,
+    // Free-form label set by the caller, e.g. to tell strategies sharing an exchange apart.
+    pub client_tag: Option<String>,
+    // Bars this order has been evaluated against since it started resting.
+    pub bars_rested: usize,
+    // Stop-limit entries stay dormant until the market reaches `trigger_price`, then rest as
+    // ordinary limit orders.
+    pub trigger_price: Option<Decimal>,
+    pub activated: bool,
+    // Post-only orders only ever rest as makers, they are rejected instead of crossing and
+    // fill at their limit price, never at a better open a bar gaps to.
+    pub post_only: bool,
+    // Reduce-only orders never fill past a flat position, whatever is left of them is dropped.
+    pub reduce_only: bool,
+}
+
+/// The parameters of `Exchange::place_order`, for placing orders in batches.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderRequest {
+    pub pair: String,
+    pub price: Option<Decimal>,
+    pub qty: Decimal,
+    pub direction: OrderDirection,
+    pub order_type: OrderType,
+}
+
+/// What `Exchange::simulate_order` expects an order to cost: the estimated fill price and the
+/// fee, valued in the quote asset, at the maker or taker rate the order would pay.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderPreview {
+    pub pair: String,
+    pub direction: OrderDirection,
+    pub price: Decimal,
+    pub qty: Decimal,
+    pub notional: Decimal,
+    pub fee: Decimal,
+}
+
+/// Narrows `Exchange::open_orders`, a field left as None matches any order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OrderFilter {
+    pub pair: Option<String>,
+    pub direction: Option<OrderDirection>,
+    pub order_type: Option<OrderType>,
+}
+
+impl OrderFilter {
+    pub fn matches(&self, order: &Order) -> bool {
+        !matches!(&self.pair, Some(pair) if *pair != order.pair)
+            && !matches!(&self.direction, Some(direction) if *direction != order.direction)
+            && !matches!(&self.order_type, Some(order_type) if *order_type != order.order_type)
+    }
 }
 
 impl Order {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         id: u64,
         ts: i64,
@@ -56,8 +160,20 @@ impl Order {
             price,
             qty,
             status,
+            oco_group: None,
+            visible_qty: None,
+            filled_qty: dec!(0),
+            time_in_force: TimeInForce::GTC,
+            client_tag: None,
+            bars_rested: 0,
+            trigger_price: None,
+            activated: true,
+            post_only: false,
+            reduce_only: false,
         }
     }
+    /// A pending order with id 0, as are those of the `new_*` shorthands below. Ids are only
+    /// unique once the exchange assigns one, when the order is placed.
     pub fn new_order(
         pair: &str,
         price: Option<Decimal>,
@@ -65,10 +181,9 @@ impl Order {
         direction: OrderDirection,
         order_type: OrderType,
     ) -> Self {
-        let id = ORDER_ID_COUNTER.fetch_add(1, Ordering::Release);
         let ts = Utc::now().timestamp();
         Order::new(
-            id,
+            0,
             ts,
             order_type,
             direction,
@@ -114,7 +229,117 @@ impl Order {
             OrderType::Market,
         )
     }
+    pub fn new_stop_loss_sell(pair: &str, price: Decimal, qty: Decimal) -> Self {
+        Order::new_order(
+            pair,
+            Some(price),
+            qty,
+            OrderDirection::Sell,
+            OrderType::StopLoss,
+        )
+    }
+    pub fn new_iceberg_limit_sell(
+        pair: &str,
+        price: Decimal,
+        total_qty: Decimal,
+        visible_qty: Decimal,
+    ) -> Self {
+        let mut order = Order::new_limit_sell(pair, price, total_qty);
+        order.visible_qty = Some(visible_qty);
+        order
+    }
     pub fn filled(&mut self) {
         self.status = OrderStatus::Filled;
     }
+    pub fn cancelled(&mut self) {
+        self.status = OrderStatus::Cancelled;
+    }
+    pub fn remaining_qty(&self) -> Decimal {
+        self.qty - self.filled_qty
+    }
+    /// The quantity that can trade on the next tick, capped by the visible slice for icebergs.
+    pub fn active_qty(&self) -> Decimal {
+        let remaining = self.remaining_qty();
+        match self.visible_qty {
+            Some(visible_qty) => visible_qty.min(remaining),
+            None => remaining,
+        }
+    }
+    /// Whether the order is good-til-date with an expiry before `ts`.
+    pub fn expired_at(&self, ts: i64) -> bool {
+        matches!(self.time_in_force, TimeInForce::GTD(expiry_ts) if expiry_ts < ts)
+    }
+    /// How many bars the order rested before it completely filled, zero for orders that
+    /// filled on placement. `None` until the order is filled.
+    pub fn bars_to_fill(&self) -> Option<usize> {
+        (self.status == OrderStatus::Filled).then_some(self.bars_rested)
+    }
+    pub fn record_fill(&mut self, qty: Decimal) {
+        self.filled_qty += qty;
+        if self.remaining_qty() <= dec!(0) {
+            self.filled();
+        } else {
+            let percent = (self.filled_qty / self.qty * dec!(100)).trunc();
+            self.status = OrderStatus::PartiallyFilled(percent.to_u8().unwrap_or(0));
+        }
+    }
+}
+
+/// One line summary for logs, e.g. `#42 BUY 1 BTCUSDT @ 0.0093 LIMIT [Pending]`.
+impl fmt::Display for Order {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "#{} {} {} {}",
+            self.id, self.direction, self.qty, self.pair
+        )?;
+        if let Some(price) = self.price {
+            write!(f, " @ {}", price)?;
+        }
+        write!(f, " {} [{}]", self.order_type, self.status)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_order_display() {
+        let mut order = Order::new_limit_buy("BTCUSDT", dec!(0.0093), dec!(1));
+        order.id = 42;
+        assert_eq!(
+            order.to_string(),
+            "#42 BUY 1 BTCUSDT @ 0.0093 LIMIT [Pending]"
+        );
+
+        order.record_fill(dec!(0.5));
+        assert_eq!(
+            order.to_string(),
+            "#42 BUY 1 BTCUSDT @ 0.0093 LIMIT [PartiallyFilled 50%]"
+        );
+
+        let mut order = Order::new_order(
+            "ETHUSDT",
+            None,
+            dec!(2.5),
+            OrderDirection::Sell,
+            OrderType::Market,
+        );
+        order.id = 7;
+        assert_eq!(order.to_string(), "#7 SELL 2.5 ETHUSDT MARKET [Pending]");
+    }
+
+    #[test]
+    fn test_order_json_round_trip() {
+        let mut order = Order::new_stop_loss_sell("BTCUSDT", dec!(90), dec!(1));
+        order.id = 3;
+        order.oco_group = Some(2);
+        order.time_in_force = TimeInForce::GTD(1_700_000_000_000);
+        order.record_fill(dec!(0.25));
+
+        let json = serde_json::to_string(&order).unwrap();
+        let restored: Order = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, order);
+    }
 }
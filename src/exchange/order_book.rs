@@ -0,0 +1,119 @@
+use crate::exchange::order::{Order, OrderDirection, OrderType};
+use rust_decimal::prelude::Decimal;
+use std::collections::BTreeMap;
+
+/**
+OrderBook is a synthetic view of the resting limit orders on a pair. Buys are aggregated into
+bid levels sorted from the highest price down and sells into ask levels sorted from the lowest
+price up, each level summing the quantity resting at that price. Iceberg orders only contribute
+their visible slice, and stop orders aren't on the book until they trigger.
+*/
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct OrderBook {
+    pub bids: Vec<PriceLevel>,
+    pub asks: Vec<PriceLevel>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceLevel {
+    pub price: Decimal,
+    pub qty: Decimal,
+}
+
+impl OrderBook {
+    pub fn from_orders(orders: &[Order]) -> Self {
+        let mut bids: BTreeMap<Decimal, Decimal> = BTreeMap::new();
+        let mut asks: BTreeMap<Decimal, Decimal> = BTreeMap::new();
+        for order in orders {
+            let Some(price) = order.price else {
+                continue;
+            };
+            if order.order_type != OrderType::Limit || !order.activated {
+                continue;
+            }
+            let levels = match order.direction {
+                OrderDirection::Buy => &mut bids,
+                OrderDirection::Sell => &mut asks,
+            };
+            *levels.entry(price).or_default() += order.active_qty();
+        }
+        OrderBook {
+            bids: bids
+                .into_iter()
+                .rev()
+                .map(|(price, qty)| PriceLevel { price, qty })
+                .collect(),
+            asks: asks
+                .into_iter()
+                .map(|(price, qty)| PriceLevel { price, qty })
+                .collect(),
+        }
+    }
+    pub fn best_bid(&self) -> Option<&PriceLevel> {
+        self.bids.first()
+    }
+    pub fn best_ask(&self) -> Option<&PriceLevel> {
+        self.asks.first()
+    }
+    pub fn spread(&self) -> Option<Decimal> {
+        Some(self.best_ask()?.price - self.best_bid()?.price)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_order_book_aggregates_and_sorts_levels() {
+        let orders = vec![
+            Order::new_limit_buy("BTCUSDT", dec!(99), dec!(1)),
+            Order::new_limit_buy("BTCUSDT", dec!(100), dec!(0.5)),
+            Order::new_limit_buy("BTCUSDT", dec!(99), dec!(2)),
+            Order::new_limit_sell("BTCUSDT", dec!(102), dec!(1)),
+            Order::new_limit_sell("BTCUSDT", dec!(101), dec!(0.25)),
+            Order::new_iceberg_limit_sell("BTCUSDT", dec!(102), dec!(10), dec!(1)),
+            Order::new_stop_loss_sell("BTCUSDT", dec!(90), dec!(1)),
+        ];
+        let book = OrderBook::from_orders(&orders);
+
+        assert_eq!(
+            book.bids,
+            vec![
+                PriceLevel {
+                    price: dec!(100),
+                    qty: dec!(0.5)
+                },
+                PriceLevel {
+                    price: dec!(99),
+                    qty: dec!(3)
+                },
+            ]
+        );
+        assert_eq!(
+            book.asks,
+            vec![
+                PriceLevel {
+                    price: dec!(101),
+                    qty: dec!(0.25)
+                },
+                PriceLevel {
+                    price: dec!(102),
+                    qty: dec!(2)
+                },
+            ]
+        );
+        assert_eq!(book.best_bid().unwrap().price, dec!(100));
+        assert_eq!(book.best_ask().unwrap().price, dec!(101));
+        assert_eq!(book.spread(), Some(dec!(1)));
+    }
+
+    #[test]
+    fn test_empty_order_book() {
+        let book = OrderBook::from_orders(&[Order::new_limit_buy("BTCUSDT", dec!(99), dec!(1))]);
+        assert!(book.best_ask().is_none());
+        assert_eq!(book.spread(), None);
+        assert_eq!(OrderBook::from_orders(&[]), OrderBook::default());
+    }
+}
@@ -0,0 +1,239 @@
+use crate::exchange::fill::Fill;
+use crate::exchange::order::OrderDirection;
+use rust_decimal::prelude::Decimal;
+use rust_decimal_macros::dec;
+use std::collections::HashMap;
+
+/// An open position: the quantity held through a pair's fills, its average entry price and
+/// what it would gain or lose if closed at the current price.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Position {
+    pub qty: Decimal,
+    pub avg_entry: Decimal,
+    pub unrealized_pnl: Decimal,
+}
+
+/**
+PnlTracker computes realized profit and loss per base asset using average cost.
+
+Buys add to the held quantity and to its cost, with fees taken in the base asset reducing the
+quantity received. Sells realize the proceeds net of fees minus the average cost of the
+quantity sold; the average cost of what remains is unchanged. Once a position is sold down to
+nothing its cost resets, so the next buy starts a new average. Quantity that wasn't bought
+through a recorded fill, such as starting capital, has no known cost, so selling it realizes
+nothing.
+
+With shorts, selling more than the bought quantity opens a short position instead, entered at
+the proceeds net of fees. Buys cover it first, realizing the entry proceeds minus what the
+covering quantity cost.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct PnlTracker {
+    holdings: HashMap<String, Holding>,
+    realized: HashMap<String, Decimal>,
+    shorts: bool,
+}
+
+// The cost of a short is negative, the proceeds it was opened with
+#[derive(Debug, Clone, Default)]
+struct Holding {
+    qty: Decimal,
+    cost: Decimal,
+}
+
+impl PnlTracker {
+    pub fn new() -> Self {
+        PnlTracker::default()
+    }
+    /// A tracker where sells beyond the bought quantity open shorts.
+    pub fn with_shorts() -> Self {
+        PnlTracker {
+            shorts: true,
+            ..PnlTracker::default()
+        }
+    }
+    pub fn record(&mut self, base: &str, fill: &Fill) {
+        let holding = self.holdings.entry(base.to_string()).or_default();
+        // Realized PnL is only recorded when a fill closes part of a position
+        let mut realized: Option<Decimal> = None;
+        match fill.direction {
+            OrderDirection::Buy => {
+                let mut qty = fill.qty - fill.fee;
+                let mut cost = fill.notional();
+                let covered_qty = qty.min(holding.qty * dec!(-1));
+                if covered_qty > dec!(0) {
+                    let average_entry = holding.cost / holding.qty;
+                    let cover_cost = cost * covered_qty / qty;
+                    holding.qty += covered_qty;
+                    holding.cost += average_entry * covered_qty;
+                    if holding.qty >= dec!(0) {
+                        holding.cost = dec!(0);
+                    }
+                    realized = Some(average_entry * covered_qty - cover_cost);
+                    qty -= covered_qty;
+                    cost -= cover_cost;
+                }
+                holding.qty += qty;
+                holding.cost += cost;
+            }
+            OrderDirection::Sell => {
+                let proceeds = fill.notional() - fill.fee;
+                let matched_qty = fill.qty.min(holding.qty);
+                if matched_qty > dec!(0) {
+                    let average_cost = holding.cost / holding.qty;
+                    holding.qty -= matched_qty;
+                    holding.cost -= average_cost * matched_qty;
+                    if holding.qty <= dec!(0) {
+                        holding.cost = dec!(0);
+                    }
+                    realized = Some(proceeds * matched_qty / fill.qty - average_cost * matched_qty);
+                }
+                let short_qty = fill.qty - matched_qty.max(dec!(0));
+                if self.shorts && short_qty > dec!(0) {
+                    holding.qty -= short_qty;
+                    holding.cost -= proceeds * short_qty / fill.qty;
+                }
+            }
+        }
+        if let Some(realized) = realized {
+            *self.realized.entry(base.to_string()).or_default() += realized;
+        }
+    }
+    pub fn realized(&self) -> &HashMap<String, Decimal> {
+        &self.realized
+    }
+    /// The quantity of `base` bought through recorded fills and not yet sold, negative for a
+    /// short.
+    pub fn quantity(&self, base: &str) -> Decimal {
+        self.holdings
+            .get(base)
+            .map(|holding| holding.qty)
+            .unwrap_or_default()
+    }
+    /// The average entry price of the position in `base`, long or short.
+    pub fn average_cost(&self, base: &str) -> Option<Decimal> {
+        let holding = self.holdings.get(base)?;
+        if holding.qty.is_zero() {
+            return None;
+        }
+        Some(holding.cost / holding.qty)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::exchange::order::Order;
+
+    fn fill(order: Order, price: Decimal, qty: Decimal) -> Fill {
+        Fill::new(0, &order, price, qty)
+    }
+
+    #[test]
+    fn test_realized_pnl_on_round_trip() {
+        let mut tracker = PnlTracker::new();
+        tracker.record(
+            "BTC",
+            &fill(
+                Order::new_limit_buy("BTCUSDT", dec!(100), dec!(1)),
+                dec!(100),
+                dec!(1),
+            ),
+        );
+        assert!(tracker.realized().is_empty());
+        tracker.record(
+            "BTC",
+            &fill(
+                Order::new_limit_sell("BTCUSDT", dec!(150), dec!(1)),
+                dec!(150),
+                dec!(1),
+            ),
+        );
+        assert_eq!(tracker.realized()["BTC"], dec!(50));
+        assert_eq!(tracker.average_cost("BTC"), None);
+    }
+
+    #[test]
+    fn test_realized_pnl_uses_average_cost() {
+        let mut tracker = PnlTracker::new();
+        tracker.record(
+            "ETH",
+            &fill(
+                Order::new_limit_buy("ETHUSDT", dec!(10), dec!(1)),
+                dec!(10),
+                dec!(1),
+            ),
+        );
+        tracker.record(
+            "ETH",
+            &fill(
+                Order::new_limit_buy("ETHUSDT", dec!(20), dec!(1)),
+                dec!(20),
+                dec!(1),
+            ),
+        );
+        assert_eq!(tracker.average_cost("ETH"), Some(dec!(15)));
+
+        tracker.record(
+            "ETH",
+            &fill(
+                Order::new_limit_sell("ETHUSDT", dec!(12), dec!(1)),
+                dec!(12),
+                dec!(1),
+            ),
+        );
+        assert_eq!(tracker.realized()["ETH"], dec!(-3));
+        assert_eq!(tracker.average_cost("ETH"), Some(dec!(15)));
+    }
+
+    #[test]
+    fn test_realized_pnl_ignores_quantity_without_cost() {
+        let mut tracker = PnlTracker::new();
+        tracker.record(
+            "BTC",
+            &fill(
+                Order::new_limit_buy("BTCUSDT", dec!(100), dec!(1)),
+                dec!(100),
+                dec!(1),
+            ),
+        );
+        tracker.record(
+            "BTC",
+            &fill(
+                Order::new_limit_sell("BTCUSDT", dec!(150), dec!(2)),
+                dec!(150),
+                dec!(2),
+            ),
+        );
+        assert_eq!(tracker.realized()["BTC"], dec!(50));
+    }
+
+    #[test]
+    fn test_realized_pnl_on_short_round_trip() {
+        let mut tracker = PnlTracker::with_shorts();
+        tracker.record(
+            "BTC",
+            &fill(
+                Order::new_limit_sell("BTCUSDT", dec!(100), dec!(2)),
+                dec!(100),
+                dec!(2),
+            ),
+        );
+        assert!(tracker.realized().is_empty());
+        assert_eq!(tracker.quantity("BTC"), dec!(-2));
+        assert_eq!(tracker.average_cost("BTC"), Some(dec!(100)));
+
+        // Covers the short and goes long with the rest
+        tracker.record(
+            "BTC",
+            &fill(
+                Order::new_limit_buy("BTCUSDT", dec!(80), dec!(3)),
+                dec!(80),
+                dec!(3),
+            ),
+        );
+        assert_eq!(tracker.realized()["BTC"], dec!(40));
+        assert_eq!(tracker.quantity("BTC"), dec!(1));
+        assert_eq!(tracker.average_cost("BTC"), Some(dec!(80)));
+    }
+}
@@ -1,27 +1,58 @@
-use reqwest::blocking::Response;
+use crate::error::TradeSimError;
+use crate::exchange::kline_stream::{parse_kline_event, KlineSource};
+use crate::exchange::price_provider::{default_price_provider, BinanceProvider, PriceProvider};
 use rust_decimal::prelude::Decimal;
 use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::fs::File;
 use std::io::prelude::*;
-use std::io::BufReader;
-use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use thiserror::Error;
 
-// https://api.binance.com/api/v3/klines?symbol=BTCBUSD&interval=1h&limit=10
-const BINANCE_API: &str = "https://api.binance.com/api/v3";
-const KLINES: &str = "klines";
+const MAX_KLINES_PER_REQUEST: i32 = 1000;
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum PriceFeedError {
+    #[error("Malformed CSV line {line}: {reason}")]
+    MalformedCsvLine { line: usize, reason: String },
+    #[error("Malformed kline at index {index}: {reason}")]
+    MalformedKline { index: usize, reason: String },
+    #[error("Malformed klines response: {0}")]
+    MalformedResponse(String),
+    #[error("Kline stream failed: {0}")]
+    StreamFailed(String),
+}
+
+/// Parses a Binance klines response, naming the first kline that doesn't fit so bad data is
+/// rejected on load instead of part way through a simulation.
+pub fn parse_klines(json: &str) -> Result<Vec<BinanceKline>, PriceFeedError> {
+    let rows: Vec<serde_json::Value> = serde_json::from_str(json)
+        .map_err(|error| PriceFeedError::MalformedResponse(error.to_string()))?;
+    rows.into_iter()
+        .enumerate()
+        .map(|(index, row)| {
+            let malformed = |reason: String| PriceFeedError::MalformedKline { index, reason };
+            let kline: BinanceKline =
+                serde_json::from_value(row).map_err(|error| malformed(error.to_string()))?;
+            kline.validate().map_err(malformed)?;
+            Ok(kline)
+        })
+        .collect()
+}
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 #[serde(
     expecting = "expecting [<open_timestamp>, <open>, <high>, <low>, <close>, <volume>, <close_timestamp>, <quote_volume>, <trades>, <bid_volume>, <ask_volume>, <ignore>,] array"
 )]
+/// Prices and volume are parsed into `Decimal` once on load, Binance sends them as strings.
 pub struct BinanceKline {
     open_timestamp: i64,
-    open: String,
-    high: String,
-    low: String,
-    close: String,
-    volume: String,
+    open: Decimal,
+    high: Decimal,
+    low: Decimal,
+    close: Decimal,
+    volume: Decimal,
     close_timestamp: i64,
     quote_volume: String,
     trades: i32,
@@ -30,13 +61,14 @@ pub struct BinanceKline {
     ignore: String,
 }
 impl BinanceKline {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         open_timestamp: i64,
-        open: &str,
-        high: &str,
-        low: &str,
-        close: &str,
-        volume: &str,
+        open: Decimal,
+        high: Decimal,
+        low: Decimal,
+        close: Decimal,
+        volume: Decimal,
         close_timestamp: i64,
         quote_volume: &str,
         trades: i32,
@@ -46,11 +78,11 @@ impl BinanceKline {
     ) -> Self {
         BinanceKline {
             open_timestamp,
-            open: open.to_string(),
-            high: high.to_string(),
-            low: low.to_string(),
-            close: close.to_string(),
-            volume: volume.to_string(),
+            open,
+            high,
+            low,
+            close,
+            volume,
             close_timestamp,
             quote_volume: quote_volume.to_string(),
             trades,
@@ -59,84 +91,421 @@ impl BinanceKline {
             ignore: ignore.to_string(),
         }
     }
-    pub fn get_ohlc(&self) -> (i64, &str, &str, &str, &str){
-        (self.close_timestamp, self.open.as_str(), self.high.as_str(), self.low.as_str(), self.close.as_str())
+    /// Prices and volume can't be negative.
+    fn validate(&self) -> Result<(), String> {
+        let fields = [
+            ("open", self.open),
+            ("high", self.high),
+            ("low", self.low),
+            ("close", self.close),
+            ("volume", self.volume),
+        ];
+        match fields.iter().find(|(_, value)| value.is_sign_negative()) {
+            Some((name, value)) => Err(format!("negative {} {}", name, value)),
+            None => Ok(()),
+        }
+    }
+    /// Combines consecutive `klines` into a single kline spanning them all.
+    fn merge(klines: &[BinanceKline]) -> BinanceKline {
+        let sum = |field: fn(&BinanceKline) -> &str| -> String {
+            klines
+                .iter()
+                .map(|kline| Decimal::from_str_exact(field(kline)).unwrap_or_default())
+                .sum::<Decimal>()
+                .to_string()
+        };
+        let (first, last) = (&klines[0], &klines[klines.len() - 1]);
+        BinanceKline {
+            open_timestamp: first.open_timestamp,
+            open: first.open,
+            high: klines
+                .iter()
+                .map(|kline| kline.high)
+                .max()
+                .unwrap_or_default(),
+            low: klines
+                .iter()
+                .map(|kline| kline.low)
+                .min()
+                .unwrap_or_default(),
+            close: last.close,
+            volume: klines.iter().map(|kline| kline.volume).sum(),
+            close_timestamp: last.close_timestamp,
+            quote_volume: sum(|kline| &kline.quote_volume),
+            trades: klines.iter().map(|kline| kline.trades).sum(),
+            bid_volume: sum(|kline| &kline.bid_volume),
+            ask_volume: sum(|kline| &kline.ask_volume),
+            ignore: "0".to_string(),
+        }
+    }
+    pub fn get_open_timestamp(&self) -> i64 {
+        self.open_timestamp
+    }
+    pub fn get_close_timestamp(&self) -> i64 {
+        self.close_timestamp
+    }
+    pub fn get_volume(&self) -> Decimal {
+        self.volume
     }
+    pub fn get_ohlc(&self) -> (i64, Decimal, Decimal, Decimal, Decimal) {
+        (
+            self.close_timestamp,
+            self.open,
+            self.high,
+            self.low,
+            self.close,
+        )
+    }
+}
+// What the stream thread hands over to its feed.
+#[derive(Debug, Default)]
+struct StreamBuffer {
+    klines: Vec<BinanceKline>,
+    errors: Vec<PriceFeedError>,
 }
-#[derive(Clone, Debug)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct PriceFeed {
     cursor: usize,
     price_data: Option<Vec<BinanceKline>>,
+    // Not persisted, a restored feed fetches from the default provider.
+    #[serde(skip, default = "default_price_provider")]
+    provider: Arc<dyn PriceProvider>,
+    // Closed candles a live stream delivered that haven't been appended to the feed yet.
+    #[serde(skip)]
+    streamed: Option<Arc<Mutex<StreamBuffer>>>,
+    // Messages the stream couldn't parse, and the error that ended it.
+    #[serde(skip)]
+    stream_errors: Vec<PriceFeedError>,
+}
+/// A clone holds the klines received so far, only the original keeps receiving a live stream.
+impl Clone for PriceFeed {
+    fn clone(&self) -> Self {
+        PriceFeed {
+            cursor: self.cursor,
+            price_data: self.price_data.clone(),
+            provider: self.provider.clone(),
+            streamed: None,
+            stream_errors: self.stream_errors.clone(),
+        }
+    }
+}
+impl Default for PriceFeed {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 impl PriceFeed {
     pub fn new() -> Self {
+        PriceFeed::with_provider(default_price_provider())
+    }
+    /// A feed that loads its klines from `provider` instead of Binance.
+    pub fn with_provider(provider: Arc<dyn PriceProvider>) -> Self {
         PriceFeed {
             cursor: 0usize,
             price_data: None,
+            provider,
+            streamed: None,
+            stream_errors: vec![],
         }
     }
-    pub fn initialize_price_feed(&mut self, symbol: String, interval: String, limit: i32) -> Result<(), Box<dyn Error>>{
-        self.price_data = Some(PriceFeed::fetch(symbol, interval, limit)?);
+    pub fn initialize_price_feed(
+        &mut self,
+        symbol: String,
+        interval: String,
+        limit: i32,
+    ) -> Result<(), TradeSimError> {
+        self.price_data = Some(self.provider.fetch(&symbol, &interval, limit)?);
         Ok(())
     }
+    /// A feed that grows as `source` delivers closed candles, read on a background thread
+    /// until the source ends. Until a candle arrives `next()` returns `None`, ticking an
+    /// exchange on the feed just doesn't advance it.
+    pub fn stream_from(mut source: impl KlineSource) -> PriceFeed {
+        let streamed = Arc::new(Mutex::new(StreamBuffer::default()));
+        let buffer = streamed.clone();
+        thread::spawn(move || {
+            // Stops with the source, or once the feed is dropped
+            loop {
+                let message = match source.next_message() {
+                    Ok(Some(message)) => message,
+                    Ok(None) => break,
+                    Err(error) => {
+                        lock_buffer(&buffer)
+                            .errors
+                            .push(PriceFeedError::StreamFailed(error.to_string()));
+                        break;
+                    }
+                };
+                if Arc::strong_count(&buffer) == 1 {
+                    break;
+                }
+                match parse_kline_event(&message) {
+                    Ok(Some(kline)) => lock_buffer(&buffer).klines.push(kline),
+                    Ok(None) => {}
+                    Err(error) => lock_buffer(&buffer).errors.push(error),
+                }
+            }
+        });
+        let mut price_feed = PriceFeed::new();
+        price_feed.price_data = Some(vec![]);
+        price_feed.streamed = Some(streamed);
+        price_feed
+    }
+    /// Streams the closed klines of `symbol` from Binance's WebSocket API.
+    #[cfg(feature = "websocket")]
+    pub fn stream(symbol: &str, interval: &str) -> Result<PriceFeed, TradeSimError> {
+        let source = crate::exchange::kline_stream::BinanceKlineStream::connect(symbol, interval)?;
+        Ok(PriceFeed::stream_from(source))
+    }
+    /// Appends the candles a live stream delivered since the last call, returning how many.
+    /// Errors the stream ran into are collected in `stream_errors`.
+    pub fn receive_streamed(&mut self) -> usize {
+        let Some(streamed) = self.streamed.as_ref() else {
+            return 0;
+        };
+        let StreamBuffer { klines, errors } = std::mem::take(&mut *lock_buffer(streamed));
+        let received = klines.len();
+        self.price_data.get_or_insert_with(Vec::new).extend(klines);
+        self.stream_errors.extend(errors);
+        received
+    }
+    /// Messages the live stream couldn't parse, followed by the error that ended it, if any.
+    pub fn stream_errors(&self) -> &[PriceFeedError] {
+        &self.stream_errors
+    }
     pub fn add_price_data(&mut self, klines: Vec<BinanceKline>) {
         self.price_data = Some(klines);
         self.cursor = 0;
     }
-    fn save_price_data(
-        filename: String,
-        price_data: &Vec<BinanceKline>,
-    ) -> Result<(), Box<dyn Error>> {
-        let mut f = File::create(filename)?;
-        let serialized = serde_json::to_string(price_data)?;
-        f.write_all(serialized.as_bytes())?;
-        Ok(())
+    /// Loads klines from a CSV file laid out like the Binance kline array:
+    /// `open_timestamp,open,high,low,close,volume,close_timestamp,...`. The trailing columns
+    /// are optional and a leading header row is skipped.
+    pub fn from_csv(path: &str) -> Result<PriceFeed, TradeSimError> {
+        let mut contents = String::new();
+        File::open(path)?.read_to_string(&mut contents)?;
+        let mut klines = vec![];
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let is_header = index == 0
+                && line
+                    .split(',')
+                    .next()
+                    .is_some_and(|field| field.trim().parse::<i64>().is_err());
+            if is_header {
+                continue;
+            }
+            klines.push(PriceFeed::parse_csv_line(index + 1, line)?);
+        }
+        let mut price_feed = PriceFeed::new();
+        price_feed.add_price_data(klines);
+        Ok(price_feed)
     }
-    fn fetch(
-        symbol: String,
-        interval: String,
-        limit: i32,
-    ) -> Result<Vec<BinanceKline>, Box<dyn Error>> {
-        let fetch_filename = format!("data/{:}{:}{:}", symbol, interval, limit);
-        let price_data: Vec<BinanceKline>;
-        if let Ok(file) = File::open(&fetch_filename) {
-            let mut buf_reader = BufReader::new(file);
-            let mut contents = String::new();
-            buf_reader.read_to_string(&mut contents)?;
-            price_data = serde_json::from_str(contents.as_str())?;
-        } else {
-            let arguments = format!("symbol={:}&interval={:}&limit={:}", symbol, interval, limit);
-            let url = format!("{:}/{:}?{:}", BINANCE_API, KLINES, arguments);
-            price_data = reqwest::blocking::get(url)?.json()?;
-            PriceFeed::save_price_data(fetch_filename, &price_data)?;
+    fn parse_csv_line(line_number: usize, line: &str) -> Result<BinanceKline, PriceFeedError> {
+        let malformed = |reason: String| PriceFeedError::MalformedCsvLine {
+            line: line_number,
+            reason,
+        };
+        let fields: Vec<&str> = line.split(',').map(|field| field.trim()).collect();
+        if fields.len() < 7 {
+            return Err(malformed(format!(
+                "expected at least 7 columns, found {}",
+                fields.len()
+            )));
         }
-
-        Ok(price_data)
+        let timestamp = |index: usize| {
+            fields[index]
+                .parse::<i64>()
+                .map_err(|_| malformed(format!("invalid timestamp {:?}", fields[index])))
+        };
+        let decimal = |index: usize| match fields.get(index) {
+            None => Ok(Decimal::ZERO),
+            Some(field) => Decimal::from_str_exact(field)
+                .map_err(|_| malformed(format!("invalid number {:?}", field))),
+        };
+        let trades = match fields.get(8) {
+            None => 0,
+            Some(field) => field
+                .parse::<i32>()
+                .map_err(|_| malformed(format!("invalid trade count {:?}", field)))?,
+        };
+        let kline = BinanceKline::new(
+            timestamp(0)?,
+            decimal(1)?,
+            decimal(2)?,
+            decimal(3)?,
+            decimal(4)?,
+            decimal(5)?,
+            timestamp(6)?,
+            &decimal(7)?.to_string(),
+            trades,
+            &decimal(9)?.to_string(),
+            &decimal(10)?.to_string(),
+            fields.get(11).copied().unwrap_or("0"),
+        );
+        kline.validate().map_err(malformed)?;
+        Ok(kline)
+    }
+    /// A new feed with every `factor` consecutive klines merged into one, e.g. 60 for 1m to 1h.
+    /// A trailing group shorter than `factor` is dropped rather than passed off as a whole
+    /// candle. The new feed starts at its first kline. A `factor` of 0 is taken as 1, which
+    /// copies the klines unmerged.
+    pub fn resample(&self, factor: usize) -> PriceFeed {
+        let factor = factor.max(1);
+        let price_data = self.price_data.as_deref().unwrap_or_default();
+        let klines = price_data
+            .chunks_exact(factor)
+            .map(BinanceKline::merge)
+            .collect();
+        let mut price_feed = PriceFeed::with_provider(self.provider.clone());
+        price_feed.add_price_data(klines);
+        price_feed
+    }
+    /// Where klines are missing: for each pair of neighbours opening more than
+    /// `expected_interval_ms` apart, the half-open range `[first missing open, next open)`.
+    pub fn find_gaps(&self, expected_interval_ms: i64) -> Vec<(i64, i64)> {
+        let price_data = self.price_data.as_deref().unwrap_or_default();
+        price_data
+            .windows(2)
+            .filter(|pair| pair[1].open_timestamp - pair[0].open_timestamp > expected_interval_ms)
+            .map(|pair| {
+                (
+                    pair[0].open_timestamp + expected_interval_ms,
+                    pair[1].open_timestamp,
+                )
+            })
+            .collect()
+    }
+    /// Rewinds the cursor so the feed replays from its first kline.
+    pub fn reset(&mut self) {
+        self.cursor = 0;
+    }
+    /// Moves the cursor to `index`, clamped to the number of klines so seeking past the end
+    /// leaves the feed exhausted.
+    pub fn seek(&mut self, index: usize) {
+        self.cursor = index.min(self.len());
     }
-    pub fn next(&mut self) -> Option<BinanceKline> {
-        let price_data = self.price_data.as_ref().unwrap();
-        if (self.cursor) < price_data.len() {
-            let data_out = price_data[self.cursor].clone();
-            self.cursor += 1;
-            return Some(data_out);
+    pub fn position(&self) -> usize {
+        self.cursor
+    }
+    /// The number of klines the feed holds, consumed or not.
+    pub fn len(&self) -> usize {
+        self.price_data
+            .as_ref()
+            .map_or(0, |price_data| price_data.len())
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// How many more klines `next()` will return before the feed is exhausted.
+    pub fn remaining(&self) -> usize {
+        self.len().saturating_sub(self.cursor)
+    }
+    /// Fetches every kline opening between `start` and `end` (millis), paging through Binance
+    /// since a single request returns at most 1000 klines.
+    pub fn fetch_range(
+        symbol: &str,
+        interval: &str,
+        start: i64,
+        end: i64,
+    ) -> Result<PriceFeed, TradeSimError> {
+        let klines = PriceFeed::fetch_pages(start, end, |page_start, page_end, limit| {
+            BinanceProvider::new().fetch_page(symbol, interval, page_start, page_end, limit)
+        })?;
+        let mut price_feed = PriceFeed::new();
+        price_feed.add_price_data(klines);
+        Ok(price_feed)
+    }
+    /// Calls `fetch_page(start, end, limit)` until a page comes back short, each page starting
+    /// right after the last kline of the previous one.
+    fn fetch_pages<F>(
+        start: i64,
+        end: i64,
+        mut fetch_page: F,
+    ) -> Result<Vec<BinanceKline>, Box<dyn Error + Send + Sync>>
+    where
+        F: FnMut(i64, i64, i32) -> Result<Vec<BinanceKline>, Box<dyn Error + Send + Sync>>,
+    {
+        let mut klines: Vec<BinanceKline> = vec![];
+        let mut page_start = start;
+        while page_start <= end {
+            let page = fetch_page(page_start, end, MAX_KLINES_PER_REQUEST)?;
+            let page_len = page.len();
+            let Some(last) = page.last() else {
+                break;
+            };
+            page_start = last.open_timestamp + 1;
+            klines.extend(page);
+            if page_len < MAX_KLINES_PER_REQUEST as usize {
+                break;
+            }
+        }
+        Ok(klines)
+    }
+    /// The most recently consumed kline, if `next()` has been called at least once.
+    pub fn last_consumed(&self) -> Option<&BinanceKline> {
+        let index = self.cursor.checked_sub(1)?;
+        self.price_data.as_ref()?.get(index)
+    }
+    /// Every kline consumed so far, oldest first.
+    pub fn consumed(&self) -> &[BinanceKline] {
+        match self.price_data.as_ref() {
+            Some(price_data) => &price_data[..self.cursor.min(price_data.len())],
+            None => &[],
+        }
+    }
+    /// Consumes every kline from the cursor onwards that closes at or before `close_timestamp`.
+    pub fn next_until(&mut self, close_timestamp: i64) -> Vec<BinanceKline> {
+        let mut klines = vec![];
+        if let Some(price_data) = self.price_data.as_ref() {
+            while let Some(kline) = price_data.get(self.cursor) {
+                if kline.close_timestamp > close_timestamp {
+                    break;
+                }
+                klines.push(kline.clone());
+                self.cursor += 1;
+            }
         }
-        None
+        klines
+    }
+    /// The kline `next()` would return, without consuming it.
+    pub fn peek(&self) -> Option<&BinanceKline> {
+        self.price_data.as_ref()?.get(self.cursor)
     }
 }
+/// Iterating consumes the feed from the cursor onwards, like calling `next()` directly.
+impl Iterator for PriceFeed {
+    type Item = BinanceKline;
+    fn next(&mut self) -> Option<BinanceKline> {
+        self.receive_streamed();
+        let data_out = self.peek()?.clone();
+        self.cursor += 1;
+        Some(data_out)
+    }
+}
+
+fn lock_buffer(buffer: &Mutex<StreamBuffer>) -> std::sync::MutexGuard<'_, StreamBuffer> {
+    buffer
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rust_decimal_macros::dec;
 
     fn sample_klines() -> Vec<BinanceKline> {
         vec![
             BinanceKline {
                 open_timestamp: 1633064400000,
-                open: "55000.00".to_string(),
-                high: "55100.00".to_string(),
-                low: "54900.00".to_string(),
-                close: "55050.00".to_string(),
-                volume: "1000.00".to_string(),
+                open: dec!(55000.00),
+                high: dec!(55100.00),
+                low: dec!(54900.00),
+                close: dec!(55050.00),
+                volume: dec!(1000.00),
                 close_timestamp: 1633067999999,
                 quote_volume: "55050000.00".to_string(),
                 trades: 100,
@@ -146,11 +515,11 @@ mod tests {
             },
             BinanceKline {
                 open_timestamp: 1633068000000,
-                open: "55050.00".to_string(),
-                high: "55200.00".to_string(),
-                low: "54950.00".to_string(),
-                close: "55100.00".to_string(),
-                volume: "1100.00".to_string(),
+                open: dec!(55050.00),
+                high: dec!(55200.00),
+                low: dec!(54950.00),
+                close: dec!(55100.00),
+                volume: dec!(1100.00),
                 close_timestamp: 1633071599999,
                 quote_volume: "60505000.00".to_string(),
                 trades: 110,
@@ -165,7 +534,16 @@ mod tests {
     fn test_get_ohlc() {
         let kline = &sample_klines()[0];
         let ohlc = kline.get_ohlc();
-        assert_eq!(ohlc, (1633067999999, "55000.00", "55100.00", "54900.00", "55050.00"));
+        assert_eq!(
+            ohlc,
+            (
+                1633067999999,
+                dec!(55000.00),
+                dec!(55100.00),
+                dec!(54900.00),
+                dec!(55050.00)
+            )
+        );
     }
 
     #[test]
@@ -174,11 +552,420 @@ mod tests {
         price_feed.add_price_data(sample_klines());
 
         let kline1 = price_feed.next().unwrap();
-        assert_eq!(kline1.get_ohlc(), (1633067999999, "55000.00", "55100.00", "54900.00", "55050.00"));
+        assert_eq!(
+            kline1.get_ohlc(),
+            (
+                1633067999999,
+                dec!(55000.00),
+                dec!(55100.00),
+                dec!(54900.00),
+                dec!(55050.00)
+            )
+        );
 
         let kline2 = price_feed.next().unwrap();
-        assert_eq!(kline2.get_ohlc(), (1633071599999, "55050.00", "55200.00", "54950.00", "55100.00"));
+        assert_eq!(
+            kline2.get_ohlc(),
+            (
+                1633071599999,
+                dec!(55050.00),
+                dec!(55200.00),
+                dec!(54950.00),
+                dec!(55100.00)
+            )
+        );
+
+        assert!(price_feed.next().is_none());
+    }
+
+    #[test]
+    fn test_price_feed_next_until() {
+        let mut price_feed = PriceFeed::new();
+        price_feed.add_price_data(sample_klines());
+
+        assert!(price_feed.next_until(1633064400000).is_empty());
+        let klines = price_feed.next_until(1633067999999);
+        assert_eq!(klines.len(), 1);
+        assert_eq!(klines[0].get_close_timestamp(), 1633067999999);
+        assert_eq!(price_feed.next_until(i64::MAX).len(), 1);
+        assert!(price_feed.next().is_none());
+    }
+
+    #[test]
+    fn test_price_feed_last_consumed() {
+        let mut price_feed = PriceFeed::new();
+        assert!(price_feed.last_consumed().is_none());
+        price_feed.add_price_data(sample_klines());
+        assert!(price_feed.last_consumed().is_none());
 
+        let kline = price_feed.next().unwrap();
+        assert_eq!(
+            price_feed.last_consumed().unwrap().get_ohlc(),
+            kline.get_ohlc()
+        );
+    }
+
+    #[test]
+    fn test_price_feed_from_csv() {
+        let mut price_feed = PriceFeed::from_csv("tests/fixtures/btcusdt_1h.csv").unwrap();
+        let klines = sample_klines();
+        assert_eq!(price_feed.next().unwrap().get_ohlc(), klines[0].get_ohlc());
+        let second = price_feed.next().unwrap();
+        assert_eq!(second.get_ohlc(), klines[1].get_ohlc());
+        assert_eq!(second.get_volume(), dec!(1100.00));
+
+        // The last row leaves out the optional trailing columns
+        let third = price_feed.next().unwrap();
+        assert_eq!(
+            third.get_ohlc(),
+            (
+                1633075199999,
+                dec!(55100.00),
+                dec!(55150.00),
+                dec!(54800.00),
+                dec!(54900.00)
+            )
+        );
         assert!(price_feed.next().is_none());
     }
+
+    #[test]
+    fn test_price_feed_from_csv_rejects_malformed_lines() {
+        let path = std::env::temp_dir().join("price_feed_malformed.csv");
+        std::fs::write(
+            &path,
+            "1633064400000,55000.00,55100.00,54900.00,55050.00,1000.00,1633067999999\n\
+             1633068000000,55050.00,abc,54950.00,55100.00,1100.00,1633071599999\n",
+        )
+        .unwrap();
+        let error = PriceFeed::from_csv(path.to_str().unwrap()).unwrap_err();
+        assert!(matches!(
+            error,
+            TradeSimError::PriceFeed(PriceFeedError::MalformedCsvLine { line: 2, reason })
+                if reason == "invalid number \"abc\""
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_price_feed_reset_replays_identical_klines() {
+        let mut price_feed = PriceFeed::new();
+        price_feed.add_price_data(sample_klines());
+        let first_pass: Vec<_> = std::iter::from_fn(|| price_feed.next())
+            .map(|kline| kline.get_ohlc().0)
+            .collect();
+        assert_eq!(price_feed.position(), 2);
+
+        price_feed.reset();
+        assert_eq!(price_feed.position(), 0);
+        let second_pass: Vec<_> = std::iter::from_fn(|| price_feed.next())
+            .map(|kline| kline.get_ohlc().0)
+            .collect();
+        assert_eq!(first_pass, second_pass);
+    }
+
+    #[test]
+    fn test_price_feed_seek() {
+        let mut price_feed = PriceFeed::new();
+        price_feed.add_price_data(sample_klines());
+        price_feed.seek(1);
+        assert_eq!(price_feed.position(), 1);
+        assert_eq!(
+            price_feed.next().unwrap().get_close_timestamp(),
+            1633071599999
+        );
+
+        price_feed.seek(10);
+        assert_eq!(price_feed.position(), 2);
+        assert!(price_feed.next().is_none());
+    }
+
+    #[test]
+    fn test_price_feed_peek() {
+        let mut price_feed = PriceFeed::new();
+        assert!(price_feed.peek().is_none());
+        assert!(price_feed.next().is_none());
+
+        price_feed.add_price_data(sample_klines());
+        let peeked = price_feed.peek().unwrap().get_close_timestamp();
+        assert_eq!(price_feed.position(), 0);
+        assert_eq!(price_feed.next().unwrap().get_close_timestamp(), peeked);
+        price_feed.next();
+        assert!(price_feed.peek().is_none());
+    }
+
+    #[test]
+    fn test_price_feed_next_on_uninitialized_feed() {
+        let mut price_feed = PriceFeed::new();
+        assert!(price_feed.next().is_none());
+        assert!(price_feed.last_consumed().is_none());
+        assert!(price_feed.next_until(i64::MAX).is_empty());
+    }
+
+    #[test]
+    fn test_fetch_pages_until_a_short_page() {
+        // A fake exchange with 2500 one minute klines, served at most `limit` at a time
+        let minute = 60_000;
+        let all: Vec<BinanceKline> = (0..2500)
+            .map(|i| BinanceKline {
+                open_timestamp: i * minute,
+                close_timestamp: i * minute + minute - 1,
+                ..BinanceKline::default()
+            })
+            .collect();
+        let mut requests = vec![];
+        let klines = PriceFeed::fetch_pages(0, 2499 * minute, |start, end, limit| {
+            requests.push(start);
+            Ok(all
+                .iter()
+                .filter(|kline| kline.open_timestamp >= start && kline.open_timestamp <= end)
+                .take(limit as usize)
+                .cloned()
+                .collect())
+        })
+        .unwrap();
+
+        // Each page starts just after the open of the previous page's last kline
+        assert_eq!(requests, vec![0, 999 * minute + 1, 1999 * minute + 1]);
+        assert_eq!(klines.len(), 2500);
+        assert!(klines
+            .windows(2)
+            .all(|pair| pair[1].open_timestamp == pair[0].open_timestamp + minute));
+    }
+
+    #[test]
+    fn test_deserialize_binance_kline_array() {
+        let raw = r#"[1499040000000,"0.01634790","0.80000000","0.01575800","0.01577100","148976.11427815",1499644799999,"2434.19055334",308,"1756.87402397","28.46694368","17928899.62484339"]"#;
+        let kline: BinanceKline = serde_json::from_str(raw).unwrap();
+        assert_eq!(kline.get_open_timestamp(), 1499040000000);
+        assert_eq!(
+            kline.get_ohlc(),
+            (
+                1499644799999,
+                dec!(0.01634790),
+                dec!(0.80000000),
+                dec!(0.01575800),
+                dec!(0.01577100)
+            )
+        );
+        assert_eq!(kline.get_volume(), dec!(148976.11427815));
+
+        // Prices serialize back as exact strings
+        let json = serde_json::to_string(&kline).unwrap();
+        assert!(json.contains(r#""open":"0.01634790""#));
+        let restored: BinanceKline = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.get_ohlc(), kline.get_ohlc());
+    }
+
+    #[test]
+    fn test_deserialize_binance_kline_rejects_non_numeric_price() {
+        let raw = r#"[1499040000000,"abc","0.80000000","0.01575800","0.01577100","148976.11427815",1499644799999,"2434.19055334",308,"1756.87402397","28.46694368","17928899.62484339"]"#;
+        assert!(serde_json::from_str::<BinanceKline>(raw).is_err());
+    }
+
+    #[test]
+    fn test_parse_klines_names_the_malformed_kline() {
+        let json = r#"[
+            [1499040000000,"0.0163","0.8000","0.0157","0.0157","148976.11",1499644799999,"2434.19",308,"1756.87","28.46","0"],
+            [1499644800000,"0.0157","oops","0.0150","0.0155","1000.00",1500249599999,"15.50",12,"10.00","5.50","0"]
+        ]"#;
+        let error = parse_klines(json).unwrap_err();
+        match error {
+            PriceFeedError::MalformedKline { index, ref reason } => {
+                assert_eq!(index, 1);
+                assert!(reason.contains("oops"), "{}", reason);
+            }
+            other => panic!("unexpected error {:?}", other),
+        }
+        assert!(error.to_string().starts_with("Malformed kline at index 1"));
+
+        let short_row = r#"[[1499040000000,"0.0163","0.8000"]]"#;
+        assert!(matches!(
+            parse_klines(short_row),
+            Err(PriceFeedError::MalformedKline { index: 0, .. })
+        ));
+        assert!(matches!(
+            parse_klines(r#"{"code":-1121,"msg":"Invalid symbol."}"#),
+            Err(PriceFeedError::MalformedResponse(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_klines_rejects_negative_prices() {
+        let json = r#"[[1499040000000,"-0.0163","0.8000","0.0157","0.0157","148976.11",1499644799999,"2434.19",308,"1756.87","28.46","0"]]"#;
+        assert_eq!(
+            parse_klines(json).unwrap_err(),
+            PriceFeedError::MalformedKline {
+                index: 0,
+                reason: "negative open -0.0163".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_price_feed_len_and_remaining() {
+        let mut price_feed = PriceFeed::new();
+        assert_eq!(price_feed.len(), 0);
+        assert_eq!(price_feed.remaining(), 0);
+        assert!(price_feed.is_empty());
+
+        price_feed.add_price_data(sample_klines());
+        assert_eq!(price_feed.len(), 2);
+        assert_eq!(price_feed.remaining(), 2);
+        assert!(!price_feed.is_empty());
+
+        price_feed.next();
+        assert_eq!(price_feed.len(), 2);
+        assert_eq!(price_feed.remaining(), 1);
+        price_feed.next();
+        assert_eq!(price_feed.remaining(), 0);
+        assert!(price_feed.next().is_none());
+        assert_eq!(price_feed.remaining(), 0);
+    }
+
+    #[test]
+    fn test_price_feed_iterator() {
+        let mut price_feed = PriceFeed::new();
+        price_feed.add_price_data(sample_klines());
+        let klines: Vec<BinanceKline> = price_feed.by_ref().collect();
+        let ohlc = |klines: &[BinanceKline]| -> Vec<_> {
+            klines.iter().map(|kline| kline.get_ohlc()).collect()
+        };
+        assert_eq!(ohlc(&klines), ohlc(&sample_klines()));
+        assert_eq!(price_feed.remaining(), 0);
+
+        price_feed.reset();
+        let closes: Vec<i64> = price_feed
+            .take(1)
+            .map(|kline| kline.get_close_timestamp())
+            .collect();
+        assert_eq!(closes, vec![1633067999999]);
+    }
+
+    #[test]
+    fn test_resample_merges_consecutive_klines() {
+        let minute = |i: i64, ohlcv: [Decimal; 5]| BinanceKline {
+            open_timestamp: i * 60_000,
+            open: ohlcv[0],
+            high: ohlcv[1],
+            low: ohlcv[2],
+            close: ohlcv[3],
+            volume: ohlcv[4],
+            close_timestamp: i * 60_000 + 59_999,
+            quote_volume: "10".to_string(),
+            trades: 3,
+            ..BinanceKline::default()
+        };
+        let mut price_feed = PriceFeed::new();
+        price_feed.add_price_data(vec![
+            minute(0, [dec!(10), dec!(12), dec!(9), dec!(11), dec!(1.5)]),
+            minute(1, [dec!(11), dec!(14), dec!(10), dec!(13), dec!(2)]),
+            minute(2, [dec!(13), dec!(13.5), dec!(8), dec!(9), dec!(4)]),
+            minute(3, [dec!(9), dec!(10), dec!(8.5), dec!(10), dec!(0.5)]),
+            // Left over, half of a 2m candle isn't one
+            minute(4, [dec!(10), dec!(20), dec!(1), dec!(15), dec!(9)]),
+        ]);
+        price_feed.next();
+
+        let mut resampled = price_feed.resample(2);
+        assert_eq!(resampled.len(), 2);
+        assert_eq!(resampled.position(), 0);
+        let first = resampled.next().unwrap();
+        assert_eq!(first.get_open_timestamp(), 0);
+        assert_eq!(
+            first.get_ohlc(),
+            (119_999, dec!(10), dec!(14), dec!(9), dec!(13))
+        );
+        assert_eq!(first.get_volume(), dec!(3.5));
+        assert_eq!(first.trades, 6);
+        assert_eq!(first.quote_volume, "20");
+        let second = resampled.next().unwrap();
+        assert_eq!(second.get_open_timestamp(), 120_000);
+        assert_eq!(
+            second.get_ohlc(),
+            (239_999, dec!(13), dec!(13.5), dec!(8), dec!(10))
+        );
+        assert_eq!(second.get_volume(), dec!(4.5));
+
+        assert_eq!(price_feed.resample(1).len(), 5);
+        assert_eq!(price_feed.resample(0).len(), 5);
+        assert!(PriceFeed::new().resample(60).is_empty());
+    }
+
+    #[test]
+    fn test_find_gaps() {
+        let minute = 60_000;
+        let mut price_feed = PriceFeed::new();
+        assert!(price_feed.find_gaps(minute).is_empty());
+        price_feed.add_price_data(
+            [0, 1, 3, 4, 8]
+                .iter()
+                .map(|i| BinanceKline {
+                    open_timestamp: i * minute,
+                    close_timestamp: i * minute + minute - 1,
+                    ..BinanceKline::default()
+                })
+                .collect(),
+        );
+        assert_eq!(
+            price_feed.find_gaps(minute),
+            vec![(2 * minute, 3 * minute), (5 * minute, 8 * minute)]
+        );
+        // At a coarser expected interval the first gap is just normal spacing
+        assert_eq!(
+            price_feed.find_gaps(2 * minute),
+            vec![(6 * minute, 8 * minute)]
+        );
+    }
+
+    struct MockSource(std::sync::mpsc::Receiver<Result<String, String>>);
+
+    impl KlineSource for MockSource {
+        fn next_message(&mut self) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+            match self.0.recv() {
+                Ok(message) => message.map(Some).map_err(|error| error.into()),
+                Err(_) => Ok(None),
+            }
+        }
+    }
+
+    fn receive_until(price_feed: &mut PriceFeed, done: impl Fn(&PriceFeed) -> bool) {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while !done(price_feed) && std::time::Instant::now() < deadline {
+            price_feed.receive_streamed();
+            thread::sleep(std::time::Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_stream_errors_are_reported_to_the_feed() {
+        let (sender, messages) = std::sync::mpsc::channel();
+        let mut price_feed = PriceFeed::stream_from(MockSource(messages));
+        let closed = r#"{"e":"kline","E":0,"s":"BTCUSDT","k":{"t":0,"T":59999,"s":"BTCUSDT","i":"1m","f":0,"L":0,"o":"3","c":"3","h":"3.5","l":"2.5","v":"100","n":10,"x":true,"q":"300","V":"50","Q":"150","B":"0"}}"#;
+        sender.send(Ok("not json".to_string())).unwrap();
+        sender.send(Ok(closed.to_string())).unwrap();
+        sender.send(Err("connection reset".to_string())).unwrap();
+        receive_until(&mut price_feed, |feed| feed.stream_errors().len() == 2);
+
+        assert_eq!(price_feed.len(), 1);
+        let errors = price_feed.stream_errors();
+        assert!(matches!(errors[0], PriceFeedError::MalformedResponse(_)));
+        assert_eq!(
+            errors[1],
+            PriceFeedError::StreamFailed("connection reset".to_string())
+        );
+    }
+
+    #[test]
+    fn test_clone_does_not_share_the_stream() {
+        let (sender, messages) = std::sync::mpsc::channel();
+        let mut price_feed = PriceFeed::stream_from(MockSource(messages));
+        let mut snapshot = price_feed.clone();
+        sender.send(Ok("not json".to_string())).unwrap();
+        receive_until(&mut price_feed, |feed| !feed.stream_errors().is_empty());
+
+        assert_eq!(price_feed.stream_errors().len(), 1);
+        assert_eq!(snapshot.receive_streamed(), 0);
+        assert!(snapshot.stream_errors().is_empty());
+    }
 }
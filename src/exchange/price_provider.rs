@@ -0,0 +1,392 @@
+use crate::exchange::price_feed::{parse_klines, BinanceKline};
+use crate::exchange::rate_limiter::RateLimiter;
+use std::error::Error;
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+// https://api.binance.com/api/v3/klines?symbol=BTCBUSD&interval=1h&limit=10
+const BINANCE_API: &str = "https://api.binance.com/api/v3";
+const KLINES: &str = "klines";
+const DEFAULT_CACHE_DIR: &str = "data";
+
+/**
+A PriceProvider is a source of klines for a PriceFeed. BinanceProvider is the default, other
+sources (another exchange, a local simulator, canned data in tests) only need to implement
+fetch().
+*/
+pub trait PriceProvider: Debug + Send + Sync {
+    fn fetch(
+        &self,
+        symbol: &str,
+        interval: &str,
+        limit: i32,
+    ) -> Result<Vec<BinanceKline>, Box<dyn Error + Send + Sync>>;
+}
+
+/// How often a failed request is retried. The wait before each retry starts at
+/// `initial_backoff` and doubles every attempt.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(250),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32, initial_backoff: Duration) -> Self {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff,
+        }
+    }
+    /// A single attempt, failures are returned straight away.
+    pub fn none() -> Self {
+        RetryPolicy::new(1, Duration::ZERO)
+    }
+    /// Calls `attempt` until it succeeds, it fails with an error `retryable` rejects, or the
+    /// attempts run out, in which case the last error is returned.
+    pub fn run<T, F, R>(
+        &self,
+        mut attempt: F,
+        retryable: R,
+    ) -> Result<T, Box<dyn Error + Send + Sync>>
+    where
+        F: FnMut() -> Result<T, Box<dyn Error + Send + Sync>>,
+        R: Fn(&(dyn Error + 'static)) -> bool,
+    {
+        let mut backoff = self.initial_backoff;
+        let mut attempts = 1;
+        loop {
+            match attempt() {
+                Err(error) if attempts < self.max_attempts && retryable(error.as_ref()) => {
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                    attempts += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// The provider feeds use unless told otherwise.
+pub fn default_price_provider() -> Arc<dyn PriceProvider> {
+    Arc::new(BinanceProvider::new())
+}
+
+/// Pulls klines from the Binance REST API, caching every response under `data/` unless told
+/// otherwise.
+#[derive(Debug, Clone)]
+pub struct BinanceProvider {
+    base_url: String,
+    connect_timeout: Duration,
+    timeout: Duration,
+    user_agent: String,
+    cache_dir: PathBuf,
+    retry_policy: RetryPolicy,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    // Built on the first request and reused after, the settings above reset it.
+    client: OnceLock<reqwest::blocking::Client>,
+}
+
+impl Default for BinanceProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BinanceProvider {
+    pub fn new() -> Self {
+        BinanceProvider {
+            base_url: BINANCE_API.to_string(),
+            connect_timeout: Duration::from_secs(10),
+            timeout: Duration::from_secs(30),
+            user_agent: format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            cache_dir: PathBuf::from(DEFAULT_CACHE_DIR),
+            retry_policy: RetryPolicy::default(),
+            rate_limiter: None,
+            client: OnceLock::new(),
+        }
+    }
+    /// Points the provider at another Binance compatible API, e.g. the testnet.
+    pub fn with_base_url(&mut self, base_url: &str) -> &mut Self {
+        self.base_url = base_url.to_string();
+        self
+    }
+    pub fn with_connect_timeout(&mut self, connect_timeout: Duration) -> &mut Self {
+        self.connect_timeout = connect_timeout;
+        self.client = OnceLock::new();
+        self
+    }
+    /// Limits how long a whole request, including reading the response, may take.
+    pub fn with_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = timeout;
+        self.client = OnceLock::new();
+        self
+    }
+    pub fn with_user_agent(&mut self, user_agent: &str) -> &mut Self {
+        self.user_agent = user_agent.to_string();
+        self.client = OnceLock::new();
+        self
+    }
+    /// Where responses are cached, relative paths resolve against the working directory. The
+    /// directory is created on the first fetch that needs it.
+    pub fn with_cache_dir(&mut self, cache_dir: impl Into<PathBuf>) -> &mut Self {
+        self.cache_dir = cache_dir.into();
+        self
+    }
+    /// Retries requests that fail on the network, time out or get a 5xx or 429 back. Cached
+    /// responses never reach the network so they aren't affected.
+    pub fn with_retry_policy(&mut self, retry_policy: RetryPolicy) -> &mut Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+    /// Makes every request, retries included, wait for a token from `rate_limiter`. Clones of
+    /// the `Arc` can be handed to other providers to share the budget.
+    pub fn with_rate_limiter(&mut self, rate_limiter: Arc<RateLimiter>) -> &mut Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+    fn get(&self, url: String) -> Result<Vec<BinanceKline>, Box<dyn Error + Send + Sync>> {
+        let client = self.client()?;
+        let body = self.retry_policy.run(
+            || {
+                if let Some(rate_limiter) = &self.rate_limiter {
+                    rate_limiter.acquire();
+                }
+                Ok(client.get(&url).send()?.error_for_status()?.text()?)
+            },
+            BinanceProvider::is_transient,
+        )?;
+        Ok(parse_klines(&body)?)
+    }
+    fn client(&self) -> Result<&reqwest::blocking::Client, reqwest::Error> {
+        if let Some(client) = self.client.get() {
+            return Ok(client);
+        }
+        let client = reqwest::blocking::Client::builder()
+            .connect_timeout(self.connect_timeout)
+            .timeout(self.timeout)
+            .user_agent(self.user_agent.as_str())
+            .build()?;
+        Ok(self.client.get_or_init(|| client))
+    }
+    fn is_transient(error: &(dyn Error + 'static)) -> bool {
+        let Some(error) = error.downcast_ref::<reqwest::Error>() else {
+            return false;
+        };
+        match error.status() {
+            Some(status) => {
+                status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            }
+            None => error.is_timeout() || error.is_connect() || error.is_request(),
+        }
+    }
+    fn save_price_data(
+        filename: &PathBuf,
+        price_data: &Vec<BinanceKline>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if let Some(dir) = filename.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut f = File::create(filename)?;
+        let serialized = serde_json::to_string(price_data)?;
+        f.write_all(serialized.as_bytes())?;
+        Ok(())
+    }
+    /// Up to `limit` klines opening between `start` and `end` (millis), uncached.
+    pub fn fetch_page(
+        &self,
+        symbol: &str,
+        interval: &str,
+        start: i64,
+        end: i64,
+        limit: i32,
+    ) -> Result<Vec<BinanceKline>, Box<dyn Error + Send + Sync>> {
+        let arguments = format!(
+            "symbol={:}&interval={:}&startTime={:}&endTime={:}&limit={:}",
+            symbol, interval, start, end, limit
+        );
+        let url = format!("{:}/{:}?{:}", self.base_url, KLINES, arguments);
+        self.get(url)
+    }
+}
+
+impl PriceProvider for BinanceProvider {
+    fn fetch(
+        &self,
+        symbol: &str,
+        interval: &str,
+        limit: i32,
+    ) -> Result<Vec<BinanceKline>, Box<dyn Error + Send + Sync>> {
+        let fetch_filename = self
+            .cache_dir
+            .join(format!("{:}{:}{:}", symbol, interval, limit));
+        let price_data: Vec<BinanceKline>;
+        if let Ok(file) = File::open(&fetch_filename) {
+            let mut buf_reader = BufReader::new(file);
+            let mut contents = String::new();
+            buf_reader.read_to_string(&mut contents)?;
+            price_data = parse_klines(&contents)?;
+        } else {
+            let arguments = format!("symbol={:}&interval={:}&limit={:}", symbol, interval, limit);
+            let url = format!("{:}/{:}?{:}", self.base_url, KLINES, arguments);
+            price_data = self.get(url)?;
+            BinanceProvider::save_price_data(&fetch_filename, &price_data)?;
+        }
+
+        Ok(price_data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Instant;
+
+    #[test]
+    fn test_fetch_times_out_on_a_hung_server() {
+        // Accepts the connection but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (_stream, _) = listener.accept().unwrap();
+            thread::sleep(Duration::from_secs(5));
+        });
+
+        let mut provider = BinanceProvider::new();
+        provider
+            .with_base_url(&format!("http://{}", addr))
+            .with_timeout(Duration::from_millis(200))
+            .with_user_agent("trade-sim-test");
+        let start = Instant::now();
+        let error = provider.fetch("TIMEOUTTESTUSDT", "1h", 10).unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(error.downcast_ref::<reqwest::Error>().unwrap().is_timeout());
+        assert!(error.to_string().contains("TIMEOUTTESTUSDT"), "{}", error);
+    }
+
+    const ONE_KLINE: &str = r#"[[1499040000000,"0.0163","0.8000","0.0157","0.0158","148976.11",1499644799999,"2434.19",308,"1756.87","28.46","0"]]"#;
+
+    /// Serves one connection per response in turn: `Some(body)` answers 200 with it, `None`
+    /// drops the connection without answering.
+    fn serve(responses: Vec<Option<&'static str>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                if let Some(body) = response {
+                    write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                    .unwrap();
+                }
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[test]
+    fn test_fetch_caches_into_configured_dir() {
+        let base_url = serve(vec![Some(ONE_KLINE)]);
+        let cache_dir =
+            std::env::temp_dir().join(format!("trade-sim-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&cache_dir);
+        let mut provider = BinanceProvider::new();
+        provider
+            .with_base_url(&base_url)
+            .with_cache_dir(cache_dir.join("nested"));
+        let klines = provider.fetch("CACHETESTUSDT", "1h", 1).unwrap();
+        assert_eq!(klines.len(), 1);
+
+        let cached = cache_dir.join("nested").join("CACHETESTUSDT1h1");
+        let mut contents = String::new();
+        File::open(&cached)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(parse_klines(&contents).unwrap().len(), 1);
+        // The server is gone, so a second fetch can only be served from the cache
+        assert_eq!(provider.fetch("CACHETESTUSDT", "1h", 1).unwrap().len(), 1);
+        std::fs::remove_dir_all(cache_dir).unwrap();
+    }
+
+    #[test]
+    fn test_retry_policy_gives_up_after_max_attempts() {
+        let policy = RetryPolicy::new(3, Duration::from_millis(1));
+        let mut calls = 0;
+        let result: Result<(), _> = policy.run(
+            || {
+                calls += 1;
+                Err("down".into())
+            },
+            |_| true,
+        );
+        assert_eq!(result.unwrap_err().to_string(), "down");
+        assert_eq!(calls, 3);
+
+        // Errors that aren't worth retrying are returned on the first attempt
+        let mut calls = 0;
+        let result: Result<(), _> = policy.run(
+            || {
+                calls += 1;
+                Err("bad request".into())
+            },
+            |_| false,
+        );
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_fetch_page_retries_dropped_connections() {
+        let base_url = serve(vec![None, None, Some(ONE_KLINE)]);
+        let mut provider = BinanceProvider::new();
+        provider
+            .with_base_url(&base_url)
+            .with_retry_policy(RetryPolicy::new(3, Duration::from_millis(10)));
+        let klines = provider
+            .fetch_page("RETRYTESTUSDT", "1h", 0, i64::MAX, 1)
+            .unwrap();
+        assert_eq!(klines.len(), 1);
+        assert_eq!(klines[0].get_open_timestamp(), 1499040000000);
+    }
+
+    #[test]
+    fn test_client_is_reused_until_settings_change() {
+        let mut provider = BinanceProvider::new();
+        let first = provider.client().unwrap() as *const _;
+        assert_eq!(provider.client().unwrap() as *const _, first);
+
+        provider.with_timeout(Duration::from_secs(1));
+        assert!(provider.client.get().is_none());
+        provider.client().unwrap();
+        assert!(provider.client.get().is_some());
+    }
+}
@@ -0,0 +1,145 @@
+use std::fmt::Debug;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/**
+RateLimiter is a token bucket that keeps requests to an API under a requests-per-minute limit.
+The bucket holds up to `burst` tokens and refills continuously at the configured rate, each
+request takes one token and waits for it when the bucket is empty. Providers share one limiter
+behind an `Arc` so every feed they populate draws from the same budget.
+*/
+#[derive(Debug)]
+pub struct RateLimiter {
+    requests_per_minute: u32,
+    burst: u32,
+    clock: Box<dyn Clock>,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Duration,
+}
+
+/// Where the limiter reads the time from and how it waits, so tests can use a fake clock.
+pub trait Clock: Debug + Send + Sync {
+    /// Time elapsed since a fixed starting point.
+    fn now(&self) -> Duration;
+    fn sleep(&self, duration: Duration);
+}
+
+#[derive(Debug)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        SystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+impl RateLimiter {
+    /// Spaces requests evenly, one every `60 / requests_per_minute` seconds.
+    pub fn per_minute(requests_per_minute: u32) -> Self {
+        RateLimiter::new(requests_per_minute, 1, Box::<SystemClock>::default())
+    }
+    /// Lets up to `burst` requests through back to back before spacing the rest.
+    pub fn new(requests_per_minute: u32, burst: u32, clock: Box<dyn Clock>) -> Self {
+        let burst = burst.max(1);
+        let last_refill = clock.now();
+        RateLimiter {
+            requests_per_minute: requests_per_minute.max(1),
+            burst,
+            clock,
+            bucket: Mutex::new(Bucket {
+                tokens: burst as f64,
+                last_refill,
+            }),
+        }
+    }
+    pub fn requests_per_minute(&self) -> u32 {
+        self.requests_per_minute
+    }
+    /// Takes a token, blocking until one is available.
+    pub fn acquire(&self) {
+        let per_second = self.requests_per_minute as f64 / 60.0;
+        let mut bucket = self
+            .bucket
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let now = self.clock.now();
+        let refill = now.saturating_sub(bucket.last_refill).as_secs_f64() * per_second;
+        bucket.tokens = (bucket.tokens + refill).min(self.burst as f64);
+        bucket.last_refill = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return;
+        }
+        // Wait for the missing part of a token, which the request then uses up
+        let wait = Duration::from_secs_f64((1.0 - bucket.tokens) / per_second);
+        self.clock.sleep(wait);
+        bucket.tokens = 0.0;
+        bucket.last_refill = now + wait;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+
+    #[derive(Debug, Default)]
+    struct MockClock {
+        now: Mutex<Duration>,
+    }
+
+    impl Clock for Arc<MockClock> {
+        fn now(&self) -> Duration {
+            *self.now.lock().unwrap()
+        }
+        fn sleep(&self, duration: Duration) {
+            *self.now.lock().unwrap() += duration;
+        }
+    }
+
+    impl MockClock {
+        fn advance(&self, duration: Duration) {
+            *self.now.lock().unwrap() += duration;
+        }
+    }
+
+    #[test]
+    fn test_requests_are_throttled_to_the_rate() {
+        let clock = Arc::new(MockClock::default());
+        let limiter = RateLimiter::new(120, 2, Box::new(clock.clone()));
+
+        // The burst goes straight through, then one request every half second
+        let mut sent_at = vec![];
+        for _ in 0..5 {
+            limiter.acquire();
+            sent_at.push(clock.now().as_millis());
+        }
+        assert_eq!(sent_at, vec![0, 0, 500, 1000, 1500]);
+
+        // An idle bucket refills, but never beyond the burst
+        clock.advance(Duration::from_secs(10));
+        for _ in 0..3 {
+            limiter.acquire();
+        }
+        assert_eq!(clock.now().as_millis(), 12_000);
+    }
+}
@@ -0,0 +1,102 @@
+use crate::exchange::order::OrderDirection;
+use crate::exchange::rng::SimRng;
+use rust_decimal::prelude::Decimal;
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
+
+/**
+SlippageModel decides how far a market order's execution price moves away from the reference
+price, always against the order: buys pay more and sells receive less. Amounts are in basis
+points of the reference price. The default model has no slippage.
+*/
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum SlippageModel {
+    #[default]
+    None,
+    // The same number of basis points on every market order.
+    FixedBps(Decimal),
+    // Basis points scaled by the share of the bar's volume the order takes, so this many
+    // basis points for an order as large as the whole bar.
+    VolumeProportional(Decimal),
+    // Anywhere from zero up to this many basis points, drawn from the exchange's seeded RNG.
+    RandomBps(Decimal),
+}
+
+impl SlippageModel {
+    pub fn slippage_bps(&self, qty: Decimal, volume: Decimal, rng: &mut SimRng) -> Decimal {
+        match self {
+            SlippageModel::None => dec!(0),
+            SlippageModel::FixedBps(bps) => *bps,
+            SlippageModel::VolumeProportional(bps) if volume > dec!(0) => bps * qty / volume,
+            SlippageModel::VolumeProportional(_) => dec!(0),
+            SlippageModel::RandomBps(max_bps) => {
+                max_bps * Decimal::from(rng.next_up_to(10_000)) / dec!(10_000)
+            }
+        }
+    }
+    /// The execution price for an order in `direction` against `reference_price`.
+    pub fn apply(
+        &self,
+        reference_price: Decimal,
+        direction: &OrderDirection,
+        qty: Decimal,
+        volume: Decimal,
+        rng: &mut SimRng,
+    ) -> Decimal {
+        let slippage = reference_price * self.slippage_bps(qty, volume, rng) / dec!(10_000);
+        match direction {
+            OrderDirection::Buy => reference_price + slippage,
+            OrderDirection::Sell => reference_price - slippage,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fixed_and_volume_proportional_slippage() {
+        let mut rng = SimRng::new(0);
+        let fixed = SlippageModel::FixedBps(dec!(10));
+        assert_eq!(
+            fixed.apply(dec!(100), &OrderDirection::Buy, dec!(1), dec!(10), &mut rng),
+            dec!(100.1)
+        );
+        assert_eq!(
+            fixed.apply(
+                dec!(100),
+                &OrderDirection::Sell,
+                dec!(1),
+                dec!(10),
+                &mut rng
+            ),
+            dec!(99.9)
+        );
+
+        let proportional = SlippageModel::VolumeProportional(dec!(100));
+        assert_eq!(
+            proportional.apply(dec!(100), &OrderDirection::Buy, dec!(1), dec!(4), &mut rng),
+            dec!(100.25)
+        );
+        assert_eq!(
+            SlippageModel::None.apply(dec!(100), &OrderDirection::Buy, dec!(1), dec!(4), &mut rng),
+            dec!(100)
+        );
+    }
+
+    #[test]
+    fn test_random_slippage_is_bounded_and_seeded() {
+        let model = SlippageModel::RandomBps(dec!(50));
+        let draws = |seed| {
+            let mut rng = SimRng::new(seed);
+            (0..20)
+                .map(|_| model.slippage_bps(dec!(1), dec!(1), &mut rng))
+                .collect::<Vec<Decimal>>()
+        };
+        assert_eq!(draws(9), draws(9));
+        assert!(draws(9)
+            .iter()
+            .all(|bps| *bps >= dec!(0) && *bps <= dec!(50)));
+    }
+}
@@ -0,0 +1,102 @@
+use crate::exchange::order::OrderDirection;
+use rust_decimal::prelude::Decimal;
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
+
+/**
+SymbolFilters are the trading rules of one symbol, like those Binance publishes per pair. A
+zero value leaves that rule out, which is what the default does for all of them.
+
+`tick_size` is the price increment, `step_size` the quantity increment and `min_notional` the
+smallest price times quantity an order may have. An order quantity off the step is rounded down
+to it rather than rejected, the way a client would size an order to fit, and it is the rounded
+quantity that has to meet the minimum notional.
+
+Prices are never rounded to the nearest tick. A limit price is rounded to the side that stays
+within it, down for a buy and up for a sell, and a taker fill is rounded against the taker, up for
+a buy and down for a sell, so slippage and spread costs aren't rounded away.
+*/
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct SymbolFilters {
+    pub min_notional: Decimal,
+    pub step_size: Decimal,
+    pub tick_size: Decimal,
+}
+
+impl SymbolFilters {
+    pub fn new(min_notional: Decimal, step_size: Decimal, tick_size: Decimal) -> Self {
+        SymbolFilters {
+            min_notional,
+            step_size,
+            tick_size,
+        }
+    }
+    /// Limit `price` rounded to a tick within it: down for a buy, up for a sell.
+    pub fn round_limit_price(&self, price: Decimal, direction: &OrderDirection) -> Decimal {
+        match direction {
+            OrderDirection::Buy => Self::round_to_multiple(price, self.tick_size, Decimal::floor),
+            OrderDirection::Sell => Self::round_to_multiple(price, self.tick_size, Decimal::ceil),
+        }
+    }
+    /// Taker fill `price` rounded to a tick against the taker: up for a buy, down for a sell.
+    pub fn round_fill_price(&self, price: Decimal, direction: &OrderDirection) -> Decimal {
+        match direction {
+            OrderDirection::Buy => Self::round_to_multiple(price, self.tick_size, Decimal::ceil),
+            OrderDirection::Sell => Self::round_to_multiple(price, self.tick_size, Decimal::floor),
+        }
+    }
+    /// `qty` rounded down to a whole number of steps.
+    pub fn round_qty(&self, qty: Decimal) -> Decimal {
+        Self::round_to_multiple(qty, self.step_size, Decimal::floor)
+    }
+    /// `qty` rounded up to a whole number of steps.
+    pub fn round_qty_up(&self, qty: Decimal) -> Decimal {
+        Self::round_to_multiple(qty, self.step_size, Decimal::ceil)
+    }
+    pub fn meets_min_notional(&self, price: Decimal, qty: Decimal) -> bool {
+        price * qty >= self.min_notional
+    }
+    fn round_to_multiple(value: Decimal, step: Decimal, round: fn(&Decimal) -> Decimal) -> Decimal {
+        if step > dec!(0) {
+            round(&(value / step)) * step
+        } else {
+            value
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rounding_to_filters() {
+        let filters = SymbolFilters::new(dec!(10), dec!(0.001), dec!(0.01));
+        assert_eq!(filters.round_qty(dec!(1.23456)), dec!(1.234));
+        assert_eq!(
+            filters.round_limit_price(dec!(100.456), &OrderDirection::Buy),
+            dec!(100.45)
+        );
+        assert_eq!(
+            filters.round_limit_price(dec!(100.451), &OrderDirection::Sell),
+            dec!(100.46)
+        );
+        assert_eq!(
+            filters.round_fill_price(dec!(100.451), &OrderDirection::Buy),
+            dec!(100.46)
+        );
+        assert_eq!(
+            filters.round_fill_price(dec!(100.456), &OrderDirection::Sell),
+            dec!(100.45)
+        );
+        assert!(filters.meets_min_notional(dec!(10), dec!(1)));
+        assert!(!filters.meets_min_notional(dec!(9.99), dec!(1)));
+
+        let unconstrained = SymbolFilters::default();
+        assert_eq!(unconstrained.round_qty(dec!(1.23456)), dec!(1.23456));
+        assert_eq!(
+            unconstrained.round_limit_price(dec!(100.456), &OrderDirection::Buy),
+            dec!(100.456)
+        );
+    }
+}
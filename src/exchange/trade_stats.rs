@@ -0,0 +1,131 @@
+use crate::exchange::cost_basis::{LotMatching, Lots};
+use crate::exchange::fill::Fill;
+use crate::exchange::order::OrderDirection;
+use rust_decimal::prelude::Decimal;
+use rust_decimal_macros::dec;
+use std::collections::HashMap;
+
+/**
+TradeStats aggregates closed trades, pairing entries and exits first in, first out.
+
+Every buy fill opens a lot of the quantity received net of its fee, costing the fill's
+notional. Every sell fill closes one trade: it consumes the oldest open lots of its pair until
+its quantity is covered, and the trade's PnL is the sell's proceeds net of fees minus the cost
+of the lot quantity consumed. A sell can close part of a lot, leaving the rest open for the
+next sell, or span several lots. Sold quantity with no open lot behind it, such as starting
+capital, has no known cost and is left out, and a sell with no lot at all is not a trade.
+PnL is in each pair's quote asset.
+*/
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TradeStats {
+    pub trades: usize,
+    pub wins: usize,
+    pub losses: usize,
+    /// Fraction of trades with a positive PnL, 0 without trades.
+    pub win_rate: Decimal,
+    pub average_pnl: Decimal,
+    pub average_win: Decimal,
+    /// Average PnL of losing trades, as a negative number.
+    pub average_loss: Decimal,
+    /// Gross profit over gross loss, `None` without losing trades.
+    pub profit_factor: Option<Decimal>,
+}
+
+impl TradeStats {
+    pub fn from_fills<'a>(fills: impl IntoIterator<Item = &'a Fill>) -> Self {
+        let mut lots: HashMap<&str, Lots> = HashMap::new();
+        let mut pnls: Vec<Decimal> = vec![];
+        for fill in fills {
+            let pair_lots = lots.entry(fill.pair.as_str()).or_default();
+            match fill.direction {
+                OrderDirection::Buy => {
+                    let qty = fill.qty - fill.fee;
+                    if qty > dec!(0) {
+                        pair_lots.open(qty, fill.notional() / qty);
+                    }
+                }
+                OrderDirection::Sell => {
+                    let (matched, cost) = pair_lots.take(fill.qty, LotMatching::Fifo);
+                    if matched > dec!(0) {
+                        let proceeds = (fill.notional() - fill.fee) * matched / fill.qty;
+                        pnls.push(proceeds - cost);
+                    }
+                }
+            }
+        }
+        Self::from_pnls(&pnls)
+    }
+
+    fn from_pnls(pnls: &[Decimal]) -> Self {
+        let wins: Vec<Decimal> = pnls.iter().copied().filter(|pnl| *pnl > dec!(0)).collect();
+        let losses: Vec<Decimal> = pnls.iter().copied().filter(|pnl| *pnl < dec!(0)).collect();
+        let average = |values: &[Decimal]| match values.len() {
+            0 => dec!(0),
+            n => values.iter().sum::<Decimal>() / Decimal::from(n),
+        };
+        let gross_loss = -losses.iter().sum::<Decimal>();
+        TradeStats {
+            trades: pnls.len(),
+            wins: wins.len(),
+            losses: losses.len(),
+            win_rate: match pnls.len() {
+                0 => dec!(0),
+                n => Decimal::from(wins.len()) / Decimal::from(n),
+            },
+            average_pnl: average(pnls),
+            average_win: average(&wins),
+            average_loss: average(&losses),
+            profit_factor: (gross_loss > dec!(0))
+                .then(|| wins.iter().sum::<Decimal>() / gross_loss),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::exchange::order::Order;
+
+    fn buy(price: Decimal, qty: Decimal) -> Fill {
+        Fill::new(0, &Order::new_limit_buy("ETHUSDT", price, qty), price, qty)
+    }
+
+    fn sell(price: Decimal, qty: Decimal) -> Fill {
+        Fill::new(0, &Order::new_limit_sell("ETHUSDT", price, qty), price, qty)
+    }
+
+    #[test]
+    fn test_fifo_pairing_buy_buy_sell() {
+        let fills = vec![
+            buy(dec!(10), dec!(1)),
+            buy(dec!(20), dec!(1)),
+            // Closes the first lot and half of the second: 45 - (10 + 10)
+            sell(dec!(30), dec!(1.5)),
+            // Closes the rest of the second lot: 7.5 - 10
+            sell(dec!(15), dec!(0.5)),
+        ];
+        let stats = TradeStats::from_fills(&fills);
+        assert_eq!(stats.trades, 2);
+        assert_eq!(stats.wins, 1);
+        assert_eq!(stats.losses, 1);
+        assert_eq!(stats.win_rate, dec!(0.5));
+        assert_eq!(stats.average_pnl, dec!(11.25));
+        assert_eq!(stats.average_win, dec!(25));
+        assert_eq!(stats.average_loss, dec!(-2.5));
+        assert_eq!(stats.profit_factor, Some(dec!(10)));
+    }
+
+    #[test]
+    fn test_sells_without_lots_are_not_trades() {
+        let fills = vec![
+            sell(dec!(30), dec!(1)),
+            buy(dec!(10), dec!(1)),
+            sell(dec!(12), dec!(2)),
+        ];
+        let stats = TradeStats::from_fills(&fills);
+        assert_eq!(stats.trades, 1);
+        // Only the quantity bought is paired: 12 - 10
+        assert_eq!(stats.average_win, dec!(2));
+        assert_eq!(stats.profit_factor, None);
+    }
+}
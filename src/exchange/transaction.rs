@@ -1,12 +1,32 @@
 use rust_decimal::prelude::Decimal;
-use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum TransactionKind {
+    // Trade legs, funding, deposits and withdrawals.
+    #[default]
+    Trade,
+    // A fee charged on a fill, booked separately from the fill's trade legs.
+    Fee,
+    // Margin borrowed into the wallet, or repaid out of it when negative.
+    Loan,
+    // Interest charged on margin loans and shorts.
+    Interest,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Transaction {
     ts: i64,
     symbol: String,
     price: Decimal,
     qty: Decimal,
+    #[serde(default)]
+    kind: TransactionKind,
+    // The order a fill leg was booked for, and the base asset of the pair it traded.
+    #[serde(default)]
+    order_id: Option<u64>,
+    #[serde(default)]
+    base: Option<String>,
 }
 
 impl Transaction {
@@ -16,8 +36,50 @@ impl Transaction {
             symbol,
             price,
             qty,
+            kind: TransactionKind::Trade,
+            order_id: None,
+            base: None,
+        }
+    }
+    /// Marks the transaction as a leg of a fill of `order_id` on a pair of `base`.
+    pub fn with_fill(mut self, order_id: u64, base: &str) -> Self {
+        self.order_id = Some(order_id);
+        self.base = Some(base.to_string());
+        self
+    }
+    /// A fee of `amount` taken out of `symbol`, booked as a negative quantity.
+    pub fn fee(ts: i64, symbol: String, price: Decimal, amount: Decimal) -> Self {
+        Transaction {
+            kind: TransactionKind::Fee,
+            ..Transaction::new(ts, symbol, price, -amount)
+        }
+    }
+    /// `amount` of `symbol` borrowed on margin, a negative amount repays it.
+    pub fn loan(ts: i64, symbol: String, amount: Decimal) -> Self {
+        Transaction {
+            kind: TransactionKind::Loan,
+            ..Transaction::new(ts, symbol, Decimal::ZERO, amount)
+        }
+    }
+    /// Interest of `amount` charged in `symbol`, booked as a negative quantity.
+    pub fn interest(ts: i64, symbol: String, amount: Decimal) -> Self {
+        Transaction {
+            kind: TransactionKind::Interest,
+            ..Transaction::new(ts, symbol, Decimal::ZERO, -amount)
         }
     }
+    pub fn get_kind(&self) -> TransactionKind {
+        self.kind
+    }
+    pub fn is_fee(&self) -> bool {
+        self.kind == TransactionKind::Fee
+    }
+    pub fn get_order_id(&self) -> Option<u64> {
+        self.order_id
+    }
+    pub fn get_base(&self) -> Option<&str> {
+        self.base.as_deref()
+    }
     pub fn get_ts(&self) -> &i64 {
         &self.ts
     }
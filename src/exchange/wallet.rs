@@ -1,8 +1,8 @@
-use crate::exchange::transaction::Transaction;
-use chrono::Utc;
+use crate::exchange::transaction::{Transaction, TransactionKind};
 use rust_decimal::prelude::Decimal;
-use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use thiserror::Error;
 
 /**
 This code defines a Wallet struct that tracks a user's wallet transactions and balances.
@@ -16,18 +16,41 @@ asset symbols and values being Decimal types representing the amount of that ass
 The Wallet struct has several methods:
 
 - new() creates a new Wallet instance with an empty list of
-    transactions and an empty hashmap for wallets.
+  transactions and an empty hashmap for wallets.
 - get_wallets() returns a reference to the wallets hashmap.
 - update_wallet() updates the balance of the wallets hashmap based on the given Transaction.
 - add() adds a Transaction to the transactions vector and updates the wallet balance based on the
-    given Transaction.
+  given Transaction.
+- try_add() adds a Transaction like add(), but rejects it if it would leave the balance of its
+  asset negative. try_add_all() does the same for several Transactions as a single unit, and
+  try_add_all_short() lets one asset go negative, a short position in it. check_all() runs the
+  same check without applying anything.
+- verify_integrity() recomputes every balance from the transactions and reports the assets
+  whose balance in wallets has drifted from the ledger.
+- get_borrowed() returns the amount owed per asset, which loan Transactions add to and
+  repayments take off. A negative balance is borrowed as well, so going short adds to it and
+  buying the asset back takes it off again.
 - has_funds_for_order() checks if there are sufficient funds for a given asset symbol and required
-    amount, and returns the available funds if they are sufficient, otherwise returns None.
+  amount, and returns the available funds if they are sufficient, otherwise returns None.
 */
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum WalletError {
+    #[error("Insufficient {0} balance")]
+    InsufficientBalance(String),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Wallet {
     transactions: Vec<Transaction>,
     wallets: HashMap<String, Decimal>,
+    #[serde(default)]
+    borrowed: HashMap<String, Decimal>,
+}
+
+impl Default for Wallet {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Wallet {
@@ -35,30 +58,106 @@ impl Wallet {
         Wallet {
             transactions: vec![],
             wallets: HashMap::new(),
+            borrowed: HashMap::new(),
         }
     }
     pub fn get_wallets(&self) -> &HashMap<String, Decimal> {
         &self.wallets
     }
+    pub fn get_borrowed(&self) -> &HashMap<String, Decimal> {
+        &self.borrowed
+    }
     pub fn get_transactions(&self) -> &Vec<Transaction> {
         &self.transactions
     }
     fn update_wallet(&mut self, tx: &Transaction) {
         let symbol = tx.get_symbol();
         let qty = tx.get_qty();
-        self.wallets
-            .entry(symbol.clone())
-            .and_modify(|e| *e += qty)
-            .or_insert(qty.clone());
+        let balance = self.wallets.entry(symbol.clone()).or_default();
+        let short_before = (-*balance).max(Decimal::ZERO);
+        *balance += qty;
+        let mut borrowed = (-*balance).max(Decimal::ZERO) - short_before;
+        if tx.get_kind() == TransactionKind::Loan {
+            borrowed += qty;
+        }
+        if !borrowed.is_zero() {
+            *self.borrowed.entry(symbol.clone()).or_default() += borrowed;
+        }
     }
     pub fn add(&mut self, tx: &Transaction) {
         self.transactions.push(tx.clone());
         self.update_wallet(tx);
     }
+    pub fn try_add(&mut self, tx: &Transaction) -> Result<(), WalletError> {
+        self.try_add_all(std::slice::from_ref(tx))
+    }
+    /// Applies all of `txs` or none of them, if any asset would end up with a negative balance.
+    pub fn try_add_all(&mut self, txs: &[Transaction]) -> Result<(), WalletError> {
+        self.try_add_all_except(txs, None)
+    }
+    /// Like try_add_all(), but the balance of `short` may go negative.
+    pub fn try_add_all_short(
+        &mut self,
+        txs: &[Transaction],
+        short: &str,
+    ) -> Result<(), WalletError> {
+        self.try_add_all_except(txs, Some(short))
+    }
+    fn try_add_all_except(
+        &mut self,
+        txs: &[Transaction],
+        short: Option<&str>,
+    ) -> Result<(), WalletError> {
+        self.check_all(txs, short)?;
+        for tx in txs {
+            self.add(tx);
+        }
+        Ok(())
+    }
+    /// Checks `txs` the way try_add_all_short() would, without applying them. Only `short`, if
+    /// any, may go negative.
+    pub fn check_all(&self, txs: &[Transaction], short: Option<&str>) -> Result<(), WalletError> {
+        let mut balances: HashMap<&String, Decimal> = HashMap::new();
+        for tx in txs {
+            let symbol = tx.get_symbol();
+            let current = self.wallets.get(symbol).copied().unwrap_or_default();
+            let balance = balances.entry(symbol).or_insert(current);
+            *balance += tx.get_qty();
+            if *balance < Decimal::ZERO && short != Some(symbol.as_str()) {
+                return Err(WalletError::InsufficientBalance(symbol.clone()));
+            }
+        }
+        Ok(())
+    }
+    /// Compares the balances against the sum of the transaction ledger. On mismatch, returns
+    /// each diverging asset mapped to its balance minus the ledger total.
+    pub fn verify_integrity(&self) -> Result<(), HashMap<String, Decimal>> {
+        let mut ledger: HashMap<&String, Decimal> = HashMap::new();
+        for tx in &self.transactions {
+            *ledger.entry(tx.get_symbol()).or_default() += tx.get_qty();
+        }
+        let mut discrepancies = HashMap::new();
+        for (symbol, balance) in &self.wallets {
+            let diff = balance - ledger.remove(symbol).unwrap_or_default();
+            if !diff.is_zero() {
+                discrepancies.insert(symbol.clone(), diff);
+            }
+        }
+        for (symbol, total) in ledger {
+            if !total.is_zero() {
+                discrepancies.insert(symbol.clone(), -total);
+            }
+        }
+        if discrepancies.is_empty() {
+            Ok(())
+        } else {
+            Err(discrepancies)
+        }
+    }
     pub fn has_funds_for_order(&self, asset: &str, required_amount: Decimal) -> Option<Decimal> {
         if let Some(funds) = self.wallets.get(asset) {
             if funds >= &required_amount {
-                return Some(funds.clone());
+                return Some(*funds);
             }
         }
         None
@@ -67,6 +166,8 @@ impl Wallet {
 #[cfg(test)]
 mod test {
     use super::*;
+    use chrono::Utc;
+    use rust_decimal_macros::dec;
 
     #[test]
     fn test_wallet_adds_to_account() {
@@ -211,4 +312,131 @@ mod test {
         );
         assert_eq!(w.has_funds_for_order("ETH", dec!(9)), Some(dec!(10)));
     }
+
+    #[test]
+    fn test_try_add_rejects_negative_balance() {
+        let mut w = Wallet::new();
+        w.add(&Transaction::new(
+            0i64,
+            String::from("BTC"),
+            dec!(0),
+            dec!(1),
+        ));
+
+        let result = w.try_add(&Transaction::new(
+            0i64,
+            String::from("BTC"),
+            dec!(23456.8),
+            dec!(-2),
+        ));
+        assert_eq!(
+            result,
+            Err(WalletError::InsufficientBalance(String::from("BTC")))
+        );
+        assert_eq!(w.get_wallets().get("BTC").unwrap(), &dec!(1));
+        assert_eq!(w.get_transactions().len(), 1);
+
+        let result = w.try_add(&Transaction::new(
+            0i64,
+            String::from("BTC"),
+            dec!(23456.8),
+            dec!(-1),
+        ));
+        assert!(result.is_ok());
+        assert_eq!(w.get_wallets().get("BTC").unwrap(), &dec!(0));
+    }
+
+    #[test]
+    fn test_try_add_all_is_all_or_nothing() {
+        let mut w = Wallet::new();
+        w.add(&Transaction::new(
+            0i64,
+            String::from("USDT"),
+            dec!(0),
+            dec!(10),
+        ));
+
+        let result = w.try_add_all(&[
+            Transaction::new(0i64, String::from("BTC"), dec!(20), dec!(1)),
+            Transaction::new(0i64, String::from("USDT"), dec!(20), dec!(-20)),
+        ]);
+        assert!(result.is_err());
+        assert_eq!(w.get_wallets().get("BTC"), None);
+        assert_eq!(w.get_wallets().get("USDT").unwrap(), &dec!(10));
+
+        let result = w.try_add_all(&[
+            Transaction::new(0i64, String::from("BTC"), dec!(5), dec!(1)),
+            Transaction::new(0i64, String::from("USDT"), dec!(5), dec!(-5)),
+            Transaction::new(0i64, String::from("USDT"), dec!(5), dec!(-5)),
+        ]);
+        assert!(result.is_ok());
+        assert_eq!(w.get_wallets().get("USDT").unwrap(), &dec!(0));
+    }
+
+    #[test]
+    fn test_verify_integrity_reports_drift() {
+        let mut w = Wallet::new();
+        w.add(&Transaction::new(
+            0i64,
+            String::from("BTC"),
+            dec!(0),
+            dec!(2),
+        ));
+        w.add(&Transaction::new(
+            0i64,
+            String::from("USDT"),
+            dec!(0),
+            dec!(100),
+        ));
+        w.add(&Transaction::new(
+            0i64,
+            String::from("BTC"),
+            dec!(50),
+            dec!(-1),
+        ));
+        assert_eq!(w.verify_integrity(), Ok(()));
+
+        w.wallets.insert(String::from("BTC"), dec!(1.5));
+        w.wallets.remove("USDT");
+        assert_eq!(
+            w.verify_integrity(),
+            Err(HashMap::from([
+                (String::from("BTC"), dec!(0.5)),
+                (String::from("USDT"), dec!(-100)),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_short_balance_is_borrowed() {
+        let mut w = Wallet::new();
+        w.add(&Transaction::new(
+            0i64,
+            String::from("BTC"),
+            dec!(0),
+            dec!(1),
+        ));
+        w.try_add_all_short(
+            &[Transaction::new(
+                0i64,
+                String::from("BTC"),
+                dec!(5),
+                dec!(-3),
+            )],
+            "BTC",
+        )
+        .unwrap();
+        assert_eq!(w.get_wallets().get("BTC").unwrap(), &dec!(-2));
+        assert_eq!(w.get_borrowed().get("BTC").unwrap(), &dec!(2));
+
+        // Buying back covers the short before anything is held again
+        w.add(&Transaction::new(
+            0i64,
+            String::from("BTC"),
+            dec!(5),
+            dec!(3),
+        ));
+        assert_eq!(w.get_wallets().get("BTC").unwrap(), &dec!(1));
+        assert_eq!(w.get_borrowed().get("BTC").unwrap(), &dec!(0));
+    }
 }
@@ -0,0 +1,189 @@
+/*!
+Technical indicators over a series of klines, computed from the closes. Each returns one value
+per kline, `None` while the indicator is still warming up, so the output lines up index for
+index with the input.
+*/
+use crate::exchange::price_feed::BinanceKline;
+use rust_decimal::Decimal;
+
+fn closes(klines: &[BinanceKline]) -> Vec<Decimal> {
+    klines.iter().map(|kline| kline.get_ohlc().4).collect()
+}
+
+/// The mean close of the last `period` klines, `None` for the first `period - 1`.
+pub fn sma(klines: &[BinanceKline], period: usize) -> Vec<Option<Decimal>> {
+    let closes = closes(klines);
+    let mut averages = vec![None; closes.len()];
+    if period == 0 {
+        return averages;
+    }
+    let mut sum = Decimal::ZERO;
+    for (i, close) in closes.iter().enumerate() {
+        sum += close;
+        if i >= period {
+            sum -= closes[i - period];
+        }
+        if i + 1 >= period {
+            averages[i] = Some(sum / Decimal::from(period));
+        }
+    }
+    averages
+}
+
+/// The exponential moving average of the closes with smoothing factor `2 / (period + 1)`. The
+/// average is seeded with the SMA of the first `period` closes, so like `sma` the first
+/// `period - 1` values are `None` and the two agree at index `period - 1`.
+pub fn ema(klines: &[BinanceKline], period: usize) -> Vec<Option<Decimal>> {
+    let closes = closes(klines);
+    let mut averages = sma(klines, period);
+    if period == 0 || closes.len() < period {
+        return averages;
+    }
+    let k = Decimal::TWO / Decimal::from(period + 1);
+    let mut ema = averages[period - 1].unwrap_or_default();
+    for i in period..closes.len() {
+        ema += (closes[i] - ema) * k;
+        averages[i] = Some(ema);
+    }
+    averages
+}
+
+/// The Relative Strength Index of the close to close changes, with gains and losses averaged
+/// using Wilder's smoothing: the first average is the plain mean of the first `period` changes,
+/// each later one `(previous * (period - 1) + change) / period`. The first `period` values are
+/// `None`, and a window without losses reads 100.
+pub fn rsi(klines: &[BinanceKline], period: usize) -> Vec<Option<Decimal>> {
+    let closes = closes(klines);
+    let mut values = vec![None; closes.len()];
+    if period == 0 || closes.len() <= period {
+        return values;
+    }
+    let n = Decimal::from(period);
+    let mut avg_gain = Decimal::ZERO;
+    let mut avg_loss = Decimal::ZERO;
+    for i in 1..closes.len() {
+        let change = closes[i] - closes[i - 1];
+        let gain = change.max(Decimal::ZERO);
+        let loss = (-change).max(Decimal::ZERO);
+        if i <= period {
+            avg_gain += gain / n;
+            avg_loss += loss / n;
+            if i < period {
+                continue;
+            }
+        } else {
+            avg_gain = (avg_gain * (n - Decimal::ONE) + gain) / n;
+            avg_loss = (avg_loss * (n - Decimal::ONE) + loss) / n;
+        }
+        values[i] = Some(if avg_loss.is_zero() {
+            Decimal::ONE_HUNDRED
+        } else {
+            Decimal::ONE_HUNDRED - Decimal::ONE_HUNDRED / (Decimal::ONE + avg_gain / avg_loss)
+        });
+    }
+    values
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::minute_klines as klines;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_sma() {
+        let series = klines(&[dec!(10), dec!(11), dec!(12), dec!(13), dec!(17)]);
+        assert_eq!(
+            sma(&series, 3),
+            vec![None, None, Some(dec!(11)), Some(dec!(12)), Some(dec!(14))]
+        );
+        assert_eq!(
+            sma(&series, 1),
+            series
+                .iter()
+                .map(|k| Some(k.get_ohlc().4))
+                .collect::<Vec<_>>()
+        );
+        // Not enough klines to fill a single window
+        assert_eq!(sma(&series, 6), vec![None; 5]);
+        assert_eq!(sma(&series, 0), vec![None; 5]);
+    }
+
+    #[test]
+    fn test_ema_is_seeded_from_the_sma() {
+        let series = klines(&[dec!(10), dec!(11), dec!(12), dec!(13), dec!(17)]);
+        // k = 0.5: 11 -> 11 + (13 - 11) * 0.5 = 12 -> 12 + (17 - 12) * 0.5 = 14.5
+        assert_eq!(
+            ema(&series, 3),
+            vec![None, None, Some(dec!(11)), Some(dec!(12)), Some(dec!(14.5))]
+        );
+        // k = 0.4: 11.5 -> 11.5 + (17 - 11.5) * 0.4 = 13.7
+        assert_eq!(
+            ema(&series, 4),
+            vec![None, None, None, Some(dec!(11.5)), Some(dec!(13.7))]
+        );
+        assert_eq!(ema(&series, 6), vec![None; 5]);
+        assert_eq!(ema(&series, 0), vec![None; 5]);
+    }
+
+    #[test]
+    fn test_rsi_textbook_series() {
+        // Wilder's 14 period example, without rounding the intermediate averages
+        let series = klines(&[
+            dec!(44.34),
+            dec!(44.09),
+            dec!(44.15),
+            dec!(43.61),
+            dec!(44.33),
+            dec!(44.83),
+            dec!(45.10),
+            dec!(45.42),
+            dec!(45.84),
+            dec!(46.08),
+            dec!(45.89),
+            dec!(46.03),
+            dec!(45.61),
+            dec!(46.28),
+            dec!(46.28),
+            dec!(46.00),
+            dec!(46.03),
+            dec!(46.41),
+            dec!(46.22),
+            dec!(45.64),
+        ]);
+        let values = rsi(&series, 14);
+        assert_eq!(values[..14], vec![None; 14]);
+        let rounded: Vec<Decimal> = values[14..]
+            .iter()
+            .map(|value| value.unwrap().round_dp(2))
+            .collect();
+        assert_eq!(
+            rounded,
+            vec![
+                dec!(70.46),
+                dec!(66.25),
+                dec!(66.48),
+                dec!(69.35),
+                dec!(66.29),
+                dec!(57.92)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rsi_of_a_rising_series_is_100() {
+        let series = klines(&[dec!(1), dec!(2), dec!(3), dec!(4), dec!(5), dec!(6)]);
+        assert_eq!(
+            rsi(&series, 3),
+            vec![
+                None,
+                None,
+                None,
+                Some(dec!(100)),
+                Some(dec!(100)),
+                Some(dec!(100))
+            ]
+        );
+        assert_eq!(rsi(&series, 6), vec![None; 6]);
+    }
+}
@@ -1,54 +1,93 @@
+pub mod backtest;
+pub mod error;
 pub mod exchange;
+pub mod indicators;
+pub mod metrics;
+#[cfg(test)]
+mod test_util;
 
 use thiserror::Error;
 
-use crate::exchange::exchange::{Exchange, ExchangeError};
-use crate::exchange::price_feed::PriceFeed;
-use chrono::Utc;
+pub use crate::error::TradeSimError;
+use crate::exchange::exchange::Exchange;
+use crate::exchange::order::Order;
 use rust_decimal::Decimal;
-use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use thiserror::__private::AsDynError;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Error)]
 pub enum ExchangesError {
     #[error("No exchange with the given exchange_id")]
     InvalidExchangeId,
+    #[error("Exchange instance is unusable, a thread panicked while holding it")]
+    PoisonedInstance,
 }
 
-struct Exchanges {
+#[derive(Serialize, Deserialize)]
+pub struct Exchanges {
     exchanges: HashMap<String, Exchange>,
 }
+impl Default for Exchanges {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl Exchanges {
     pub fn new() -> Self {
         Self {
             exchanges: HashMap::new(),
         }
     }
+    /// Writes every instance, keyed by its instance id, to `path` as JSON.
+    pub fn save(&self, path: &str) -> Result<(), TradeSimError> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+    pub fn load(path: &str) -> Result<Exchanges, TradeSimError> {
+        let reader = BufReader::new(File::open(path)?);
+        let exchanges: Exchanges = serde_json::from_reader(reader)?;
+        Ok(exchanges)
+    }
     pub fn create_new_exchange(&mut self) -> String {
         let instance_id = Uuid::new_v4().hyphenated().to_string();
         self.exchanges.insert(instance_id.clone(), Exchange::new());
         instance_id
     }
+    pub fn remove_exchange(&mut self, instance_id: &str) -> Result<(), TradeSimError> {
+        self.exchanges
+            .remove(instance_id)
+            .ok_or(ExchangesError::InvalidExchangeId)?;
+        Ok(())
+    }
+    /// The ids of all live instances, sorted.
+    pub fn list_instances(&self) -> Vec<String> {
+        let mut instance_ids: Vec<String> = self.exchanges.keys().cloned().collect();
+        instance_ids.sort();
+        instance_ids
+    }
     pub fn mut_unwrap_exchange_from_instance(
         &mut self,
         instance_id: &str,
-    ) -> Result<&mut Exchange, ExchangesError> {
+    ) -> Result<&mut Exchange, TradeSimError> {
         if let Some(exchange) = self.exchanges.get_mut(instance_id) {
             Ok(exchange)
         } else {
-            Err(ExchangesError::InvalidExchangeId)
+            Err(ExchangesError::InvalidExchangeId.into())
         }
     }
     pub fn unwrap_exchange_from_instance(
         &self,
         instance_id: &str,
-    ) -> Result<&Exchange, ExchangesError> {
+    ) -> Result<&Exchange, TradeSimError> {
         if let Some(exchange) = self.exchanges.get(instance_id) {
             Ok(exchange)
         } else {
-            Err(ExchangesError::InvalidExchangeId)
+            Err(ExchangesError::InvalidExchangeId.into())
         }
     }
     pub fn add_capital(
@@ -56,7 +95,7 @@ impl Exchanges {
         instance_id: &str,
         symbol: &str,
         amount: Decimal,
-    ) -> Result<(), ExchangesError> {
+    ) -> Result<(), TradeSimError> {
         let exchange = self.mut_unwrap_exchange_from_instance(instance_id)?;
         exchange.with_capital(vec![(symbol.to_string(), amount)]);
         Ok(())
@@ -67,26 +106,100 @@ impl Exchanges {
         symbol: &str,
         interval: &str,
         limit: i32,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ) -> Result<(), TradeSimError> {
         let exchange = self.mut_unwrap_exchange_from_instance(instance_id)?;
         exchange.with_price_feed(symbol.to_string(), interval.to_string(), limit)?;
         Ok(())
     }
-    pub fn tick(
-        &mut self,
-        instance_id: &str,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    pub fn tick(&mut self, instance_id: &str) -> Result<Vec<Order>, TradeSimError> {
         let exchange = self.mut_unwrap_exchange_from_instance(instance_id)?;
-        exchange.tick()?;
+        Ok(exchange.tick()?)
+    }
+    /// Moves every instance into a `SharedExchanges` that threads can use concurrently.
+    pub fn shared(self) -> SharedExchanges {
+        let exchanges = self
+            .exchanges
+            .into_iter()
+            .map(|(instance_id, exchange)| (instance_id, Arc::new(Mutex::new(exchange))))
+            .collect();
+        SharedExchanges {
+            exchanges: Arc::new(RwLock::new(exchanges)),
+        }
+    }
+}
+
+/**
+SharedExchanges holds exchange instances for concurrent use. Clones share the same instances,
+so one can be handed to each thread. Every instance has its own lock, a thread ticking one
+instance doesn't hold up another thread querying a different one. The registry of instances
+is only locked for as long as it takes to look one up, add it or remove it.
+*/
+#[derive(Clone, Default)]
+pub struct SharedExchanges {
+    exchanges: Arc<RwLock<HashMap<String, Arc<Mutex<Exchange>>>>>,
+}
+
+impl SharedExchanges {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn create_new_exchange(&self) -> String {
+        let instance_id = Uuid::new_v4().hyphenated().to_string();
+        self.exchanges
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(instance_id.clone(), Arc::new(Mutex::new(Exchange::new())));
+        instance_id
+    }
+    pub fn remove_exchange(&self, instance_id: &str) -> Result<(), TradeSimError> {
+        self.exchanges
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(instance_id)
+            .ok_or(ExchangesError::InvalidExchangeId)?;
         Ok(())
     }
+    /// The ids of all live instances, sorted.
+    pub fn list_instances(&self) -> Vec<String> {
+        let exchanges = self
+            .exchanges
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut instance_ids: Vec<String> = exchanges.keys().cloned().collect();
+        instance_ids.sort();
+        instance_ids
+    }
+    /// A handle on one instance, stays usable even if the instance is removed meanwhile.
+    pub fn instance(&self, instance_id: &str) -> Result<Arc<Mutex<Exchange>>, TradeSimError> {
+        let exchange = self
+            .exchanges
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(instance_id)
+            .cloned()
+            .ok_or(ExchangesError::InvalidExchangeId)?;
+        Ok(exchange)
+    }
+    /// Locks `exchange` for exclusive use until the guard is dropped.
+    pub fn lock(exchange: &Mutex<Exchange>) -> Result<MutexGuard<'_, Exchange>, TradeSimError> {
+        let exchange = exchange
+            .lock()
+            .map_err(|_| ExchangesError::PoisonedInstance)?;
+        Ok(exchange)
+    }
+    pub fn tick(&self, instance_id: &str) -> Result<Vec<Order>, TradeSimError> {
+        let exchange = self.instance(instance_id)?;
+        let mut exchange = Self::lock(&exchange)?;
+        Ok(exchange.tick()?)
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::exchange::order::OrderStatus;
-    use crate::exchange::price_feed::BinanceKline;
+    use crate::exchange::price_feed::{BinanceKline, PriceFeed};
+    use crate::test_util::ohlc_kline;
+    use rust_decimal_macros::dec;
 
     #[test]
     fn test_create_new_exchange() {
@@ -118,6 +231,24 @@ mod test {
             .is_err());
     }
 
+    #[test]
+    fn test_list_and_remove_instances() {
+        let mut exchanges = Exchanges::new();
+        let first = exchanges.create_new_exchange();
+        let second = exchanges.create_new_exchange();
+        let mut expected = vec![first.clone(), second.clone()];
+        expected.sort();
+        assert_eq!(exchanges.list_instances(), expected);
+
+        exchanges.remove_exchange(&first).unwrap();
+        assert_eq!(exchanges.list_instances(), vec![second]);
+        assert!(exchanges.unwrap_exchange_from_instance(&first).is_err());
+        assert!(matches!(
+            exchanges.remove_exchange(&first),
+            Err(TradeSimError::Exchanges(ExchangesError::InvalidExchangeId))
+        ));
+    }
+
     #[test]
     fn test_add_capital() {
         let mut exchanges = Exchanges::new();
@@ -139,11 +270,11 @@ mod test {
         let instance_id = exchanges.create_new_exchange();
         let custom_kline_data = vec![BinanceKline::new(
             1626578400000,
-            "1.0000000",
-            "2.0000000",
-            "0.08000000",
-            "0.15000000",
-            "5000.00000000",
+            dec!(1.0000000),
+            dec!(2.0000000),
+            dec!(0.08000000),
+            dec!(0.15000000),
+            dec!(5000.00000000),
             1626578500000,
             "750.00000000",
             10,
@@ -172,8 +303,8 @@ mod test {
             .place_limit_buy_order("BTCUSDT", dec!(1), dec!(1))
             .unwrap();
 
-        let result = exchanges.tick(&instance_id);
-        assert!(result.is_ok());
+        let filled = exchanges.tick(&instance_id).unwrap();
+        assert_eq!(filled.len(), 1);
 
         let exchange = exchanges
             .unwrap_exchange_from_instance(&instance_id)
@@ -182,4 +313,84 @@ mod test {
         assert_eq!(wallets["BTC"], dec!(2.0));
         assert_eq!(wallets["USDT"], dec!(0.0));
     }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut exchanges = Exchanges::new();
+        let first = exchanges.create_new_exchange();
+        let second = exchanges.create_new_exchange();
+        exchanges.add_capital(&first, "BTC", dec!(1.5)).unwrap();
+        exchanges.add_capital(&second, "USDT", dec!(250)).unwrap();
+
+        let path = std::env::temp_dir().join("exchanges_save_and_load.json");
+        exchanges.save(path.to_str().unwrap()).unwrap();
+        let loaded = Exchanges::load(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        for instance_id in [&first, &second] {
+            assert_eq!(
+                loaded
+                    .unwrap_exchange_from_instance(instance_id)
+                    .unwrap()
+                    .get_wallet(),
+                exchanges
+                    .unwrap_exchange_from_instance(instance_id)
+                    .unwrap()
+                    .get_wallet()
+            );
+        }
+        assert_eq!(loaded.exchanges.len(), 2);
+    }
+
+    fn kline() -> BinanceKline {
+        ohlc_kline(dec!(1), dec!(2), dec!(0.08), dec!(0.15))
+    }
+
+    #[test]
+    fn test_shared_exchanges_tick_concurrently() {
+        let mut exchanges = Exchanges::new();
+        let existing_id = exchanges.create_new_exchange();
+        let shared = exchanges.shared();
+        assert_eq!(shared.list_instances(), vec![existing_id]);
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let shared = shared.clone();
+                std::thread::spawn(move || {
+                    let instance_id = shared.create_new_exchange();
+                    {
+                        let exchange = shared.instance(&instance_id).unwrap();
+                        let mut exchange = SharedExchanges::lock(&exchange).unwrap();
+                        let mut price_feed = PriceFeed::new();
+                        price_feed.add_price_data(vec![kline(); 3]);
+                        exchange
+                            .with_capital(vec![("USDT".to_string(), dec!(1))])
+                            .add_price_feed("BTCUSDT".to_string(), price_feed);
+                        exchange
+                            .place_limit_buy_order("BTCUSDT", dec!(0.1), dec!(1))
+                            .unwrap();
+                    }
+                    for _ in 0..3 {
+                        shared.tick(&instance_id).unwrap();
+                    }
+                    instance_id
+                })
+            })
+            .collect();
+        let instance_ids: Vec<String> = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect();
+
+        assert_eq!(shared.list_instances().len(), 5);
+        for instance_id in instance_ids {
+            let exchange = shared.instance(&instance_id).unwrap();
+            let exchange = SharedExchanges::lock(&exchange).unwrap();
+            assert_eq!(exchange.bars_processed()["BTCUSDT"], 3);
+            assert_eq!(exchange.get_balance("BTC"), dec!(1));
+            // Each instance numbers its own orders from 1
+            assert_eq!(exchange.get_filled_orders()[0].id, 1);
+        }
+        assert!(shared.tick("missing").is_err());
+    }
 }
@@ -0,0 +1,106 @@
+/*!
+Performance metrics over an equity curve, the `(timestamp, portfolio value)` pairs an
+`Exchange` records after each tick once equity tracking is enabled. Returns are measured from
+one point of the curve to the next, so they are per bar rather than annualized.
+*/
+use rust_decimal::{Decimal, MathematicalOps};
+use rust_decimal_macros::dec;
+
+/// The change in value from the first to the last point, as a fraction of the first.
+pub fn total_return(equity_curve: &[(i64, Decimal)]) -> Option<Decimal> {
+    let (_, first) = equity_curve.first()?;
+    let (_, last) = equity_curve.last()?;
+    if first.is_zero() {
+        return None;
+    }
+    Some(last / first - dec!(1))
+}
+
+/// The largest fall from a peak to a later trough, as a fraction of the peak. 0 when the curve
+/// never falls.
+pub fn max_drawdown(equity_curve: &[(i64, Decimal)]) -> Decimal {
+    let mut peak: Option<Decimal> = None;
+    let mut drawdown = dec!(0);
+    for (_, value) in equity_curve {
+        let high = peak.map_or(*value, |peak| peak.max(*value));
+        if high > dec!(0) {
+            drawdown = drawdown.max((high - value) / high);
+        }
+        peak = Some(high);
+    }
+    drawdown
+}
+
+/// The return of each point over the previous one, skipping points that follow a zero value.
+pub fn bar_returns(equity_curve: &[(i64, Decimal)]) -> Vec<Decimal> {
+    equity_curve
+        .windows(2)
+        .filter(|window| !window[0].1.is_zero())
+        .map(|window| window[1].1 / window[0].1 - dec!(1))
+        .collect()
+}
+
+/// The mean per-bar return in excess of `risk_free_rate` divided by the sample standard
+/// deviation of the returns. `None` with fewer than two returns or when they don't vary.
+pub fn sharpe_ratio(equity_curve: &[(i64, Decimal)], risk_free_rate: Decimal) -> Option<Decimal> {
+    let returns = bar_returns(equity_curve);
+    if returns.len() < 2 {
+        return None;
+    }
+    let n = Decimal::from(returns.len());
+    let mean = returns.iter().sum::<Decimal>() / n;
+    let variance = returns
+        .iter()
+        .map(|r| (r - mean) * (r - mean))
+        .sum::<Decimal>()
+        / (n - dec!(1));
+    let std_dev = variance.sqrt()?;
+    if std_dev.is_zero() {
+        return None;
+    }
+    Some((mean - risk_free_rate) / std_dev)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn curve(values: &[Decimal]) -> Vec<(i64, Decimal)> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| (i as i64 * 60_000, *value))
+            .collect()
+    }
+
+    #[test]
+    fn test_drawdown_and_total_return() {
+        // Falls 30 from 120 (25%) and 45 from 150 (30%)
+        let equity_curve = curve(&[
+            dec!(100),
+            dec!(120),
+            dec!(90),
+            dec!(150),
+            dec!(105),
+            dec!(160),
+        ]);
+        assert_eq!(max_drawdown(&equity_curve), dec!(0.3));
+        assert_eq!(total_return(&equity_curve), Some(dec!(0.6)));
+
+        assert_eq!(max_drawdown(&curve(&[dec!(1), dec!(2), dec!(3)])), dec!(0));
+        assert_eq!(max_drawdown(&[]), dec!(0));
+        assert_eq!(total_return(&[]), None);
+    }
+
+    #[test]
+    fn test_sharpe_ratio() {
+        // Returns of 10%, -10% and 10%
+        let equity_curve = curve(&[dec!(100), dec!(110), dec!(99), dec!(108.9)]);
+        let sharpe = sharpe_ratio(&equity_curve, dec!(0)).unwrap();
+        assert!((sharpe - dec!(0.288675)).abs() < dec!(0.000001));
+
+        let steady = curve(&[dec!(100), dec!(110), dec!(121)]);
+        assert_eq!(sharpe_ratio(&steady, dec!(0)), None);
+        assert_eq!(sharpe_ratio(&steady[..2], dec!(0)), None);
+    }
+}
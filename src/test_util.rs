@@ -0,0 +1,54 @@
+use crate::exchange::price_feed::BinanceKline;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+/// A kline from `open_ts` to `close_ts` with the given prices and volume. The quote volumes
+/// and trade count are placeholders, nothing reads them.
+pub(crate) fn kline(
+    open_ts: i64,
+    close_ts: i64,
+    [open, high, low, close]: [Decimal; 4],
+    volume: Decimal,
+) -> BinanceKline {
+    BinanceKline::new(
+        open_ts,
+        open,
+        high,
+        low,
+        close,
+        volume,
+        close_ts,
+        "750.00000000",
+        10,
+        "2500.00000000",
+        "2500.00000000",
+        "0.0",
+    )
+}
+
+/// A kline with the given prices, at a fixed time and volume.
+pub(crate) fn ohlc_kline(
+    open: Decimal,
+    high: Decimal,
+    low: Decimal,
+    close: Decimal,
+) -> BinanceKline {
+    kline(
+        1626578400000,
+        1626578500000,
+        [open, high, low, close],
+        dec!(5000),
+    )
+}
+
+/// One minute kline per close, from timestamp 0, each flat at its close.
+pub(crate) fn minute_klines(closes: &[Decimal]) -> Vec<BinanceKline> {
+    closes
+        .iter()
+        .enumerate()
+        .map(|(i, &close)| {
+            let open_ts = i as i64 * 60_000;
+            kline(open_ts, open_ts + 59_999, [close; 4], dec!(1))
+        })
+        .collect()
+}
@@ -1,6 +1,6 @@
-use rust_decimal::prelude::Decimal;
 use rust_decimal_macros::dec;
 use trade_sim::exchange::exchange::Exchange;
+use trade_sim::exchange::order::OrderStatus;
 use trade_sim::exchange::price_feed::{BinanceKline, PriceFeed};
 
 #[test]
@@ -8,11 +8,11 @@ fn test_create_and_place_exchange_order() {
     let custom_kline_data = vec![
         BinanceKline::new(
             1626578400000,
-            "1.0000000",
-            "2.0000000",
-            "0.08000000",
-            "0.15000000",
-            "5000.00000000",
+            dec!(1.0000000),
+            dec!(2.0000000),
+            dec!(0.08000000),
+            dec!(0.15000000),
+            dec!(5000.00000000),
             1626578500000,
             "750.00000000",
             10,
@@ -22,11 +22,11 @@ fn test_create_and_place_exchange_order() {
         ),
         BinanceKline::new(
             1626578400000,
-            "0.1500000",
-            "0.2000000",
-            "0.04000000",
-            "0.3000000",
-            "5000.00000000",
+            dec!(0.1500000),
+            dec!(0.2000000),
+            dec!(0.04000000),
+            dec!(0.3000000),
+            dec!(5000.00000000),
             1626578500000,
             "750.00000000",
             10,
@@ -47,26 +47,30 @@ fn test_create_and_place_exchange_order() {
     exchange.add_price_feed("BTCUSDT".to_string(), price_feed);
 
     // Place a limit sell order
-    let _ = exchange
+    let sell_order = exchange
         .place_limit_sell_order("BTCUSDT", dec!(1), dec!(1))
         .unwrap();
 
     // Place a limit buy order
-    let _ = exchange
+    let buy_order = exchange
         .place_limit_buy_order("BTCUSDT", dec!(0.05), dec!(1))
         .unwrap();
 
     // Call the tick() function
-    let result = exchange.tick();
-    assert!(result.is_ok());
+    let filled = exchange.tick().unwrap();
+    assert_eq!(filled.len(), 1);
+    assert_eq!(filled[0].id, sell_order.id);
+    assert_eq!(filled[0].status, OrderStatus::Filled);
 
     let wallets = exchange.get_wallet();
     assert_eq!(wallets["USDT"], dec!(2.0));
     assert_eq!(wallets["BTC"], dec!(0.0));
 
     // Call the tick() function
-    let result = exchange.tick();
-    assert!(result.is_ok());
+    let filled = exchange.tick().unwrap();
+    assert_eq!(filled.len(), 1);
+    assert_eq!(filled[0].id, buy_order.id);
+    assert_eq!(filled[0].status, OrderStatus::Filled);
 
     let wallets = exchange.get_wallet();
     assert_eq!(wallets["USDT"], dec!(1.95));